@@ -1,46 +1,79 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+#[cfg(feature = "render")]
+use std::time::Duration;
 
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand, ValueEnum};
+use anyhow::{bail, Context, Result};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use encoding_rs::{Encoding, UTF_8};
 use serde::Serialize;
 
-use crate::layout::{
-    LayoutConfig, DEFAULT_GAP, DEFAULT_HEADER_HEIGHT, DEFAULT_HEIGHT, DEFAULT_PARENT_PAD,
-    DEFAULT_WIDTH,
+use crate::parser::{
+    format_import_time, merge_runs, parse_graph_json, parse_import_json, parse_import_runs,
+    parse_import_time, parse_import_time_lenient, parse_import_time_two_column, self_time_samples,
+    split_by_pid, split_import_runs, std_dev, unwrap_continuation_lines, ImportRecord, InputFormat,
+    MergeRuns, NoRecords,
+};
+#[cfg(feature = "render")]
+use crate::report::{
+    graph_command, run_command, GraphArgs, OutputCache, OutputFormat, ProfileVia, RunOptions,
+};
+use crate::tree::{
+    build_tree_from_parsed, canonical_listing, module_paths, nest_records,
+    top_level_imports, Tree, TreeOptions,
+};
+#[cfg(feature = "render")]
+use crate::util::{plain_file_input, wait_for_stable_file};
+use crate::util::{
+    ensure_output_dir, ensure_output_is_not_input, parse_encoding, read_input, read_input_as,
+    write_text_output,
 };
-use crate::parser::{parse_import_time, ImportRecord};
-use crate::render::{build_graph_html, build_graph_json};
-use crate::util::{read_input, write_html_or_open, write_text_output};
 
 #[derive(Parser)]
 #[command(name = "pyimporttime", version, about = "Python import time visualization")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Create missing parent directories of `--output`.
+    #[arg(long, global = true)]
+    mkdir: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    #[cfg(feature = "render")]
     Run {
+        /// Interpreter or launcher command; multi-word values like "uv run" are split on
+        /// whitespace. Launchers must pass PYTHONPROFILEIMPORTTIME through to Python.
         #[arg(long, default_value = "python")]
         python: String,
         #[arg(long, default_value_t = true)]
         open: bool,
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "data_uri")]
         output: Option<PathBuf>,
-        #[arg(long, default_value_t = DEFAULT_WIDTH)]
-        width: f64,
-        #[arg(long, default_value_t = DEFAULT_HEIGHT)]
-        height: f64,
-        #[arg(long, default_value_t = DEFAULT_GAP)]
-        gap: f64,
-        #[arg(long, default_value_t = DEFAULT_PARENT_PAD)]
-        parent_pad: f64,
-        #[arg(long, default_value_t = DEFAULT_HEADER_HEIGHT)]
-        header_height: f64,
+        /// Record the interpreter version in the report.
+        #[arg(long)]
+        version_banner: bool,
+        /// Enable profiling through the environment or the interpreter's `-X importtime` flag.
+        #[arg(long, value_enum, default_value_t = ProfileVia::Env)]
+        via: ProfileVia,
+        /// Profile the bare interpreter first and subtract its startup imports.
+        #[arg(long)]
+        baseline: bool,
+        /// Print the command and environment that would be run, without running it.
+        #[arg(long)]
+        dry_run: bool,
+        /// Roll self time up by source directory under the working directory. Asks the
+        /// interpreter for each module's file, which takes an extra run.
+        #[arg(long)]
+        by_file: bool,
+        /// Leave the interpreter, arguments and working directory out of the report, e.g. when
+        /// the arguments carry secrets.
+        #[arg(long)]
+        no_record_args: bool,
+        #[command(flatten)]
+        graph: GraphArgs,
         #[arg(last = true, required = true)]
         args: Vec<String>,
     },
@@ -49,33 +82,166 @@ enum Commands {
         input: String,
         #[arg(short, long)]
         output: Option<PathBuf>,
+        /// Emit each module with its full ancestor path instead of its depth.
+        #[arg(long)]
+        flat_paths: bool,
+        /// Emit a sorted, tab-separated listing that is stable across runs, for snapshot tests.
+        #[arg(long, conflicts_with = "flat_paths")]
+        canonical: bool,
+        /// Emit each module's logged cumulative time next to the total recomputed from its
+        /// self time and imports, and the difference between the two.
+        #[arg(long, conflicts_with_all = ["flat_paths", "canonical"])]
+        totals: bool,
+        /// Dump the raw tree arena (index, name, parent, children, cumulative and total time
+        /// of every node) for debugging tree building.
+        #[arg(long, hide = true, conflicts_with_all = ["flat_paths", "canonical", "totals"])]
+        dump_tree: bool,
+        /// Emit the records as a `-X importtime` log again, e.g. after `--merge-runs`, for
+        /// tools that read that format.
+        #[arg(long, conflicts_with_all = ["flat_paths", "canonical", "totals", "dump_tree"])]
+        importtime: bool,
+        /// Allow `--output` to overwrite INPUT.
+        #[arg(long)]
+        overwrite_input: bool,
+        #[command(flatten)]
+        records: RecordArgs,
     },
+    #[cfg(feature = "render")]
     Graph {
-        #[arg(value_name = "INPUT", default_value = "-")]
-        input: String,
-        #[arg(short, long)]
+        /// One or more logs; several are combined under a top-level node per file.
+        #[arg(value_name = "INPUT", default_value = "-", num_args = 1..)]
+        inputs: Vec<String>,
+        #[arg(short, long, conflicts_with = "data_uri")]
         output: Option<PathBuf>,
+        #[arg(long, conflicts_with = "data_uri")]
+        open: bool,
+        /// Wait until INPUT has stopped growing for MS milliseconds before reading it.
+        #[arg(
+            long,
+            value_name = "MS",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "500"
+        )]
+        wait_for_complete: Option<u64>,
+        #[command(flatten)]
+        records: RecordArgs,
+        #[command(flatten)]
+        graph: GraphArgs,
+        /// Output format; `auto` infers it from the `--output` extension, defaulting to html.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Auto)]
+        format: OutputFormat,
+        /// Re-render even if `--output` is up to date with the input and options.
         #[arg(long)]
+        force: bool,
+        /// Allow `--output` to overwrite an input.
+        #[arg(long)]
+        overwrite_input: bool,
+    },
+    /// Lay out a report written by `graph --format json` again with new options, e.g. another
+    /// canvas size, without rerunning Python or keeping the original log.
+    #[cfg(feature = "render")]
+    Relayout {
+        #[arg(value_name = "REPORT", default_value = "-")]
+        input: String,
+        #[arg(short, long, conflicts_with = "data_uri")]
+        output: Option<PathBuf>,
+        #[arg(long, conflicts_with = "data_uri")]
         open: bool,
-        #[arg(long, default_value_t = DEFAULT_WIDTH)]
-        width: f64,
-        #[arg(long, default_value_t = DEFAULT_HEIGHT)]
-        height: f64,
-        #[arg(long, default_value_t = DEFAULT_GAP)]
-        gap: f64,
-        #[arg(long, default_value_t = DEFAULT_PARENT_PAD)]
-        parent_pad: f64,
-        #[arg(long, default_value_t = DEFAULT_HEADER_HEIGHT)]
-        header_height: f64,
-        #[arg(long, value_enum, default_value_t = OutputFormat::Html)]
+        #[command(flatten)]
+        graph: GraphArgs,
+        /// Output format; `auto` infers it from the `--output` extension, defaulting to html.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Auto)]
         format: OutputFormat,
+        /// Re-render even if `--output` is up to date.
+        #[arg(long)]
+        force: bool,
+        /// Allow `--output` to overwrite REPORT.
+        #[arg(long)]
+        overwrite_input: bool,
+    },
+    /// Fail when any module's cumulative import time exceeds a budget, for CI. Exits 0 when
+    /// within budget, 1 when over it, 2 when INPUT can't be read or parsed, 3 when it has no
+    /// import time records.
+    Check {
+        #[arg(value_name = "INPUT", default_value = "-")]
+        input: String,
+        /// Largest cumulative import time allowed for any module, in milliseconds.
+        #[arg(long, value_name = "MS")]
+        budget_ms: f64,
+        #[command(flatten)]
+        records: RecordArgs,
+    },
+    /// Fail when any module's cumulative import time grew past a committed baseline, for CI.
+    /// Exit codes match `check`, with 1 meaning a regression.
+    Compare {
+        #[arg(value_name = "INPUT", default_value = "-")]
+        input: String,
+        /// Baseline written by `parse` or `graph --format json`, or a JSON array of records.
+        #[arg(long, value_name = "JSON")]
+        baseline: String,
+        /// Allowed increase of any module's cumulative time, in milliseconds. Without either
+        /// tolerance, any increase is a regression.
+        #[arg(long, value_name = "MS")]
+        tolerance_ms: Option<f64>,
+        /// Allowed increase of any module's cumulative time, as a percentage of its baseline.
+        /// Exceeding either tolerance is a regression.
+        #[arg(long, value_name = "PCT")]
+        tolerance_pct: Option<f64>,
+        /// Only count a regression when it exceeds both tolerances.
+        #[arg(long, requires_all = ["tolerance_ms", "tolerance_pct"])]
+        require_both: bool,
+        #[command(flatten)]
+        records: RecordArgs,
+    },
+    /// List the most expensive top-level imports as lazy-import candidates.
+    Suggest {
+        #[arg(value_name = "INPUT", default_value = "-")]
+        input: String,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        #[command(flatten)]
+        records: RecordArgs,
     },
 }
 
-#[derive(Clone, Copy, ValueEnum)]
-enum OutputFormat {
-    Html,
-    Json,
+/// How INPUT is read into records.
+#[derive(Args)]
+struct RecordArgs {
+    /// Combine multiple runs in the log into one.
+    #[arg(long, value_enum)]
+    merge_runs: Option<MergeRuns>,
+    /// Format of INPUT.
+    #[arg(long, value_enum, default_value_t = InputFormat::Text)]
+    input_format: InputFormat,
+    /// Skip malformed `import time:` lines with a warning instead of failing.
+    #[arg(long)]
+    lenient: bool,
+    /// Also accept `import time: <self> | <module>` lines that lack the cumulative column.
+    #[arg(long)]
+    two_column: bool,
+    /// Rejoin `import time:` lines that the terminal wrapped, taking a following line with
+    /// no spaces as the rest of the module name.
+    #[arg(long)]
+    unwrap: bool,
+    /// Character encoding of INPUT, e.g. `windows-1252` or `utf-16le`.
+    #[arg(long, value_parser = parse_encoding, default_value = "utf-8")]
+    encoding: &'static Encoding,
+}
+
+impl RecordArgs {
+    fn source(&self) -> RecordSource {
+        RecordSource {
+            format: self.input_format,
+            merge: self.merge_runs,
+            lenient: self.lenient,
+            two_column: self.two_column,
+            unwrap: self.unwrap,
+            encoding: Some(self.encoding),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -89,245 +255,815 @@ struct ImportRecordJson {
     self_us: u64,
     cumulative_us: u64,
     depth: usize,
+    /// With `--merge-runs`, the self time from each run the module appeared in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    self_us_samples: Option<Vec<u64>>,
+    /// With `--merge-runs`, the population standard deviation of `self_us_samples`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    self_us_stddev: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct FlatPathsJson {
+    modules: Vec<ModulePathJson>,
+}
+
+#[derive(Serialize)]
+struct TotalsJson {
+    modules: Vec<ModuleTotalJson>,
+}
+
+#[derive(Serialize)]
+struct ModuleTotalJson {
+    name: String,
+    path: Vec<String>,
+    /// As logged by the interpreter.
+    cumulative_us: u64,
+    /// Recomputed from self time and imports, see `ModulePath::total_us`.
+    total_us: u64,
+    /// `cumulative_us - total_us`.
+    difference_us: i64,
+}
+
+#[derive(Serialize)]
+struct ArenaJson {
+    root: usize,
+    nodes: Vec<ArenaNodeJson>,
+}
+
+#[derive(Serialize)]
+struct ArenaNodeJson {
+    index: usize,
+    name: String,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// As logged, or the self time for "self" nodes.
+    cumulative_us: u64,
+    /// As computed by `Tree::recompute_totals`; zero for nodes not reachable from the root.
+    total_us: u64,
+    order: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ModulePathJson {
+    name: String,
+    path: Vec<String>,
+    self_us: u64,
+    cumulative_us: u64,
+}
+
+#[derive(Serialize)]
+struct SuggestJson {
+    suggestions: Vec<SuggestionJson>,
+}
+
+#[derive(Serialize)]
+struct SuggestionJson {
+    name: String,
+    self_us: u64,
+    cumulative_us: u64,
 }
 
 pub fn run() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    // Subcommand matches tell which options came from the command line, for config merging.
+    #[cfg(feature = "render")]
+    let sub_matches = matches.subcommand().map(|(_, sub)| sub);
+    let mkdir = cli.mkdir;
     match cli.command {
+        #[cfg(feature = "render")]
         Commands::Run {
             python,
             open,
             output,
-            width,
-            height,
-            gap,
-            parent_pad,
-            header_height,
-            args,
-        } => run_command(
-            &python,
+            version_banner,
+            via,
+            baseline,
+            dry_run,
+            by_file,
+            no_record_args,
+            mut graph,
             args,
+        } => {
+            if let Some(sub) = sub_matches {
+                graph.apply_config_file(sub)?;
+            }
+            let run_options = RunOptions {
+                version_banner,
+                via,
+                baseline,
+                dry_run,
+                by_file,
+                record_args: !no_record_args,
+            };
+            ensure_output_dir(output.as_deref(), mkdir)?;
+            run_command(&python, args, output, open, run_options, graph.options())
+        }
+        Commands::Parse {
+            input,
             output,
-            open,
-            LayoutConfig {
-                width,
-                height,
-                gap,
-                parent_pad,
-                header_height,
-            },
-        ),
-        Commands::Parse { input, output } => parse_command(&input, output),
+            flat_paths,
+            canonical,
+            totals,
+            dump_tree,
+            importtime,
+            overwrite_input,
+            records,
+        } => {
+            if !overwrite_input {
+                ensure_output_is_not_input(std::slice::from_ref(&input), output.as_deref())?;
+            }
+            let mode = if canonical {
+                ParseOutput::Canonical
+            } else if flat_paths {
+                ParseOutput::FlatPaths
+            } else if totals {
+                ParseOutput::Totals
+            } else if dump_tree {
+                ParseOutput::Arena
+            } else if importtime {
+                ParseOutput::Importtime
+            } else {
+                ParseOutput::Records
+            };
+            ensure_output_dir(output.as_deref(), mkdir)?;
+            parse_command(&input, output, mode, records.source())
+        }
+        #[cfg(feature = "render")]
         Commands::Graph {
-            input,
+            inputs,
             output,
             open,
-            width,
-            height,
-            gap,
-            parent_pad,
-            header_height,
+            wait_for_complete,
+            records,
+            mut graph,
             format,
-        } => graph_command(
-            &input,
+            force,
+            overwrite_input,
+        } => {
+            if let Some(sub) = sub_matches {
+                graph.apply_config_file(sub)?;
+            }
+            if let Some(quiet_ms) = wait_for_complete {
+                for path in inputs.iter().filter_map(|input| plain_file_input(input)) {
+                    wait_for_stable_file(path, Duration::from_millis(quiet_ms))?;
+                }
+            }
+            if !overwrite_input {
+                ensure_output_is_not_input(&inputs, output.as_deref())?;
+            }
+            ensure_output_dir(output.as_deref(), mkdir)?;
+            let cache = if force { OutputCache::Refresh } else { OutputCache::Reuse };
+            graph_command(&inputs, output, open, format, records.source(), cache, graph.options())
+        }
+        #[cfg(feature = "render")]
+        Commands::Relayout {
+            input,
             output,
             open,
+            mut graph,
             format,
-            LayoutConfig {
-                width,
-                height,
-                gap,
-                parent_pad,
-                header_height,
-            },
-        ),
+            force,
+            overwrite_input,
+        } => {
+            if let Some(sub) = sub_matches {
+                GraphArgs::reject_tree_options(sub)?;
+                graph.apply_config_file(sub)?;
+            }
+            let inputs = [input];
+            if !overwrite_input {
+                ensure_output_is_not_input(&inputs, output.as_deref())?;
+            }
+            ensure_output_dir(output.as_deref(), mkdir)?;
+            let cache = if force { OutputCache::Refresh } else { OutputCache::Reuse };
+            let source = RecordSource {
+                format: InputFormat::Graph,
+                ..RecordSource::default()
+            };
+            graph_command(&inputs, output, open, format, source, cache, graph.options())
+        }
+        Commands::Check {
+            input,
+            budget_ms,
+            records,
+        } => {
+            // A plain error exit would be 1 and read as "over budget", so map it explicitly.
+            let status = check_command(&input, budget_ms, records.source());
+            std::process::exit(status.code())
+        }
+        Commands::Compare {
+            input,
+            baseline,
+            tolerance_ms,
+            tolerance_pct,
+            require_both,
+            records,
+        } => {
+            let tolerance = Tolerance {
+                ms: tolerance_ms,
+                pct: tolerance_pct,
+                require_both,
+            };
+            let status = compare_command(&input, &baseline, tolerance, records.source());
+            std::process::exit(status.code())
+        }
+        Commands::Suggest {
+            input,
+            output,
+            limit,
+            records,
+        } => {
+            ensure_output_dir(output.as_deref(), mkdir)?;
+            suggest_command(&input, output, limit, records.source())
+        }
     }
 }
 
-fn run_command(
-    python: &str,
-    args: Vec<String>,
-    output: Option<PathBuf>,
-    open: bool,
-    config: LayoutConfig,
-) -> Result<()> {
-    let executable = Executable::from_python_and_args(python, &args)?;
-    let output_data = run_with_import_timing(&executable)?;
-    if !output_data.status.success() {
-        eprintln!("warning: command exited with status {}", output_data.status);
-    }
-    let html = build_graph_html(&output_data.stderr, &config)?;
-    write_html_or_open(html, output, open)
+/// Outcome of `check` and `compare`. The exit codes are a contract for CI scripts; don't
+/// renumber them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckStatus {
+    Ok,
+    /// Over the budget, or regressed past the baseline.
+    OverBudget,
+    ParseError,
+    NoRecords,
 }
 
-struct Executable {
-    path: PathBuf,
-    args: Vec<String>,
+impl CheckStatus {
+    fn code(self) -> i32 {
+        match self {
+            CheckStatus::Ok => 0,
+            CheckStatus::OverBudget => 1,
+            CheckStatus::ParseError => 2,
+            CheckStatus::NoRecords => 3,
+        }
+    }
 }
 
-impl Executable {
-    fn from_python_and_args(python: &str, args: &[String]) -> Result<Self> {
-        if let Some(script_path) = find_python_script(args) {
-            let mut script_args = Vec::with_capacity(args.len().saturating_sub(1));
-            script_args.extend(args.iter().skip(1).cloned());
-            return Ok(Self {
-                path: script_path,
-                args: script_args,
-            });
+fn check_command(input: &str, budget_ms: f64, source: RecordSource) -> CheckStatus {
+    match source.read(input) {
+        Ok(text) => check_text(&text, budget_ms, source),
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            CheckStatus::ParseError
         }
-        Ok(Self {
-            path: PathBuf::from(python),
-            args: args.to_vec(),
+    }
+}
+
+/// Builds the tree `check` and `compare` judge, printing any error and mapping it to its status.
+fn gate_tree(text: &str, source: RecordSource) -> Result<Tree, CheckStatus> {
+    parse_records(text, source)
+        .and_then(|records| build_tree_from_parsed(records, &TreeOptions::default()))
+        .map_err(|err| {
+            eprintln!("error: {err:#}");
+            if err.is::<NoRecords>() {
+                CheckStatus::NoRecords
+            } else {
+                CheckStatus::ParseError
+            }
         })
+}
+
+fn check_text(text: &str, budget_ms: f64, source: RecordSource) -> CheckStatus {
+    let tree = match gate_tree(text, source) {
+        Ok(tree) => tree,
+        Err(status) => return status,
+    };
+    let modules = module_paths(&tree);
+    let over: Vec<_> = modules
+        .iter()
+        .filter(|module| module.cumulative_us as f64 / 1000.0 > budget_ms)
+        .collect();
+    if over.is_empty() {
+        println!("ok: all {} modules within {} ms budget", modules.len(), budget_ms);
+        return CheckStatus::Ok;
+    }
+    for module in &over {
+        println!(
+            "over budget: {} ({:.3} ms > {} ms)",
+            module.path.join(" > "),
+            module.cumulative_us as f64 / 1000.0,
+            budget_ms
+        );
     }
+    CheckStatus::OverBudget
 }
 
-struct RunOutput {
-    stderr: String,
-    status: ExitStatus,
+/// How much a module's cumulative time may grow before `compare` calls it a regression.
+/// Without either limit any increase counts.
+#[derive(Debug, Clone, Copy, Default)]
+struct Tolerance {
+    ms: Option<f64>,
+    pct: Option<f64>,
+    /// Only a module past both limits regressed, rather than one past either.
+    require_both: bool,
 }
 
-fn run_with_import_timing(executable: &Executable) -> Result<RunOutput> {
-    let mut cmd = Command::new(&executable.path);
-    cmd.args(&executable.args);
-    cmd.env("PYTHONPROFILEIMPORTTIME", "1");
-    let output_data = cmd.output().context("failed to run command")?;
-    Ok(RunOutput {
-        stderr: String::from_utf8_lossy(&output_data.stderr).to_string(),
-        status: output_data.status,
-    })
+impl Tolerance {
+    fn is_exceeded(self, baseline_us: u64, current_us: u64) -> bool {
+        let increase_us = current_us as f64 - baseline_us as f64;
+        let past_ms = self.ms.map(|ms| increase_us / 1000.0 > ms);
+        let past_pct = self.pct.map(|pct| increase_us > baseline_us as f64 * pct / 100.0);
+        match (past_ms, past_pct) {
+            (None, None) => increase_us > 0.0,
+            (Some(past), None) | (None, Some(past)) => past,
+            (Some(past_ms), Some(past_pct)) if self.require_both => past_ms && past_pct,
+            (Some(past_ms), Some(past_pct)) => past_ms || past_pct,
+        }
+    }
 }
 
-fn parse_command(input: &str, output: Option<PathBuf>) -> Result<()> {
-    let text = read_input(input)?;
-    let records = parse_import_time(&text)?;
-    let json = ParseJson {
-        records: records
-            .into_iter()
-            .map(record_to_json)
-            .collect(),
+impl fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let joiner = if self.require_both { " and " } else { " or " };
+        match (self.ms, self.pct) {
+            (None, None) => f.write_str("its baseline"),
+            (Some(ms), None) => write!(f, "{ms} ms"),
+            (None, Some(pct)) => write!(f, "{pct}%"),
+            (Some(ms), Some(pct)) => write!(f, "{ms} ms{joiner}{pct}%"),
+        }
+    }
+}
+
+fn compare_command(
+    input: &str,
+    baseline: &str,
+    tolerance: Tolerance,
+    source: RecordSource,
+) -> CheckStatus {
+    let texts = source.read(input).and_then(|text| Ok((text, read_input(baseline)?)));
+    match texts {
+        Ok((text, baseline)) => compare_text(&text, &baseline, tolerance, source),
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            CheckStatus::ParseError
+        }
+    }
+}
+
+fn compare_text(
+    text: &str,
+    baseline: &str,
+    tolerance: Tolerance,
+    source: RecordSource,
+) -> CheckStatus {
+    let format = if baseline.trim_start().starts_with('[') {
+        InputFormat::Json
+    } else {
+        InputFormat::Graph
     };
-    write_text_output(serde_json::to_string_pretty(&json)?, output)
+    let baseline_source = RecordSource {
+        format,
+        ..RecordSource::default()
+    };
+    let (tree, baseline) = match (gate_tree(text, source), gate_tree(baseline, baseline_source)) {
+        (Ok(tree), Ok(baseline)) => (tree, baseline),
+        (Err(status), _) | (_, Err(status)) => return status,
+    };
+    // Repeated modules are compared by their first occurrence under the same path.
+    let mut before: HashMap<Vec<String>, u64> = HashMap::new();
+    for module in module_paths(&baseline) {
+        before.entry(module.path).or_insert(module.cumulative_us);
+    }
+    let mut seen: HashSet<Vec<String>> = HashSet::new();
+    let modules = module_paths(&tree);
+    let regressed: Vec<_> = modules
+        .iter()
+        .filter(|module| seen.insert(module.path.clone()))
+        .filter_map(|module| {
+            let baseline_us = before.get(&module.path).copied().unwrap_or(0);
+            tolerance
+                .is_exceeded(baseline_us, module.cumulative_us)
+                .then_some((module, baseline_us))
+        })
+        .collect();
+    if regressed.is_empty() {
+        println!("ok: no module regressed past {tolerance}");
+        return CheckStatus::Ok;
+    }
+    for (module, baseline_us) in &regressed {
+        let delta_ms = (module.cumulative_us as f64 - *baseline_us as f64) / 1000.0;
+        let change = if *baseline_us == 0 {
+            "new".to_string()
+        } else {
+            format!("{:+.1}%", delta_ms * 100_000.0 / *baseline_us as f64)
+        };
+        println!(
+            "regressed: {} ({:.3} ms -> {:.3} ms, {:+.3} ms, {})",
+            module.path.join(" > "),
+            *baseline_us as f64 / 1000.0,
+            module.cumulative_us as f64 / 1000.0,
+            delta_ms,
+            change
+        );
+    }
+    println!("{} of {} modules regressed", regressed.len(), seen.len());
+    CheckStatus::OverBudget
+}
+
+/// What `parse` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseOutput {
+    Records,
+    FlatPaths,
+    Canonical,
+    Totals,
+    Arena,
+    Importtime,
 }
 
-fn graph_command(
+fn parse_command(
     input: &str,
     output: Option<PathBuf>,
-    open: bool,
-    format: OutputFormat,
-    config: LayoutConfig,
+    mode: ParseOutput,
+    source: RecordSource,
 ) -> Result<()> {
-    let text = read_input(input)?;
-    match format {
-        OutputFormat::Json => {
-            let graph = build_graph_json(&text, &config)?;
-            write_text_output(serde_json::to_string_pretty(&graph)?, output)
+    let text = source.read(input)?;
+    // Keep the runs around to report their spread; logs split per process are merged per
+    // process in `parse_records` and get no samples.
+    if mode == ParseOutput::Records
+        && let Some(strategy) = source.merge
+        && source.format == InputFormat::Text
+        && split_by_pid(&text).is_none()
+    {
+        let json = merged_records_json(&parse_runs(&text, source)?, strategy);
+        return write_text_output(serde_json::to_string_pretty(&json)?, output);
+    }
+    let records = parse_records(&text, source)?;
+    let tree = || build_tree_from_parsed(records.clone(), &TreeOptions::default());
+    let text = match mode {
+        ParseOutput::Records => {
+            let json = ParseJson {
+                records: records.iter().cloned().map(record_to_json).collect(),
+            };
+            serde_json::to_string_pretty(&json)?
         }
-        OutputFormat::Html => {
-            let html = build_graph_html(&text, &config)?;
-            write_html_or_open(html, output, open)
+        ParseOutput::Canonical => canonical_listing(&tree()?),
+        ParseOutput::Totals => serde_json::to_string_pretty(&totals_json(&tree()?))?,
+        ParseOutput::Arena => serde_json::to_string_pretty(&arena_json(&tree()?))?,
+        ParseOutput::Importtime => format_import_time(&records),
+        ParseOutput::FlatPaths => {
+            let json = FlatPathsJson {
+                modules: module_paths(&tree()?)
+                    .into_iter()
+                    .map(|module| ModulePathJson {
+                        name: module.name,
+                        path: module.path,
+                        self_us: module.self_us,
+                        cumulative_us: module.cumulative_us,
+                    })
+                    .collect(),
+            };
+            serde_json::to_string_pretty(&json)?
         }
+    };
+    write_text_output(text, output)
+}
+
+fn merged_records_json(runs: &[Vec<ImportRecord>], strategy: MergeRuns) -> ParseJson {
+    let mut samples = self_time_samples(runs);
+    ParseJson {
+        records: merge_runs(runs, strategy)
+            .into_iter()
+            .map(|record| {
+                let values = samples.remove(&record.name).unwrap_or_default();
+                ImportRecordJson {
+                    self_us_stddev: Some(std_dev(&values)),
+                    self_us_samples: Some(values),
+                    ..record_to_json(record)
+                }
+            })
+            .collect(),
     }
 }
 
-fn record_to_json(record: ImportRecord) -> ImportRecordJson {
-    ImportRecordJson {
-        name: record.name,
-        self_us: record.self_us,
-        cumulative_us: record.cumulative_us,
-        depth: record.depth,
+fn totals_json(tree: &Tree) -> TotalsJson {
+    TotalsJson {
+        modules: module_paths(tree)
+            .into_iter()
+            .map(|module| ModuleTotalJson {
+                difference_us: module.cumulative_us as i64 - module.total_us as i64,
+                name: module.name,
+                path: module.path,
+                cumulative_us: module.cumulative_us,
+                total_us: module.total_us,
+            })
+            .collect(),
     }
 }
 
-fn find_python_script(args: &[String]) -> Option<PathBuf> {
-    let candidate = args.first()?;
-    if candidate.starts_with('-') {
-        return None;
+fn arena_json(tree: &Tree) -> ArenaJson {
+    ArenaJson {
+        root: tree.root,
+        nodes: tree
+            .arena
+            .iter()
+            .enumerate()
+            .map(|(index, node)| ArenaNodeJson {
+                index,
+                name: node.name.clone(),
+                parent: node.parent,
+                children: node.children.clone(),
+                cumulative_us: node.cumulative_us,
+                total_us: tree.totals[index],
+                order: node.order,
+            })
+            .collect(),
     }
-    let path = Path::new(candidate);
-    if path.is_file() && is_python_shebang(path).unwrap_or(false) {
-        return Some(path.to_path_buf());
+}
+
+/// How to turn the input text into records.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RecordSource {
+    format: InputFormat,
+    merge: Option<MergeRuns>,
+    lenient: bool,
+    two_column: bool,
+    unwrap: bool,
+    /// `None` reads UTF-8.
+    encoding: Option<&'static Encoding>,
+}
+
+impl RecordSource {
+    /// Reads `input` in the source's encoding, rejoining wrapped lines with `--unwrap`.
+    pub(crate) fn read(&self, input: &str) -> Result<String> {
+        let text = read_input_as(input, self.encoding.unwrap_or(UTF_8))?;
+        if self.unwrap && self.format == InputFormat::Text {
+            return Ok(unwrap_continuation_lines(&text));
+        }
+        Ok(text)
     }
-    let path = find_in_path(candidate)?;
-    if is_python_shebang(&path).unwrap_or(false) {
-        Some(path)
-    } else {
-        None
+
+    /// Whether the input is a `graph --format json` report to rebuild as it is.
+    #[cfg(feature = "render")]
+    pub(crate) fn reads_graph_report(&self) -> bool {
+        self.format == InputFormat::Graph && self.merge.is_none()
+    }
+}
+
+/// Logs with `[pid N]` line tags are split per process, see `split_by_pid`, and each process
+/// becomes a top-level node.
+pub(crate) fn parse_records(text: &str, source: RecordSource) -> Result<Vec<ImportRecord>> {
+    let processes = match source.format {
+        InputFormat::Text => split_by_pid(text),
+        InputFormat::Json | InputFormat::Graph => None,
+    };
+    let Some(processes) = processes else {
+        return parse_process_records(text, source);
+    };
+    let mut combined = Vec::new();
+    for (pid, log) in processes {
+        let label = pid.map_or_else(|| "untagged".to_string(), |pid| format!("pid {pid}"));
+        let records = parse_process_records(&log, source)
+            .with_context(|| format!("failed to parse {label}"))?;
+        combined.extend(nest_records(records, &label));
     }
+    if combined.is_empty() {
+        return Err(NoRecords.into());
+    }
+    Ok(combined)
 }
 
-fn find_in_path(name: &str) -> Option<PathBuf> {
-    let path_var = std::env::var_os("PATH")?;
-    for dir in std::env::split_paths(&path_var) {
-        let candidate = dir.join(name);
-        if candidate.is_file() {
-            return Some(candidate);
+fn parse_process_records(text: &str, source: RecordSource) -> Result<Vec<ImportRecord>> {
+    match (source.format, source.merge) {
+        (InputFormat::Json | InputFormat::Graph, Some(_)) => {
+            bail!("--merge-runs is only supported for text input")
+        }
+        (InputFormat::Json, None) => parse_import_json(text),
+        (InputFormat::Graph, None) => parse_graph_json(text).map(|report| report.records),
+        (InputFormat::Text, Some(strategy)) => Ok(merge_runs(&parse_runs(text, source)?, strategy)),
+        (InputFormat::Text, None) if source.lenient || source.two_column => {
+            parse_text_loosely(text, source)
         }
+        (InputFormat::Text, None) => parse_import_time(text),
     }
-    None
 }
 
-fn is_python_shebang(path: &Path) -> Result<bool> {
-    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
-    let mut reader = BufReader::new(file);
-    let mut line = String::new();
-    if reader.read_line(&mut line)? == 0 {
-        return Ok(false);
+/// Each run of a multi-run text log, see `split_import_runs`.
+fn parse_runs(text: &str, source: RecordSource) -> Result<Vec<Vec<ImportRecord>>> {
+    if !source.lenient && !source.two_column {
+        return parse_import_runs(text);
     }
-    if !line.starts_with("#!") {
-        return Ok(false);
+    split_import_runs(text)?
+        .iter()
+        .map(|run| parse_text_loosely(run, source))
+        .collect()
+}
+
+/// Text parsing with `--lenient` or `--two-column`, printing any skipped lines.
+fn parse_text_loosely(text: &str, source: RecordSource) -> Result<Vec<ImportRecord>> {
+    let (records, warnings) = if source.two_column {
+        parse_import_time_two_column(text, source.lenient)?
+    } else {
+        parse_import_time_lenient(text)?
+    };
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    Ok(records)
+}
+
+fn suggest_command(
+    input: &str,
+    output: Option<PathBuf>,
+    limit: usize,
+    source: RecordSource,
+) -> Result<()> {
+    let text = source.read(input)?;
+    let tree = build_tree_from_parsed(parse_records(&text, source)?, &TreeOptions::default())?;
+    let json = SuggestJson {
+        suggestions: top_level_imports(&tree)
+            .into_iter()
+            .take(limit)
+            .map(|module| SuggestionJson {
+                name: module.name,
+                self_us: module.self_us,
+                cumulative_us: module.cumulative_us,
+            })
+            .collect(),
+    };
+    write_text_output(serde_json::to_string_pretty(&json)?, output)
+}
+
+fn record_to_json(record: ImportRecord) -> ImportRecordJson {
+    ImportRecordJson {
+        name: record.name,
+        self_us: record.self_us,
+        cumulative_us: record.cumulative_us,
+        depth: record.depth,
+        self_us_samples: None,
+        self_us_stddev: None,
     }
-    let lower = line.to_ascii_lowercase();
-    Ok(lower.contains("python"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-    use std::time::{SystemTime, UNIX_EPOCH};
-    #[cfg(unix)]
-    use std::os::unix::fs::PermissionsExt;
-
-    fn make_temp_dir() -> PathBuf {
-        let mut dir = std::env::temp_dir();
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        dir.push(format!("pyimporttime-test-{}-{}", std::process::id(), nanos));
-        fs::create_dir(&dir).unwrap();
-        dir
+
+    #[test]
+    fn merged_records_carry_self_time_samples() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 |   a.b\n\
+import time:       20 |         30 | a\n\
+import time: self [us] | cumulative | imported package\n\
+import time:       30 |         30 |   a.b\n\
+import time:       20 |         50 | a\n\
+import time: self [us] | cumulative | imported package\n\
+import time:       50 |         50 |   a.b\n\
+import time:       20 |         70 | a\n";
+        let source = RecordSource {
+            merge: Some(MergeRuns::Mean),
+            ..RecordSource::default()
+        };
+        let json = merged_records_json(&parse_runs(log, source).expect("runs"), MergeRuns::Mean);
+        let value = serde_json::to_value(json).expect("json");
+        let record = &value["records"][0];
+        assert_eq!(record["name"], "a.b");
+        assert_eq!(record["self_us"], 30);
+        assert_eq!(record["self_us_samples"], serde_json::json!([10, 30, 50]));
+        // Deviations of -20, 0 and 20 from the mean of 30.
+        let expected = (800.0_f64 / 3.0).sqrt();
+        assert!((record["self_us_stddev"].as_f64().unwrap() - expected).abs() < 1e-9);
+        assert_eq!(value["records"][1]["self_us_stddev"], 0.0);
+
+        // A module missing from a run counts as zero there, in the mean and the samples alike.
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       20 |         20 | a\n\
+import time: self [us] | cumulative | imported package\n\
+import time:       40 |         40 | a\n\
+import time:       10 |         10 | b\n";
+        let json = merged_records_json(&parse_runs(log, source).expect("runs"), MergeRuns::Mean);
+        let value = serde_json::to_value(json).expect("json");
+        let b = &value["records"][1];
+        assert_eq!(b["name"], "b");
+        assert_eq!(b["self_us"], 5);
+        assert_eq!(b["self_us_samples"], serde_json::json!([0, 10]));
+        assert_eq!(b["self_us_stddev"], 5.0);
     }
 
     #[test]
-    fn executable_prefers_shebang_script() {
-        let dir = make_temp_dir();
-        let script = dir.join("vs");
-        fs::write(&script, "#!/usr/bin/env python\nprint('hi')\n").unwrap();
+    fn totals_report_divergent_cumulative() {
+        // b's logged cumulative (50) is more than its self time plus b.c (5 + 3).
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |         50 | b\n\
+import time:       10 |         10 | a\n";
+        let records = parse_records(log, RecordSource::default()).expect("records");
+        let tree = build_tree_from_parsed(records, &TreeOptions::default()).expect("tree");
+        let json = serde_json::to_value(totals_json(&tree)).expect("json");
+        let module = |name: &str| {
+            json["modules"]
+                .as_array()
+                .expect("modules")
+                .iter()
+                .find(|module| module["name"] == name)
+                .cloned()
+                .expect(name)
+        };
+        assert_eq!(module("b")["cumulative_us"], 50);
+        assert_eq!(module("b")["total_us"], 8);
+        assert_eq!(module("b")["difference_us"], 42);
+        assert_eq!(module("b.c")["difference_us"], 0);
+        assert_eq!(module("a")["difference_us"], 0);
+    }
 
-        let args = vec![
-            script.to_string_lossy().to_string(),
-            "arg1".to_string(),
-        ];
-        let exe = Executable::from_python_and_args("python", &args).unwrap();
+    #[test]
+    fn dump_tree_lists_every_arena_node() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |          8 | b\n\
+import time:        0 |          0 | a\n";
+        let records = parse_records(log, RecordSource::default()).expect("records");
+        let tree = build_tree_from_parsed(records, &TreeOptions::default()).expect("tree");
+        let json = serde_json::to_value(arena_json(&tree)).expect("json");
+        let nodes = json["nodes"].as_array().expect("nodes");
+        // Total, a, b, b's self, b.c and b.c's self; a has no self time and so no self node.
+        assert_eq!(nodes.len(), 6);
+        assert_eq!(json["root"], 0);
+        assert_eq!(nodes[0]["name"], "Total");
+        assert_eq!(nodes[0]["total_us"], 8);
+        let b = nodes.iter().find(|node| node["name"] == "b").expect("b");
+        assert_eq!(b["parent"], 0);
+        assert_eq!(b["children"].as_array().expect("children").len(), 2);
+    }
 
-        assert_eq!(exe.path, script);
-        assert_eq!(exe.args, vec!["arg1".to_string()]);
+    #[cfg(feature = "render")]
+    #[test]
+    fn relayout_reads_records_back_from_graph_json() {
+        use crate::layout::LayoutConfig;
+        use crate::render::{build_graph_json, RenderOptions};
 
-        fs::remove_file(&exe.path).unwrap();
-        fs::remove_dir(&dir).unwrap();
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      400 |        400 | c\n";
+        let render = |text: &str, source: RecordSource, width: f64| {
+            let records = parse_records(text, source).expect("records");
+            let tree = build_tree_from_parsed(records, &TreeOptions::default()).expect("tree");
+            let config = LayoutConfig { width, ..LayoutConfig::default() };
+            let graph = build_graph_json(&tree, &config, &RenderOptions::default()).expect("json");
+            serde_json::to_value(graph).expect("value")
+        };
+        let report = render(log, RecordSource::default(), 1000.0);
+        let source = RecordSource { format: InputFormat::Graph, ..RecordSource::default() };
+        let relaid = render(&report.to_string(), source, 500.0);
+
+        assert_eq!(relaid["records"], report["records"]);
+        assert_eq!(relaid["meta"]["total_ms"], report["meta"]["total_ms"]);
+        assert_eq!(relaid["meta"]["width"], 500.0);
+        let widths = |graph: &serde_json::Value| -> Vec<f64> {
+            let rects = graph["rects"].as_array().expect("rects");
+            rects.iter().map(|rect| rect["w"].as_f64().unwrap()).collect()
+        };
+        assert_eq!(widths(&relaid).len(), widths(&report).len());
+        assert_ne!(widths(&relaid), widths(&report));
+        let err = parse_graph_json("{\"rects\": []}").unwrap_err();
+        assert!(err.to_string().contains("no `records`"), "{err}");
     }
 
+    #[cfg(feature = "render")]
     #[test]
-    fn executable_falls_back_to_python() {
-        let args = vec!["-c".to_string(), "print('hi')".to_string()];
-        let exe = Executable::from_python_and_args("python3", &args).unwrap();
+    fn relayout_keeps_root_time_and_tree_options() {
+        use crate::layout::LayoutConfig;
+        use crate::profile::ImportProfile;
+        use crate::render::{build_graph_json, RenderOptions};
+
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      500 |        500 | _frozen_importlib_external\n\
+import time:      300 |        300 | json\n";
+        let options = TreeOptions {
+            hide_bootstrap: true,
+            dedupe: true,
+            ..TreeOptions::default()
+        };
+        let render = |tree: &Tree| {
+            let graph = build_graph_json(tree, &LayoutConfig::default(), &RenderOptions::default())
+                .expect("json");
+            serde_json::to_value(graph).expect("value")
+        };
+        let tree = build_tree_from_parsed(parse_import_time(log).expect("log"), &options)
+            .expect("tree");
+        let report = render(&tree);
+        assert_eq!(report["meta"]["total_ms"], 0.8);
+        assert_eq!(report["root_self_us"], 500);
 
-        assert_eq!(exe.path, PathBuf::from("python3"));
-        assert_eq!(exe.args, args);
+        let parsed = parse_graph_json(&report.to_string()).expect("report");
+        assert!(parsed.tree_options.dedupe && parsed.tree_options.hide_bootstrap);
+        let relaid = render(ImportProfile::from_report(parsed).expect("profile").tree());
+        assert_eq!(relaid["meta"]["total_ms"], 0.8);
+        assert_eq!(relaid["records"], report["records"]);
+        assert_eq!(relaid["tree_options"], report["tree_options"]);
     }
 
+    #[cfg(feature = "render")]
     #[test]
     fn run_defaults_open_true() {
         let cli = Cli::parse_from(["pyimporttime", "run", "--", "-c", "print('hi')"]);
@@ -337,69 +1073,123 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "render")]
     #[test]
-    fn executable_resolves_script_from_path() {
-        let dir = make_temp_dir();
-        let script = dir.join("jp");
-        fs::write(&script, "#!/usr/bin/env python\nprint('hi')\n").unwrap();
-
-        let original_path = std::env::var_os("PATH");
-        unsafe {
-            std::env::set_var("PATH", &dir);
+    fn wait_for_complete_takes_its_value_only_after_equals() {
+        let cli = Cli::parse_from(["pyimporttime", "graph", "--wait-for-complete", "run.log"]);
+        match cli.command {
+            Commands::Graph { inputs, wait_for_complete, .. } => {
+                assert_eq!(inputs, ["run.log"]);
+                assert_eq!(wait_for_complete, Some(500));
+            }
+            _ => panic!("expected graph command"),
         }
+        let cli = Cli::parse_from(["pyimporttime", "graph", "--wait-for-complete=50", "run.log"]);
+        match cli.command {
+            Commands::Graph { wait_for_complete, .. } => assert_eq!(wait_for_complete, Some(50)),
+            _ => panic!("expected graph command"),
+        }
+    }
 
-        let args = vec!["jp".to_string(), "--help".to_string()];
-        let exe = Executable::from_python_and_args("python", &args).unwrap();
-
-        assert_eq!(exe.path, script);
-        assert_eq!(exe.args, vec!["--help".to_string()]);
-
-        unsafe {
-            match original_path {
-                Some(path) => std::env::set_var("PATH", path),
-                None => std::env::remove_var("PATH"),
-            }
+    #[cfg(feature = "render")]
+    #[test]
+    fn force_does_not_allow_overwriting_the_input() {
+        let cli = Cli::parse_from(["pyimporttime", "graph", "run.log", "-o", "run.log", "--force"]);
+        match cli.command {
+            Commands::Graph { force, overwrite_input, .. } => assert!(force && !overwrite_input),
+            _ => panic!("expected graph command"),
+        }
+        let cli = Cli::parse_from(["pyimporttime", "relayout", "r.json", "--overwrite-input"]);
+        match cli.command {
+            Commands::Relayout { force, overwrite_input, .. } => assert!(!force && overwrite_input),
+            _ => panic!("expected relayout command"),
         }
-        fs::remove_file(&exe.path).unwrap();
-        fs::remove_dir(&dir).unwrap();
     }
 
-    #[cfg(unix)]
     #[test]
-    fn run_renders_on_nonzero_exit() {
-        let dir = make_temp_dir();
-        let script = dir.join("fake-python");
-        let output = dir.join("out.html");
-        let script_body = "\
-#!/bin/sh
-echo \"import time: self [us] | cumulative | imported package\" 1>&2
-echo \"import time:       1 |          1 | a\" 1>&2
-exit 2
-";
-        fs::write(&script, script_body).unwrap();
-        let mut perms = fs::metadata(&script).unwrap().permissions();
-        perms.set_mode(0o755);
-        fs::set_permissions(&script, perms).unwrap();
-
-        let result = run_command(
-            script.to_str().unwrap(),
-            Vec::new(),
-            Some(output.clone()),
-            false,
-            LayoutConfig {
-                width: DEFAULT_WIDTH,
-                height: DEFAULT_HEIGHT,
-                gap: DEFAULT_GAP,
-                parent_pad: DEFAULT_PARENT_PAD,
-                header_height: DEFAULT_HEADER_HEIGHT,
-            },
-        );
+    fn check_exit_codes() {
+        let source = RecordSource::default();
+        let log = "\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n";
+        assert_eq!(check_text(log, 1.0, source).code(), 0);
+        assert_eq!(check_text(log, 0.4, source).code(), 1);
+        let malformed = "import time:      abc |        300 | a\n";
+        assert_eq!(check_text(malformed, 1.0, source).code(), 2);
+        assert_eq!(check_text("hello\n", 1.0, source).code(), 3);
+        let json = RecordSource {
+            format: InputFormat::Json,
+            ..RecordSource::default()
+        };
+        assert_eq!(check_text("[]", 1.0, json).code(), 3);
+        assert_eq!(check_text("{", 1.0, json).code(), 2);
+        assert_eq!(check_command("/nonexistent/import.log", 1.0, source).code(), 2);
+    }
 
-        assert!(result.is_ok());
-        assert!(output.is_file());
+    #[test]
+    fn compare_fails_past_either_tolerance() {
+        let source = RecordSource::default();
+        let baseline = r#"{"records": [
+            {"name": "a.b", "self_us": 300, "cumulative_us": 300, "depth": 2},
+            {"name": "a", "self_us": 200, "cumulative_us": 500, "depth": 1}
+        ]}"#;
+        let same = "\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n";
+        let tolerance = Tolerance::default();
+        assert_eq!(compare_text(same, baseline, tolerance, source).code(), 0);
+        let slower = "\
+import time:      900 |        900 |   a.b\n\
+import time:      200 |       1100 | a\n";
+        assert_eq!(compare_text(slower, baseline, tolerance, source).code(), 1);
+        // a.b grew by 0.6 ms, 200%, and a by 0.6 ms, 120%.
+        let loose_ms = Tolerance {
+            ms: Some(1.0),
+            ..Tolerance::default()
+        };
+        assert_eq!(compare_text(slower, baseline, loose_ms, source).code(), 0);
+        let loose_pct = Tolerance {
+            pct: Some(500.0),
+            ..Tolerance::default()
+        };
+        assert_eq!(compare_text(slower, baseline, loose_pct, source).code(), 0);
+        let loose_ms_only = Tolerance {
+            ms: Some(1.0),
+            pct: Some(10.0),
+            require_both: false,
+        };
+        assert_eq!(compare_text(slower, baseline, loose_ms_only, source).code(), 1);
+        let both = Tolerance {
+            require_both: true,
+            ..loose_ms_only
+        };
+        assert_eq!(compare_text(slower, baseline, both, source).code(), 0);
+        let new_module = "\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      100 |        100 | c\n";
+        assert_eq!(compare_text(new_module, baseline, tolerance, source).code(), 1);
+        let array = r#"[
+            {"name": "a.b", "self_us": 900, "cumulative_us": 900, "depth": 2},
+            {"name": "a", "self_us": 300, "cumulative_us": 1200, "depth": 1}
+        ]"#;
+        assert_eq!(compare_text(slower, array, tolerance, source).code(), 0);
+        assert_eq!(compare_text(same, "{", tolerance, source).code(), 2);
+    }
 
-        fs::remove_file(&output).unwrap();
-        fs::remove_file(&script).unwrap();
-        fs::remove_dir(&dir).unwrap();
+    #[test]
+    fn pid_tagged_logs_get_a_root_per_process() {
+        let log = "\
+[pid 7] import time:        4 |          4 |   json.decoder\n\
+[pid 9] import time:       20 |         20 | numpy\n\
+[pid 7] import time:        6 |         10 | json\n";
+        let records = parse_records(log, RecordSource::default()).expect("records");
+        let tree = build_tree_from_parsed(records, &TreeOptions::default()).expect("tree");
+        let paths = module_paths(&tree);
+        let decoder = paths.iter().find(|m| m.name == "json.decoder").expect("decoder");
+        assert_eq!(decoder.path, vec!["pid 7", "json", "json.decoder"]);
+        let numpy = paths.iter().find(|m| m.name == "numpy").expect("numpy");
+        assert_eq!(numpy.path, vec!["pid 9", "numpy"]);
+        assert_eq!(tree.total_us(), 30);
     }
 }
@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::layout::{AreaScale, ParentPad, SelfStyle, SortChildren};
+use crate::render::{PaintOrder, TimeUnit};
+
+/// Looked up in the working directory when `--config` is not given.
+pub const CONFIG_FILE_NAME: &str = "pyimporttime.toml";
+
+/// Defaults for `graph` and `run` read from a TOML file. Keys are the long flag names with
+/// underscores, e.g. `width = 1600` or `sort_children = "name"`; flags given on the command
+/// line take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    pub gap: Option<f64>,
+    pub parent_pad: Option<ParentPad>,
+    pub header_height: Option<f64>,
+    pub sort_children: Option<SortChildren>,
+    pub self_style: Option<SelfStyle>,
+    pub min_rect_px: Option<f64>,
+    pub self_min_px: Option<f64>,
+    pub collapse_below_pct: Option<f64>,
+    pub scale: Option<AreaScale>,
+    pub max_rects: Option<usize>,
+    pub flat: Option<bool>,
+    pub time_unit: Option<TimeUnit>,
+    pub paint_order: Option<PaintOrder>,
+    pub precision: Option<u32>,
+    pub background: Option<String>,
+    pub label_format: Option<String>,
+    pub link_template: Option<String>,
+    pub link_separator: Option<String>,
+}
+
+/// Reads `explicit` if given, otherwise `pyimporttime.toml` in the working directory if it
+/// exists. A missing explicit file is an error; a missing default file is not.
+pub fn load_config(explicit: Option<&Path>) -> Result<Option<FileConfig>> {
+    let path = match explicit {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let path = PathBuf::from(CONFIG_FILE_NAME);
+            if !path.is_file() {
+                return Ok(None);
+            }
+            path
+        }
+    };
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    let config = toml::from_str(&text)
+        .with_context(|| format!("failed to parse config {}", path.display()))?;
+    Ok(Some(config))
+}
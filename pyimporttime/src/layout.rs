@@ -1,18 +1,164 @@
-use crate::tree::Tree;
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::tree::{NodeKind, Tree};
 
 pub const DEFAULT_WIDTH: f64 = 3000.0;
 pub const DEFAULT_HEIGHT: f64 = 2000.0;
 pub const DEFAULT_GAP: f64 = 2.0;
 pub const DEFAULT_PARENT_PAD: f64 = 2.0;
 pub const DEFAULT_HEADER_HEIGHT: f64 = 16.0;
+const SELF_SHADE_FACTOR: f64 = 0.7;
+/// Stands in for the folded "(other)" group among a node's children; it has no arena node.
+const OTHER_INDEX: usize = usize::MAX;
+const OTHER_COLOR: &str = "#777777";
+/// How many of a parent's largest children its rect carries for hover breakdowns.
+const TOP_CHILDREN: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortChildren {
+    /// Largest area first, which gives squarify the best aspect ratios.
+    Layout,
+    /// Largest reported cumulative time first.
+    Time,
+    /// Alphabetical, for stable comparison across runs.
+    Name,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelfStyle {
+    /// A darker shade of the owning module's fill.
+    Shade,
+    /// A desaturated color hashed from the module name.
+    Distinct,
+}
+
+/// How sibling times map to areas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AreaScale {
+    /// Area proportional to time.
+    #[default]
+    Linear,
+    /// Area proportional to `ln(1 + us)`, which deliberately breaks area-equals-time so small
+    /// imports beside a dominant one stay visible.
+    Log,
+}
+
+/// Most per-depth padding values `ParentPad` holds; deeper levels reuse the last one.
+const MAX_PAD_DEPTHS: usize = 8;
+
+/// Padding inside parent rects, per depth: the first value applies to top-level modules, the
+/// next to their imports, and so on, with the last value covering everything deeper. Parsed
+/// from a comma-separated list such as `4,2,1`; a single value pads every depth alike.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParentPad {
+    values: [f64; MAX_PAD_DEPTHS],
+    len: usize,
+}
+
+impl ParentPad {
+    pub fn uniform(pad: f64) -> Self {
+        let mut values = [0.0; MAX_PAD_DEPTHS];
+        values[0] = pad;
+        Self { values, len: 1 }
+    }
+
+    /// Padding for a node at `depth`, where top-level modules are depth 1.
+    pub fn at_depth(&self, depth: usize) -> f64 {
+        self.values[depth.saturating_sub(1).min(self.len - 1)]
+    }
+}
+
+impl FromStr for ParentPad {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut values = [0.0; MAX_PAD_DEPTHS];
+        let mut len = 0;
+        for part in text.split(',') {
+            if len == MAX_PAD_DEPTHS {
+                return Err(format!("at most {MAX_PAD_DEPTHS} padding values are supported"));
+            }
+            let pad: f64 = part
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid padding {:?}, expected pixels", part.trim()))?;
+            if !pad.is_finite() || pad < 0.0 {
+                return Err(format!("padding must be a non-negative number, got {pad}"));
+            }
+            values[len] = pad;
+            len += 1;
+        }
+        Ok(Self { values, len })
+    }
+}
+
+impl fmt::Display for ParentPad {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, pad) in self.values[..self.len].iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{pad}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Config files may give a number (`parent_pad = 2`) or a list (`parent_pad = "4,2,1"`).
+impl<'de> Deserialize<'de> for ParentPad {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Uniform(f64),
+            List(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Uniform(pad) => Ok(Self::uniform(pad)),
+            Raw::List(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct LayoutConfig {
     pub width: f64,
     pub height: f64,
     pub gap: f64,
-    pub parent_pad: f64,
+    pub parent_pad: ParentPad,
     pub header_height: f64,
+    pub sort_children: SortChildren,
+    pub self_style: SelfStyle,
+    /// Smallest width or height, in pixels, of module rects. Modules that can't get it are
+    /// folded into their parent's "(other)" rect. Zero disables the clamp.
+    pub min_rect_px: f64,
+    /// Smallest width and height, in pixels, of a self rect or self overlay, taken from the
+    /// space of its siblings. Zero disables it.
+    pub self_min_px: f64,
+    /// Children below this percentage of their parent's total are folded into one "(other)"
+    /// rect. Zero disables folding.
+    pub collapse_below_pct: f64,
+    pub scale: AreaScale,
+    /// Upper bound on emitted rects. Once expanding a node would exceed it, the node is left as
+    /// a leaf standing for its whole subtree.
+    pub max_rects: Option<usize>,
+    /// Classic flat treemap: no parent padding or header strips, and only leaves (modules
+    /// without imports, and the self time of those with) are emitted, so parents are just the
+    /// union of their children.
+    pub flat: bool,
+    /// Print the layout tree (weight, area, and rect of every node) to stderr.
+    pub verbose: bool,
+    /// Snap each package's hue to the nearest of this many evenly spaced hues; see
+    /// `quantized_color_for_name`.
+    pub quantize_colors: Option<u16>,
 }
 
 impl Default for LayoutConfig {
@@ -21,8 +167,18 @@ impl Default for LayoutConfig {
             width: DEFAULT_WIDTH,
             height: DEFAULT_HEIGHT,
             gap: DEFAULT_GAP,
-            parent_pad: DEFAULT_PARENT_PAD,
+            parent_pad: ParentPad::uniform(DEFAULT_PARENT_PAD),
             header_height: DEFAULT_HEADER_HEIGHT,
+            sort_children: SortChildren::Layout,
+            self_style: SelfStyle::Distinct,
+            min_rect_px: 0.0,
+            self_min_px: 0.0,
+            collapse_below_pct: 0.0,
+            scale: AreaScale::Linear,
+            max_rects: None,
+            flat: false,
+            verbose: false,
+            quantize_colors: None,
         }
     }
 }
@@ -31,12 +187,27 @@ impl Default for LayoutConfig {
 pub struct Rect {
     pub name: String,
     pub display_ms: f64,
+    pub us: u64,
     pub x: f64,
     pub y: f64,
     pub w: f64,
     pub h: f64,
     pub is_self: bool,
+    /// Kind of the node the rect was laid out for; "(other)" rects are groups.
+    pub kind: NodeKind,
     pub color: String,
+    /// Largest child modules (name, cumulative µs), empty for leaves.
+    pub top_children: Vec<(String, u64)>,
+    /// The module's line number in the log; `None` for self and "(other)" rects.
+    pub order: Option<usize>,
+    /// Share of the whole import time, in percent.
+    pub pct: f64,
+    /// Nesting level, 1 for top-level modules. Self rects share their module's depth.
+    pub depth: usize,
+    /// Modules imported beneath this one; for "(other)" rects, the number of folded modules.
+    pub descendants: usize,
+    /// Index of the enclosing rect in the same list; `None` for top-level rects.
+    pub parent: Option<usize>,
 }
 
 #[derive(Clone, Copy)]
@@ -48,20 +219,347 @@ struct RectArea {
 }
 
 pub fn layout_tree(tree: &Tree, config: &LayoutConfig) -> Vec<Rect> {
+    let mut trace = config.verbose.then(String::new);
+    let rects = layout_tree_traced(tree, config, trace.as_mut());
+    if let Some(trace) = trace {
+        eprint!("{trace}");
+    }
+    rects
+}
+
+/// Subtrees with at least this many modules are laid out in parallel, see `layout_parallel`.
+const PARALLEL_MIN_DESCENDANTS: usize = 2000;
+/// Parallel descent stops this deep, so long import chains can't exhaust the call stack.
+const PARALLEL_MAX_DEPTH: usize = 8;
+
+/// Lays out the tree, appending one line per node to `trace` when given.
+fn layout_tree_traced(
+    tree: &Tree,
+    config: &LayoutConfig,
+    trace: Option<&mut String>,
+) -> Vec<Rect> {
+    // The trace and the --max-rects budget follow the serial visiting order.
+    let parallel = trace.is_none() && config.max_rects.is_none();
+    layout_rects(tree, config, trace, parallel)
+}
+
+fn layout_rects(
+    tree: &Tree,
+    config: &LayoutConfig,
+    trace: Option<&mut String>,
+    parallel: bool,
+) -> Vec<Rect> {
     let rect = RectArea {
         x: 0.0,
         y: 0.0,
         w: config.width,
         h: config.height,
     };
-    let mut rects = Vec::new();
-    layout_node(tree, tree.root, rect, &mut rects, config);
+    let descendants = tree.descendant_counts();
+    let mut laid_out = Subtree::default();
+    let folded = if parallel {
+        laid_out = layout_parallel(tree, config, &descendants, tree.root, rect, 0);
+        0
+    } else {
+        let start = (tree.root, rect, 0, None);
+        layout_serial(tree, config, &descendants, start, &mut laid_out, trace)
+    };
+    let Subtree { mut rects, parents } = laid_out;
+    if folded > 0 {
+        eprintln!(
+            "warning: reached --max-rects {}, {} subtrees were folded into their parents",
+            config.max_rects.unwrap_or_default(),
+            folded
+        );
+    }
+    if !parents.is_empty() {
+        let mut dropped = vec![false; rects.len()];
+        for index in parents {
+            dropped[index] = true;
+        }
+        drop_rects(&mut rects, &dropped);
+    }
     rects
 }
 
-fn layout_node(tree: &Tree, index: usize, area: RectArea, rects: &mut Vec<Rect>, config: &LayoutConfig) {
+/// Rects laid out for one subtree, with `parent` indices into `rects`, and the indices of
+/// expanded parents that flat mode drops.
+#[derive(Default)]
+struct Subtree {
+    rects: Vec<Rect>,
+    parents: Vec<usize>,
+}
+
+/// Lays out the subtree at `start` depth first into `out`, returning the number of subtrees
+/// folded to stay under `--max-rects`.
+fn layout_serial(
+    tree: &Tree,
+    config: &LayoutConfig,
+    descendants: &[usize],
+    start: (usize, RectArea, usize, Option<usize>),
+    out: &mut Subtree,
+    mut trace: Option<&mut String>,
+) -> usize {
+    // An explicit stack keeps pathologically deep import chains from overflowing the call stack.
+    let mut pending = vec![start];
+    let mut folded = 0;
+    while let Some((index, area, depth, parent)) = pending.pop() {
+        if let Some(trace) = trace.as_deref_mut() {
+            trace_node(trace, tree, index, &area, depth);
+        }
+        let step = (index, area, depth, parent);
+        match layout_step(tree, config, descendants, step, pending.len(), out) {
+            Step::Leaf => {}
+            Step::Folded(count) => folded += count,
+            Step::Descend(node_rect, children) => pending.extend(
+                children
+                    .into_iter()
+                    .rev()
+                    .map(|(child, area)| (child, area, depth + 1, node_rect)),
+            ),
+        }
+    }
+    folded
+}
+
+/// What to do with a node's children after `layout_step`.
+enum Step {
+    /// A flat-mode leaf: nothing beneath it gets a rect.
+    Leaf,
+    /// This many children were folded into the node to stay under `--max-rects`.
+    Folded(usize),
+    /// Lay out these children, parented to the node's rect.
+    Descend(Option<usize>, Vec<(usize, RectArea)>),
+}
+
+/// Lays out one node into `out`: appends its rects, links them to `parent`, decides whether
+/// to descend, and records expanded parents for flat mode. `pending` is the number of nodes
+/// still waiting for a rect, which count against `--max-rects`.
+fn layout_step(
+    tree: &Tree,
+    config: &LayoutConfig,
+    descendants: &[usize],
+    (index, area, depth, parent): (usize, RectArea, usize, Option<usize>),
+    pending: usize,
+    out: &mut Subtree,
+) -> Step {
+    let Subtree { rects, parents } = out;
+    let first = rects.len();
+    let children = layout_node(tree, index, area, depth, descendants, rects, config);
+    // The node's own rect comes first, followed by its "(other)" rect if any.
+    let node_rect = (index != tree.root).then_some(first);
+    if node_rect.is_some() {
+        rects[first].parent = parent;
+    }
+    for rect in &mut rects[first + usize::from(node_rect.is_some())..] {
+        rect.parent = node_rect;
+    }
+    // A module whose only child is its own self time is a leaf of the flat treemap.
+    if config.flat
+        && index != tree.root
+        && rects.len() == first + 1
+        && children.iter().all(|(child, _)| tree.arena[*child].name == "self")
+    {
+        return Step::Leaf;
+    }
+    // Every pending node still gets a rect, so count those before descending further.
+    if let Some(max_rects) = config.max_rects
+        && rects.len() + pending + children.len() > max_rects
+    {
+        // The node's "(other)" rect goes with the children it stands beside.
+        rects.truncate(first + usize::from(node_rect.is_some()));
+        return Step::Folded(children.len());
+    }
+    if config.flat && index != tree.root && rects.len() > first {
+        parents.push(first);
+    }
+    Step::Descend(node_rect, children)
+}
+
+/// Lays out the children of large subtrees on separate threads. Each child's area is fixed
+/// once its parent is squarified, so subtrees are independent; concatenating their rects in
+/// child order gives exactly the depth-first order of `layout_serial`.
+fn layout_parallel(
+    tree: &Tree,
+    config: &LayoutConfig,
+    descendants: &[usize],
+    index: usize,
+    area: RectArea,
+    depth: usize,
+) -> Subtree {
+    let mut out = Subtree::default();
+    if depth >= PARALLEL_MAX_DEPTH || descendants[index] < PARALLEL_MIN_DESCENDANTS {
+        layout_serial(tree, config, descendants, (index, area, depth, None), &mut out, None);
+        return out;
+    }
+    let step = layout_step(tree, config, descendants, (index, area, depth, None), 0, &mut out);
+    let Step::Descend(node_rect, children) = step else {
+        return out;
+    };
+    let subtrees: Vec<Subtree> = children
+        .into_par_iter()
+        .map(|(child, area)| layout_parallel(tree, config, descendants, child, area, depth + 1))
+        .collect();
+    for subtree in subtrees {
+        let offset = out.rects.len();
+        out.parents.extend(subtree.parents.into_iter().map(|index| index + offset));
+        out.rects.extend(subtree.rects.into_iter().map(|mut rect| {
+            // Only the child's own rect has no parent within its subtree.
+            rect.parent = rect.parent.map_or(node_rect, |parent| Some(parent + offset));
+            rect
+        }));
+    }
+    out
+}
+
+fn trace_node(trace: &mut String, tree: &Tree, index: usize, area: &RectArea, depth: usize) {
+    use std::fmt::Write;
+    let node = &tree.arena[index];
+    let name = if node.name == "self" {
+        format!("self of {}", parent_name(tree, index))
+    } else {
+        node.name.clone()
+    };
+    let _ = writeln!(
+        trace,
+        "{:indent$}{name}: weight {} us, area {:.1} px^2, rect x={:.1} y={:.1} w={:.1} h={:.1}",
+        "",
+        tree.sum_children(index),
+        area.w * area.h,
+        area.x,
+        area.y,
+        area.w,
+        area.h,
+        indent = depth * 2
+    );
+}
+
+/// The emitted leaf rect with the most extreme aspect ratio (long side over short side), if
+/// that ratio exceeds `threshold`, as a hint for making the treemap readable.
+pub fn sliver_warning(rects: &[Rect], threshold: f64) -> Option<String> {
+    let mut has_children = vec![false; rects.len()];
+    for parent in rects.iter().filter_map(|rect| rect.parent) {
+        has_children[parent] = true;
+    }
+    let (rect, aspect) = rects
+        .iter()
+        .zip(has_children)
+        .filter(|(rect, has_children)| !has_children && rect.w > 0.0 && rect.h > 0.0)
+        .map(|(rect, _)| (rect, rect.w.max(rect.h) / rect.w.min(rect.h)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    let label = if rect.is_self {
+        format!("{} (self)", rect.name)
+    } else {
+        rect.name.clone()
+    };
+    (aspect > threshold).then(|| {
+        format!(
+            "worst rect aspect ratio is {aspect:.0}:1 ({label}, {:.1}x{:.1} px); \
+            try --max-rects or --collapse-below-pct to fold tiny modules",
+            rect.w, rect.h
+        )
+    })
+}
+
+/// Removes the flagged rects, pointing their children at the nearest kept ancestor. Relies on
+/// parents preceding their children in the list.
+fn drop_rects(rects: &mut Vec<Rect>, dropped: &[bool]) {
+    let mut kept_index = vec![None; rects.len()];
+    let mut next = 0;
+    for (index, dropped) in dropped.iter().enumerate() {
+        if !dropped {
+            kept_index[index] = Some(next);
+            next += 1;
+        }
+    }
+    let parents: Vec<Option<usize>> = rects
+        .iter()
+        .map(|rect| {
+            let mut parent = rect.parent;
+            while let Some(index) = parent {
+                if kept_index[index].is_some() {
+                    return kept_index[index];
+                }
+                parent = rects[index].parent;
+            }
+            None
+        })
+        .collect();
+    let mut position = 0;
+    rects.retain_mut(|rect| {
+        rect.parent = parents[position];
+        position += 1;
+        !dropped[position - 1]
+    });
+}
+
+/// Slack for floating-point error when comparing rect edges.
+const VERIFY_EPSILON: f64 = 1e-6;
+
+/// Geometry problems in a finished layout: rects reaching outside their parent (or the canvas,
+/// for top-level rects) and overlapping leaf rects, i.e. rects that are nobody's parent. An
+/// empty list means the layout is sound.
+pub fn verify_layout(rects: &[Rect], config: &LayoutConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    let canvas = (0.0, 0.0, config.width, config.height);
+    let bounds = |rect: &Rect| (rect.x, rect.y, rect.x + rect.w, rect.y + rect.h);
+    let mut is_parent = vec![false; rects.len()];
+    for (index, rect) in rects.iter().enumerate() {
+        let (outer, outer_name) = match rect.parent {
+            Some(parent) => {
+                is_parent[parent] = true;
+                (bounds(&rects[parent]), rects[parent].name.as_str())
+            }
+            None => (canvas, "the canvas"),
+        };
+        let inner = bounds(rect);
+        if inner.0 < outer.0 - VERIFY_EPSILON
+            || inner.1 < outer.1 - VERIFY_EPSILON
+            || inner.2 > outer.2 + VERIFY_EPSILON
+            || inner.3 > outer.3 + VERIFY_EPSILON
+        {
+            problems.push(format!(
+                "rect {index} ({}) at {:?} extends outside {outer_name} at {:?}",
+                rect.name, inner, outer
+            ));
+        }
+    }
+    // Sweep along x so only leaves whose horizontal extents meet are compared.
+    let mut leaves: Vec<usize> = (0..rects.len()).filter(|index| !is_parent[*index]).collect();
+    leaves.sort_by(|a, b| rects[*a].x.total_cmp(&rects[*b].x));
+    for (position, &a) in leaves.iter().enumerate() {
+        let (ax0, ay0, ax1, ay1) = bounds(&rects[a]);
+        for &b in &leaves[position + 1..] {
+            let (bx0, by0, bx1, by1) = bounds(&rects[b]);
+            if bx0 >= ax1 - VERIFY_EPSILON {
+                break;
+            }
+            let overlap_x = ax1.min(bx1) - ax0.max(bx0);
+            let overlap_y = ay1.min(by1) - ay0.max(by0);
+            if overlap_x > VERIFY_EPSILON && overlap_y > VERIFY_EPSILON {
+                problems.push(format!(
+                    "leaf rects {a} ({}) and {b} ({}) overlap by {overlap_x:.3}x{overlap_y:.3}",
+                    rects[a].name, rects[b].name
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Emits the rect for `index` and returns the areas assigned to its children.
+fn layout_node(
+    tree: &Tree,
+    index: usize,
+    area: RectArea,
+    depth: usize,
+    descendants: &[usize],
+    rects: &mut Vec<Rect>,
+    config: &LayoutConfig,
+) -> Vec<(usize, RectArea)> {
     let node = &tree.arena[index];
     let total = tree.sum_children(index) as f64;
+    let pct = |us: u64| us as f64 / tree.total_us().max(1) as f64 * 100.0;
     if index != tree.root {
         let is_self = node.name == "self";
         let label = if is_self {
@@ -69,37 +567,51 @@ fn layout_node(tree: &Tree, index: usize, area: RectArea, rects: &mut Vec<Rect>,
         } else {
             node.name.clone()
         };
+        let hues = config.quantize_colors;
+        let color = if is_self && config.self_style == SelfStyle::Shade {
+            darken_color(&color_for_name(&label, false, hues), SELF_SHADE_FACTOR)
+        } else {
+            color_for_name(&label, is_self, hues)
+        };
         rects.push(Rect {
             name: label.clone(),
             display_ms: node.cumulative_us as f64 / 1000.0,
+            us: node.cumulative_us,
             x: area.x,
             y: area.y,
             w: area.w,
             h: area.h,
             is_self,
-            color: color_for_name(&label, is_self),
+            kind: node.kind,
+            color,
+            top_children: top_children(tree, index),
+            order: node.order.filter(|_| !is_self),
+            pct: pct(node.cumulative_us),
+            depth: if is_self { depth - 1 } else { depth },
+            descendants: descendants[index],
+            parent: None,
         });
     }
     if node.children.is_empty() || total <= 0.0 {
-        return;
+        return Vec::new();
     }
-    let area = if index == tree.root {
+    let area = if index == tree.root || config.flat {
         area
     } else {
-        inset_area(area, config.parent_pad)
+        inset_area(area, config.parent_pad.at_depth(depth))
     };
     if area.w <= 0.0 || area.h <= 0.0 {
-        return;
+        return Vec::new();
     }
-    let area = if index == tree.root {
+    let area = if index == tree.root || config.flat {
         area
     } else {
         reserve_header(area, config.header_height)
     };
     if area.w <= 0.0 || area.h <= 0.0 {
-        return;
+        return Vec::new();
     }
-    let children: Vec<(usize, f64)> = node
+    let mut children: Vec<(usize, f64)> = node
         .children
         .iter()
         .filter_map(|child_index| {
@@ -112,11 +624,174 @@ fn layout_node(tree: &Tree, index: usize, area: RectArea, rects: &mut Vec<Rect>,
         })
         .collect();
     if children.is_empty() {
-        return;
+        return Vec::new();
+    }
+    let mut other =
+        collapse_small_children(tree, &mut children, total, config.collapse_below_pct);
+    // Modules that come out under --min-rect-px are folded into "(other)" and the rest laid
+    // out again, so their time stays in the report rather than their rects being dropped.
+    let mut placed = loop {
+        let cumulative = |index: usize| {
+            other
+                .as_ref()
+                .filter(|_| index == OTHER_INDEX)
+                .map_or_else(|| tree.arena[index].cumulative_us, |other| other.us)
+        };
+        let name = |index: usize| {
+            other
+                .as_ref()
+                .filter(|_| index == OTHER_INDEX)
+                .map_or_else(|| tree.arena[index].name.as_str(), |other| other.label.as_str())
+        };
+        match config.sort_children {
+            SortChildren::Layout => {}
+            SortChildren::Time => {
+                children.sort_by_key(|child| std::cmp::Reverse(cumulative(child.0)))
+            }
+            SortChildren::Name => children.sort_by(|a, b| name(a.0).cmp(name(b.0))),
+        }
+        let (scaled, scaled_total) = scale_weights(children.clone(), total, config.scale);
+        let placed = if config.self_min_px > 0.0 {
+            squarify_keeping_self(tree, scaled, area, scaled_total, config)
+        } else {
+            squarify(scaled, area, scaled_total, config)
+        };
+        let too_small = too_small_children(tree, &placed, config.min_rect_px);
+        // Folding a lone module into a new "(other)" would only rename it.
+        if too_small.is_empty() || (too_small.len() == 1 && other.is_none()) {
+            break placed;
+        }
+        fold_into_other(tree, &mut children, &mut other, &too_small);
+    };
+    if let Some(other) = other
+        && let Some(position) = placed.iter().position(|(index, _)| *index == OTHER_INDEX)
+    {
+        let (_, area) = placed.remove(position);
+        rects.push(Rect {
+            name: other.label,
+            display_ms: other.us as f64 / 1000.0,
+            us: other.us,
+            x: area.x,
+            y: area.y,
+            w: area.w,
+            h: area.h,
+            is_self: false,
+            kind: NodeKind::Group,
+            color: OTHER_COLOR.to_string(),
+            top_children: Vec::new(),
+            order: None,
+            pct: pct(other.us),
+            depth: depth + 1,
+            descendants: other.count,
+            parent: None,
+        });
+    }
+    placed
+}
+
+fn top_children(tree: &Tree, index: usize) -> Vec<(String, u64)> {
+    let mut children: Vec<(String, u64)> = tree.arena[index]
+        .children
+        .iter()
+        .map(|child| &tree.arena[*child])
+        .filter(|child| child.name != "self")
+        .map(|child| (child.name.clone(), child.cumulative_us))
+        .collect();
+    children.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    children.truncate(TOP_CHILDREN);
+    children
+}
+
+struct OtherGroup {
+    label: String,
+    us: u64,
+    count: usize,
+}
+
+/// Replaces children below `pct` percent of `total` with a single `OTHER_INDEX` entry. Self
+/// time is never folded, and a lone small child is left alone since folding it hides nothing.
+fn collapse_small_children(
+    tree: &Tree,
+    children: &mut Vec<(usize, f64)>,
+    total: f64,
+    pct: f64,
+) -> Option<OtherGroup> {
+    if pct <= 0.0 {
+        return None;
+    }
+    let threshold = total * pct / 100.0;
+    let is_small =
+        |(index, weight): &(usize, f64)| *weight < threshold && tree.arena[*index].name != "self";
+    let count = children.iter().filter(|child| is_small(child)).count();
+    if count < 2 {
+        return None;
     }
-    let layout = squarify(children, area, total, config.gap);
-    for (child_index, child_area) in layout {
-        layout_node(tree, child_index, child_area, rects, config);
+    let mut weight = 0.0;
+    let mut us = 0;
+    children.retain(|child| {
+        if !is_small(child) {
+            return true;
+        }
+        weight += child.1;
+        us += tree.arena[child.0].cumulative_us;
+        false
+    });
+    children.push((OTHER_INDEX, weight));
+    Some(OtherGroup {
+        label: format!("(other: {count} modules)"),
+        us,
+        count,
+    })
+}
+
+/// Modules among `placed` narrower or shorter than `min_px`; self time and "(other)" are
+/// never among them.
+fn too_small_children(tree: &Tree, placed: &[(usize, RectArea)], min_px: f64) -> Vec<usize> {
+    if min_px <= 0.0 {
+        return Vec::new();
+    }
+    let min = min_px - VERIFY_EPSILON;
+    placed
+        .iter()
+        .filter(|(index, area)| {
+            *index != OTHER_INDEX
+                && tree.arena[*index].name != "self"
+                && (area.w < min || area.h < min)
+        })
+        .map(|(index, _)| *index)
+        .collect()
+}
+
+/// Moves `fold` out of `children` into the `OTHER_INDEX` entry, creating it if needed.
+fn fold_into_other(
+    tree: &Tree,
+    children: &mut Vec<(usize, f64)>,
+    other: &mut Option<OtherGroup>,
+    fold: &[usize],
+) {
+    let mut weight = 0.0;
+    let mut us = 0;
+    children.retain(|(index, child_weight)| {
+        if !fold.contains(index) {
+            return true;
+        }
+        weight += child_weight;
+        us += tree.arena[*index].cumulative_us;
+        false
+    });
+    let group = other.get_or_insert_with(|| {
+        children.push((OTHER_INDEX, 0.0));
+        OtherGroup {
+            label: String::new(),
+            us: 0,
+            count: 0,
+        }
+    });
+    group.us += us;
+    group.count += fold.len();
+    group.label = format!("(other: {} modules)", group.count);
+    if let Some(entry) = children.iter_mut().find(|(index, _)| *index == OTHER_INDEX) {
+        entry.1 += weight;
     }
 }
 
@@ -147,7 +822,7 @@ fn squarify(
     children: Vec<(usize, f64)>,
     area: RectArea,
     total: f64,
-    gap: f64,
+    config: &LayoutConfig,
 ) -> Vec<(usize, RectArea)> {
     if children.is_empty() || total <= 0.0 || area.w <= 0.0 || area.h <= 0.0 {
         return Vec::new();
@@ -156,7 +831,9 @@ fn squarify(
         .into_iter()
         .map(|(index, weight)| (index, weight / total * area.w * area.h))
         .collect();
-    items.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    if config.sort_children == SortChildren::Layout {
+        items.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    }
     let mut remaining = items.as_slice();
     let mut row: Vec<(usize, f64)> = Vec::new();
     let mut result: Vec<(usize, RectArea)> = Vec::new();
@@ -173,23 +850,86 @@ fn squarify(
         let mut candidate = row.clone();
         candidate.push(item);
         let worst_candidate = worst_aspect(&candidate, side);
-        if worst_candidate <= worst_current {
+        let fits_minimum = config.min_rect_px <= 0.0
+            || candidate.len() as f64 * (config.min_rect_px + config.gap) <= side + config.gap;
+        if worst_candidate <= worst_current && fits_minimum {
             row = candidate;
             remaining = &remaining[1..];
         } else {
-            let (row_rects, rest) = layout_row(&row, current, gap);
+            // Leave room for at least one more row of minimum-size rects.
+            let reserve = config.min_rect_px;
+            let (row_rects, rest) =
+                layout_row(&row, current, config.gap, config.min_rect_px, reserve);
             result.extend(row_rects);
             current = rest;
             row.clear();
         }
     }
     if !row.is_empty() {
-        let (row_rects, _rest) = layout_row(&row, current, gap);
+        let (row_rects, _rest) = layout_row(&row, current, config.gap, config.min_rect_px, 0.0);
         result.extend(row_rects);
     }
     result
 }
 
+/// Applies `scale` to sibling weights, returning them with their new sum. Folding into
+/// "(other)" has already happened on the true times.
+fn scale_weights(
+    children: Vec<(usize, f64)>,
+    total: f64,
+    scale: AreaScale,
+) -> (Vec<(usize, f64)>, f64) {
+    match scale {
+        AreaScale::Linear => (children, total),
+        AreaScale::Log => {
+            let children: Vec<(usize, f64)> =
+                children.into_iter().map(|(index, weight)| (index, weight.ln_1p())).collect();
+            let total = children.iter().map(|child| child.1).sum();
+            (children, total)
+        }
+    }
+}
+
+/// Like `squarify`, but if the self child would come out thinner than `self_min_px` it gets a
+/// full-width strip along the bottom of `area` at least that tall, and its siblings share the
+/// rest.
+fn squarify_keeping_self(
+    tree: &Tree,
+    children: Vec<(usize, f64)>,
+    area: RectArea,
+    total: f64,
+    config: &LayoutConfig,
+) -> Vec<(usize, RectArea)> {
+    let min = config.self_min_px;
+    let is_self = |index: usize| index != OTHER_INDEX && tree.arena[index].name == "self";
+    let self_weight = children.iter().find(|(index, _)| is_self(*index)).map(|child| child.1);
+    let placed = squarify(children.clone(), area, total, config);
+    let Some(self_weight) = self_weight else {
+        return placed;
+    };
+    let visible = placed
+        .iter()
+        .any(|(index, rect)| is_self(*index) && rect.w >= min && rect.h >= min);
+    if visible || area.w < min || area.h < min * 2.0 + config.gap {
+        return placed;
+    }
+    let height = (self_weight / total * area.h).max(min);
+    let rest = RectArea {
+        h: area.h - height - config.gap,
+        ..area
+    };
+    let strip = RectArea {
+        y: area.y + area.h - height,
+        h: height,
+        ..area
+    };
+    let (self_children, others): (Vec<_>, Vec<_>) =
+        children.into_iter().partition(|(index, _)| is_self(*index));
+    let mut placed = squarify(others, rest, total - self_weight, config);
+    placed.extend(self_children.into_iter().map(|(index, _)| (index, strip)));
+    placed
+}
+
 fn worst_aspect(row: &[(usize, f64)], side: f64) -> f64 {
     let mut max_area: f64 = 0.0;
     let mut min_area: f64 = f64::INFINITY;
@@ -207,7 +947,13 @@ fn worst_aspect(row: &[(usize, f64)], side: f64) -> f64 {
     (side2 * max_area / sum2).max(sum2 / (side2 * min_area))
 }
 
-fn layout_row(row: &[(usize, f64)], area: RectArea, gap: f64) -> (Vec<(usize, RectArea)>, RectArea) {
+fn layout_row(
+    row: &[(usize, f64)],
+    area: RectArea,
+    gap: f64,
+    min_px: f64,
+    reserve: f64,
+) -> (Vec<(usize, RectArea)>, RectArea) {
     let row_area: f64 = row.iter().map(|(_, area)| area).sum();
     if row_area <= 0.0 {
         return (Vec::new(), area);
@@ -219,10 +965,11 @@ fn layout_row(row: &[(usize, f64)], area: RectArea, gap: f64) -> (Vec<(usize, Re
         if available_h <= 0.0 {
             return (Vec::new(), area);
         }
-        let row_w = row_area / available_h;
+        // Clamping keeps rows that borrowed thickness for small items inside the parent.
+        let row_w = (row_area / available_h).max(min_px).min(area.w - reserve).max(0.0);
+        let lengths = row_lengths(row, available_h, min_px);
         let mut y = area.y;
-        for (index, item_area) in row {
-            let h = item_area / row_w;
+        for ((index, _), h) in row.iter().zip(lengths) {
             rects.push((
                 *index,
                 RectArea {
@@ -247,10 +994,10 @@ fn layout_row(row: &[(usize, f64)], area: RectArea, gap: f64) -> (Vec<(usize, Re
         if available_w <= 0.0 {
             return (Vec::new(), area);
         }
-        let row_h = row_area / available_w;
+        let row_h = (row_area / available_w).max(min_px).min(area.h - reserve).max(0.0);
+        let lengths = row_lengths(row, available_w, min_px);
         let mut x = area.x;
-        for (index, item_area) in row {
-            let w = item_area / row_h;
+        for ((index, _), w) in row.iter().zip(lengths) {
             rects.push((
                 *index,
                 RectArea {
@@ -272,24 +1019,85 @@ fn layout_row(row: &[(usize, f64)], area: RectArea, gap: f64) -> (Vec<(usize, Re
     }
 }
 
+/// Splits `available` between the row's items in proportion to their areas, raising items
+/// below `min_px` to the minimum at the expense of their larger siblings.
+fn row_lengths(row: &[(usize, f64)], available: f64, min_px: f64) -> Vec<f64> {
+    let weights: Vec<f64> = row.iter().map(|(_, area)| *area).collect();
+    let mut clamped = vec![false; weights.len()];
+    if min_px > 0.0 && min_px * weights.len() as f64 <= available {
+        loop {
+            let clamped_total = clamped.iter().filter(|c| **c).count() as f64 * min_px;
+            let free_weight: f64 = weights
+                .iter()
+                .zip(&clamped)
+                .filter(|(_, c)| !**c)
+                .map(|(w, _)| w)
+                .sum();
+            let mut changed = false;
+            for (weight, is_clamped) in weights.iter().zip(clamped.iter_mut()) {
+                if !*is_clamped && (available - clamped_total) * weight / free_weight < min_px {
+                    *is_clamped = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+    let clamped_total = clamped.iter().filter(|c| **c).count() as f64 * min_px;
+    let free_weight: f64 = weights
+        .iter()
+        .zip(&clamped)
+        .filter(|(_, c)| !**c)
+        .map(|(w, _)| w)
+        .sum();
+    weights
+        .iter()
+        .zip(&clamped)
+        .map(|(weight, is_clamped)| {
+            if *is_clamped {
+                min_px
+            } else {
+                (available - clamped_total) * weight / free_weight
+            }
+        })
+        .collect()
+}
+
 fn parent_name(tree: &Tree, index: usize) -> String {
     let node = &tree.arena[index];
     let parent = node.parent.and_then(|p| tree.arena.get(p));
     parent.map_or_else(|| node.name.clone(), |p| p.name.clone())
 }
 
-fn color_for_name(name: &str, is_self: bool) -> String {
+/// Hashes the top-level package name to a hue. With `hues`, the hue snaps to the nearest of
+/// that many evenly spaced hues, so packages share one of at most that many base colors.
+pub(crate) fn color_for_name(name: &str, is_self: bool, hues: Option<u16>) -> String {
     let first = name.split('.').next().unwrap_or(name);
     let mut hash: i32 = 0;
     for ch in first.chars() {
         hash = hash.wrapping_mul(31).wrapping_add(ch as i32);
     }
-    let hue = ((hash.wrapping_add(210)) % 360) as f64;
+    let mut hue = ((hash.wrapping_add(210)) % 360) as f64;
+    if let Some(hues) = hues.filter(|hues| *hues > 0) {
+        let step = 360.0 / hues as f64;
+        hue = ((hue.rem_euclid(360.0) / step).round() * step).rem_euclid(360.0);
+    }
     let (sat, light) = if is_self { (0.35, 0.45) } else { (0.45, 0.5) };
     let (r, g, b) = hsl_to_rgb(hue / 360.0, sat, light);
     format!("#{:02x}{:02x}{:02x}", r, g, b)
 }
 
+fn darken_color(color: &str, factor: f64) -> String {
+    let hex = color.trim_start_matches('#');
+    let channel = |offset: usize| {
+        let value = u8::from_str_radix(&hex[offset..offset + 2], 16).unwrap_or(0);
+        (value as f64 * factor).round() as u8
+    };
+    format!("#{:02x}{:02x}{:02x}", channel(0), channel(2), channel(4))
+}
+
 fn hsl_to_rgb(h: f64, s: f64, l: f64) -> (u8, u8, u8) {
     if s == 0.0 {
         let v = (l * 255.0).round() as u8;
@@ -344,4 +1152,510 @@ import time:        3 |          3 |   b.c\n";
         assert!(rects.iter().any(|rect| rect.name == "a"));
         assert!(rects.iter().any(|rect| rect.name == "b"));
     }
+
+    /// A trimmed real-world log with nesting, repeated imports and zero-time modules.
+    const SAMPLE_LOG: &str = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       120 |        120 |   _io\n\
+import time:        85 |         85 |   marshal\n\
+import time:       400 |        605 | _frozen_importlib_external\n\
+import time:        60 |         60 |       encodings.aliases\n\
+import time:       310 |        370 |     encodings\n\
+import time:        90 |         90 |     encodings.utf_8\n\
+import time:        40 |        500 |   _signal\n\
+import time:       210 |        210 |       _abc\n\
+import time:       180 |        390 |     abc\n\
+import time:       250 |        640 |   io\n\
+import time:        30 |       1170 | site\n\
+import time:         0 |          0 |       _weakrefset\n\
+import time:      1400 |       1400 |       numpy.core._multiarray_umath\n\
+import time:      2100 |       3500 |     numpy.core\n\
+import time:       900 |        900 |       numpy.linalg._umath_linalg\n\
+import time:       700 |       1600 |     numpy.linalg\n\
+import time:         5 |          5 |     numpy.version\n\
+import time:      3200 |       8305 |   numpy\n\
+import time:       120 |        120 |     json.decoder\n\
+import time:        80 |         80 |     json.encoder\n\
+import time:       300 |        500 |   json\n\
+import time:       650 |       9455 | app\n";
+
+    #[test]
+    fn sample_layout_has_no_overlaps_or_escapes() {
+        let tree = build_tree(SAMPLE_LOG).expect("tree");
+        for config in [
+            LayoutConfig::default(),
+            LayoutConfig {
+                flat: true,
+                ..LayoutConfig::default()
+            },
+            LayoutConfig {
+                width: 400.0,
+                height: 300.0,
+                min_rect_px: 6.0,
+                collapse_below_pct: 5.0,
+                ..LayoutConfig::default()
+            },
+        ] {
+            let rects = layout_tree(&tree, &config);
+            assert!(rects.len() > 10);
+            assert_eq!(verify_layout(&rects, &config), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn verify_layout_reports_overlaps_and_escapes() {
+        let tree = build_tree(SAMPLE_LOG).expect("tree");
+        let config = LayoutConfig::default();
+        let mut rects = layout_tree(&tree, &config);
+        let leaf = rects
+            .iter()
+            .position(|rect| rect.is_self && rect.name == "json.decoder")
+            .expect("leaf");
+        rects[leaf].w += 5000.0;
+        let problems = verify_layout(&rects, &config);
+        assert!(problems.iter().any(|problem| problem.contains("extends outside json.decoder")));
+        assert!(problems.iter().any(|problem| problem.contains("overlap")));
+    }
+
+    #[test]
+    fn sort_children_name_lays_out_alphabetically() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        5 |          5 | b\n\
+import time:       20 |         20 | c\n\
+import time:       10 |         10 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig {
+            sort_children: SortChildren::Name,
+            ..LayoutConfig::default()
+        };
+        let rects = layout_tree(&tree, &config);
+        let names: Vec<&str> = rects
+            .iter()
+            .filter(|rect| !rect.is_self)
+            .map(|rect| rect.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+        let layout_rects = layout_tree(&tree, &LayoutConfig::default());
+        let layout_names: Vec<&str> = layout_rects
+            .iter()
+            .filter(|rect| !rect.is_self)
+            .map(|rect| rect.name.as_str())
+            .collect();
+        assert_eq!(layout_names, vec!["c", "a", "b"]);
+    }
+
+    /// A log of `packages` top-level packages, each importing `modules` modules that each
+    /// import `leaves` leaf modules.
+    fn synthetic_log(packages: usize, modules: usize, leaves: usize) -> String {
+        let mut log = String::new();
+        for package in 0..packages {
+            let mut package_us = package + 1;
+            for module in 0..modules {
+                let mut module_us = module % 7 + 1;
+                for leaf in 0..leaves {
+                    let us = (package * 31 + module * 17 + leaf * 13) % 97 + 1;
+                    let name = format!("p{package}.m{module}.l{leaf}");
+                    log.push_str(&format!("import time: {us} | {us} |     {name}\n"));
+                    module_us += us;
+                }
+                log.push_str(&format!("import time: 1 | {module_us} |   p{package}.m{module}\n"));
+                package_us += module_us;
+            }
+            log.push_str(&format!("import time: 1 | {package_us} | p{package}\n"));
+        }
+        log
+    }
+
+    #[test]
+    fn parallel_layout_matches_serial() {
+        let tree = build_tree(&synthetic_log(3, 40, 50)).expect("tree");
+        assert!(tree.descendant_counts()[tree.root] >= PARALLEL_MIN_DESCENDANTS);
+        for config in [
+            LayoutConfig::default(),
+            LayoutConfig { flat: true, ..LayoutConfig::default() },
+            LayoutConfig { collapse_below_pct: 1.0, ..LayoutConfig::default() },
+        ] {
+            let serial = layout_rects(&tree, &config, None, false);
+            let parallel = layout_rects(&tree, &config, None, true);
+            assert_eq!(format!("{parallel:?}"), format!("{serial:?}"));
+        }
+    }
+
+    #[test]
+    fn quantize_colors_limits_distinct_hues() {
+        let names = ["numpy", "pandas", "django", "requests", "yaml", "attr", "click", "rich"];
+        let log: String = names
+            .iter()
+            .map(|name| format!("import time:       10 |         10 | {name}\n"))
+            .collect();
+        let tree = build_tree(&log).expect("tree");
+        let distinct = |quantize_colors| {
+            let config = LayoutConfig { quantize_colors, ..LayoutConfig::default() };
+            let rects = layout_tree(&tree, &config);
+            let colors: std::collections::HashSet<String> = rects
+                .into_iter()
+                .filter(|rect| !rect.is_self)
+                .map(|rect| rect.color)
+                .collect();
+            colors.len()
+        };
+        assert!(distinct(None) > 4);
+        assert!(distinct(Some(4)) <= 4);
+        assert_eq!(color_for_name("numpy", false, Some(1)), color_for_name("rich", false, Some(1)));
+    }
+
+    #[test]
+    fn self_style_shade_darkens_parent_color() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |          8 | b\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig {
+            self_style: SelfStyle::Shade,
+            ..LayoutConfig::default()
+        };
+        let rects = layout_tree(&tree, &config);
+        let parent = rects
+            .iter()
+            .find(|rect| rect.name == "b" && !rect.is_self)
+            .expect("parent");
+        let self_rect = rects
+            .iter()
+            .find(|rect| rect.name == "b" && rect.is_self)
+            .expect("self");
+        assert_eq!(self_rect.color, darken_color(&parent.color, SELF_SHADE_FACTOR));
+        assert_ne!(self_rect.color, color_for_name("b", true, None));
+    }
+
+    #[test]
+    fn layout_handles_very_deep_trees() {
+        let depth = 5000;
+        let mut log = String::from("import time: self [us] | cumulative | imported package\n");
+        for level in (1..=depth).rev() {
+            log.push_str(&format!(
+                "import time: {:>8} | {:>10} | {}m{}\n",
+                1,
+                depth - level + 1,
+                " ".repeat(level * 2 - 1),
+                level
+            ));
+        }
+        let tree = build_tree(&log).expect("tree");
+        assert_eq!(tree.total_us(), depth as u64);
+        let rects = layout_tree(&tree, &LayoutConfig::default());
+        assert!(rects.iter().any(|rect| rect.name == "m1"));
+    }
+
+    #[test]
+    fn min_rect_px_keeps_rects_clickable() {
+        let mut log = String::from("import time: self [us] | cumulative | imported package\n");
+        log.push_str("import time:   100000 |     100000 | big\n");
+        for index in 0..20 {
+            log.push_str(&format!("import time:        1 |          1 | tiny{}\n", index));
+        }
+        let tree = build_tree(&log).expect("tree");
+        let config = LayoutConfig {
+            width: 400.0,
+            height: 300.0,
+            min_rect_px: 4.0,
+            ..LayoutConfig::default()
+        };
+        let rects = layout_tree(&tree, &config);
+        assert!(rects.iter().all(|rect| rect.w >= 4.0 - 1e-6 && rect.h >= 4.0 - 1e-6));
+        assert_eq!(rects.iter().filter(|rect| rect.name.starts_with("tiny")).count(), 20);
+        assert_eq!(verify_layout(&rects, &config), Vec::<String>::new());
+
+        // Too many to all reach the minimum: the ones that can't are folded, not dropped.
+        let mut log = String::from("import time: self [us] | cumulative | imported package\n");
+        for index in 0..400 {
+            log.push_str(&format!("import time:        1 |          1 |   tiny{}\n", index));
+        }
+        log.push_str("import time:   100000 |     100400 | big\n");
+        let tree = build_tree(&log).expect("tree");
+        let rects = layout_tree(&tree, &config);
+        assert_eq!(verify_layout(&rects, &config), Vec::<String>::new());
+        let big = rects.iter().position(|rect| rect.name == "big").expect("big");
+        let under_big: u64 =
+            rects.iter().filter(|rect| rect.parent == Some(big)).map(|rect| rect.us).sum();
+        assert_eq!(under_big, 100400);
+        let other = rects.iter().find(|rect| rect.name.starts_with("(other")).expect("other");
+        let shown = rects.iter().filter(|rect| rect.name.starts_with("tiny")).count();
+        assert_eq!(shown + other.descendants, 400);
+    }
+
+    #[test]
+    fn log_scale_compresses_area_ratios() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:   800000 |     800000 | big\n\
+import time:     1000 |       1000 | small\n";
+        let tree = build_tree(log).expect("tree");
+        let area_ratio = |scale: AreaScale| {
+            let rects = layout_tree(&tree, &LayoutConfig { scale, ..LayoutConfig::default() });
+            let area = |name: &str| {
+                let rect = rects.iter().find(|rect| rect.name == name).expect("rect");
+                rect.w * rect.h
+            };
+            area("big") / area("small")
+        };
+        let linear = area_ratio(AreaScale::Linear);
+        let log_scaled = area_ratio(AreaScale::Log);
+        assert!(linear > 100.0, "{linear}");
+        assert!(log_scaled < 3.0, "{log_scaled}");
+        let config = LayoutConfig {
+            scale: AreaScale::Log,
+            ..LayoutConfig::default()
+        };
+        let rects = layout_tree(&tree, &config);
+        let small = rects.iter().find(|rect| rect.name == "small").expect("small");
+        assert_eq!(small.us, 1000);
+    }
+
+    #[test]
+    fn self_min_px_keeps_tiny_self_time_visible() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:   500000 |     500000 |   heavy\n\
+import time:        1 |     500001 | pkg\n";
+        let tree = build_tree(log).expect("tree");
+        let self_rect = |config: &LayoutConfig| {
+            layout_tree(&tree, config)
+                .into_iter()
+                .find(|rect| rect.is_self && rect.name == "pkg")
+                .map_or(0.0, |rect| rect.w.min(rect.h))
+        };
+        assert!(self_rect(&LayoutConfig::default()) < 1.0);
+        let config = LayoutConfig {
+            self_min_px: 3.0,
+            ..LayoutConfig::default()
+        };
+        assert!(self_rect(&config) >= 3.0);
+        let heavy = layout_tree(&tree, &config)
+            .into_iter()
+            .find(|rect| rect.name == "heavy")
+            .expect("heavy rect");
+        assert!(heavy.w > 0.0 && heavy.h > 0.0);
+    }
+
+    #[test]
+    fn collapse_below_pct_folds_tiny_siblings() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1000 |       1000 | big\n\
+import time:        5 |          5 | tiny_a\n\
+import time:        7 |          7 | tiny_b\n\
+import time:        9 |          9 | tiny_c\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig {
+            collapse_below_pct: 5.0,
+            ..LayoutConfig::default()
+        };
+        let rects = layout_tree(&tree, &config);
+        assert!(rects.iter().all(|rect| !rect.name.starts_with("tiny")));
+        let other = rects
+            .iter()
+            .find(|rect| rect.name == "(other: 3 modules)")
+            .expect("other rect");
+        assert_eq!(other.us, 21);
+        assert!(other.w > 0.0 && other.h > 0.0);
+        assert!(rects.iter().any(|rect| rect.name == "big"));
+    }
+
+    #[test]
+    fn parent_rects_carry_top_children() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        1 |          1 |   p.a\n\
+import time:        4 |          4 |   p.b\n\
+import time:        3 |          3 |   p.c\n\
+import time:        2 |          2 |   p.d\n\
+import time:        5 |         15 | p\n";
+        let tree = build_tree(log).expect("tree");
+        let rects = layout_tree(&tree, &LayoutConfig::default());
+        let parent = rects
+            .iter()
+            .find(|rect| rect.name == "p" && !rect.is_self)
+            .expect("parent rect");
+        let expected = vec![("p.b".to_string(), 4), ("p.c".to_string(), 3), ("p.d".to_string(), 2)];
+        assert_eq!(parent.top_children, expected);
+        let leaf = rects.iter().find(|rect| rect.name == "p.a").expect("leaf rect");
+        assert!(leaf.top_children.is_empty());
+    }
+
+    #[test]
+    fn max_rects_folds_subtrees_past_the_limit() {
+        let mut log = String::new();
+        for package in 0..200 {
+            for module in 0..3 {
+                log.push_str(&format!("import time: 10 | 10 |   pkg{package}.mod{module}\n"));
+            }
+            log.push_str(&format!("import time: 10 | 40 | pkg{package}\n"));
+        }
+        let tree = build_tree(&log).expect("tree");
+        assert!(layout_tree(&tree, &LayoutConfig::default()).len() > 1000);
+
+        let config = LayoutConfig {
+            max_rects: Some(300),
+            ..LayoutConfig::default()
+        };
+        let rects = layout_tree(&tree, &config);
+        assert!(rects.len() <= 300);
+        assert_eq!(rects.iter().filter(|rect| rect.name.starts_with("pkg")).count(), 300);
+        assert!(rects.iter().any(|rect| rect.name == "pkg199"));
+    }
+
+    #[test]
+    fn max_rects_fold_drops_the_other_rect_too() {
+        let mut log = String::from("import time: 500000 | 500000 |   big.main\n");
+        for module in 0..300 {
+            log.push_str(&format!("import time: 1 | 1 |   big.tiny{module}\n"));
+        }
+        log.push_str("import time: 10 | 500310 | big\n");
+        for module in 0..5 {
+            log.push_str(&format!("import time: 100000 | 100000 | small{module}\n"));
+        }
+        let tree = build_tree(&log).expect("tree");
+        let collapse = LayoutConfig {
+            collapse_below_pct: 1.0,
+            ..LayoutConfig::default()
+        };
+        let unlimited = layout_tree(&tree, &collapse);
+        assert!(unlimited.iter().any(|rect| rect.name.starts_with("(other")));
+
+        let config = LayoutConfig {
+            max_rects: Some(8),
+            ..collapse
+        };
+        let rects = layout_tree(&tree, &config);
+        let big = rects.iter().position(|rect| rect.name == "big").expect("big rect");
+        assert!(rects.iter().all(|rect| rect.parent != Some(big)), "{rects:?}");
+        assert!(verify_layout(&rects, &config).is_empty());
+    }
+
+    #[test]
+    fn flat_layout_has_no_header_gap_or_parent_rects() {
+        let log = "\
+import time:       30 |         30 |   a.b\n\
+import time:       10 |         40 | a\n\
+import time:       20 |         20 | c\n";
+        let tree = build_tree(log).expect("tree");
+        let nested = layout_tree(&tree, &LayoutConfig::default());
+        let config = LayoutConfig {
+            flat: true,
+            ..LayoutConfig::default()
+        };
+        let rects = layout_tree(&tree, &config);
+        assert!(rects.iter().all(|rect| rect.is_self || rect.name != "a"));
+        assert!(rects.iter().any(|rect| !rect.is_self && rect.name == "a.b"));
+        assert!(rects.iter().all(|rect| !rect.is_self || rect.name == "a"));
+        assert!(nested.iter().any(|rect| !rect.is_self && rect.name == "a"));
+
+        let a_children: Vec<&Rect> = rects
+            .iter()
+            .filter(|rect| rect.name == "a.b" || rect.name == "a")
+            .collect();
+        assert_eq!(a_children.len(), 2);
+        let top = a_children.iter().map(|rect| rect.y).fold(f64::INFINITY, f64::min);
+        let nested_a = nested.iter().find(|rect| !rect.is_self && rect.name == "a").expect("a");
+        assert!((top - nested_a.y).abs() < 1e-6, "children start at the parent's top edge");
+        let area: f64 = rects.iter().map(|rect| rect.w * rect.h).sum();
+        let nested_leaves: f64 = nested
+            .iter()
+            .filter(|rect| !rect.is_self && (rect.name == "a.b" || rect.name == "c"))
+            .chain(nested.iter().filter(|rect| rect.is_self && rect.name == "a"))
+            .map(|rect| rect.w * rect.h)
+            .sum();
+        assert!(area > nested_leaves);
+    }
+
+    #[test]
+    fn hidden_bootstrap_modules_get_no_rects() {
+        let log = "\
+import time:      300 |        300 | _frozen_importlib_external\n\
+import time:       10 |         10 |   importlib._bootstrap\n\
+import time:       20 |         30 | app\n";
+        let records = crate::parser::parse_import_time(log).expect("records");
+        let options = crate::tree::TreeOptions {
+            hide_bootstrap: true,
+            include_builtin: true,
+            ..Default::default()
+        };
+        let tree = crate::tree::build_tree_from_parsed(records, &options).expect("tree");
+        let rects = layout_tree(&tree, &LayoutConfig::default());
+        assert!(rects.iter().all(|rect| !crate::parser::is_bootstrap_module(&rect.name)));
+        assert!(rects.iter().any(|rect| rect.name == "app"));
+    }
+
+    #[test]
+    fn verbose_trace_reports_module_area() {
+        let tree = build_tree(SAMPLE_LOG).expect("tree");
+        let config = LayoutConfig::default();
+        let mut trace = String::new();
+        let rects = layout_tree_traced(&tree, &config, Some(&mut trace));
+        assert_eq!(rects.len(), layout_tree(&tree, &config).len());
+        let numpy = rects.iter().find(|rect| rect.name == "numpy").expect("numpy rect");
+        let line = trace
+            .lines()
+            .find(|line| line.trim_start().starts_with("numpy:"))
+            .expect("numpy line");
+        assert!(line.starts_with("    numpy:"), "{line}");
+        assert!(line.contains(&format!("area {:.1} px^2", numpy.w * numpy.h)), "{line}");
+        assert!(trace.starts_with("Total: weight "));
+    }
+
+    #[test]
+    fn sliver_warning_flags_skewed_layouts() {
+        let skewed = "\
+import time: self [us] | cumulative | imported package\n\
+import time:   100000 |     100000 | huge\n\
+import time:       10 |         10 | tiny\n";
+        let tree = build_tree(skewed).expect("tree");
+        let rects = layout_tree(&tree, &LayoutConfig::default());
+        let warning = sliver_warning(&rects, 10.0).expect("warning");
+        assert!(warning.contains("tiny"), "{warning}");
+        assert!(warning.contains("--max-rects"), "{warning}");
+
+        let even = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1000 |       1000 | a\n\
+import time:     1000 |       1000 | b\n";
+        let tree = build_tree(even).expect("tree");
+        let rects = layout_tree(&tree, &LayoutConfig::default());
+        assert_eq!(sliver_warning(&rects, 10.0), None);
+    }
+
+    #[test]
+    fn parent_pad_shrinks_with_depth() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      500 |        500 |     a.b.c\n\
+import time:      500 |       1000 |   a.b\n\
+import time:      500 |       1500 | a\n\
+import time:      500 |        500 | d\n";
+        let tree = build_tree(log).expect("tree");
+        let insets = |parent_pad: &str| {
+            let config = LayoutConfig {
+                parent_pad: parent_pad.parse().expect("pad"),
+                ..LayoutConfig::default()
+            };
+            let rects = layout_tree(&tree, &config);
+            let rect = |name: &str| {
+                rects.iter().find(|rect| rect.name == name && !rect.is_self).expect(name)
+            };
+            (rect("a.b").x - rect("a").x, rect("a.b.c").x - rect("a.b").x)
+        };
+        let (depth1, depth2) = insets("8,2");
+        assert!(depth1 > depth2, "{depth1} vs {depth2}");
+        assert!((depth1 - depth2 - 6.0).abs() < 1e-9);
+        let (depth1, depth2) = insets("3");
+        assert!((depth1 - depth2).abs() < 1e-9);
+
+        let pad: ParentPad = "4, 2,1".parse().expect("pad");
+        assert_eq!((pad.at_depth(1), pad.at_depth(2), pad.at_depth(5)), (4.0, 2.0, 1.0));
+        assert_eq!(pad.to_string(), "4,2,1");
+        assert!("4,-1".parse::<ParentPad>().is_err());
+        assert!("4,x".parse::<ParentPad>().is_err());
+    }
 }
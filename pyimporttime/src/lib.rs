@@ -0,0 +1,27 @@
+//! Reads CPython `-X importtime` logs and turns them into reports. The `pyimporttime`
+//! binary is a thin wrapper around `run`; embedders start from [`ImportProfile`].
+
+mod cli;
+#[cfg(feature = "render")]
+mod config;
+#[cfg(feature = "render")]
+mod layout;
+mod parser;
+#[cfg(feature = "render")]
+mod profile;
+#[cfg(feature = "render")]
+mod render;
+#[cfg(feature = "render")]
+mod report;
+mod tree;
+mod util;
+
+pub use cli::run;
+#[cfg(feature = "render")]
+pub use layout::LayoutConfig;
+pub use parser::{parse_import_time, ImportRecord};
+#[cfg(feature = "render")]
+pub use profile::ImportProfile;
+#[cfg(feature = "render")]
+pub use render::GraphJson;
+pub use tree::{critical_path, tree_records, PathStep, Tree, TreeOptions};
@@ -1,35 +1,561 @@
-use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Clone)]
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::tree::TreeOptions;
+
+/// The input held no import time records at all, as opposed to malformed ones. Callers can
+/// tell the two apart with `anyhow::Error::is::<NoRecords>()`.
+#[derive(Debug)]
+pub struct NoRecords;
+
+impl fmt::Display for NoRecords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("no import time records found")
+    }
+}
+
+impl std::error::Error for NoRecords {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImportRecord {
     pub name: String,
     pub self_us: u64,
     pub cumulative_us: u64,
     pub depth: usize,
+    /// Set for records that only group others, such as one input file or process of a
+    /// combined log, rather than standing for a module.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic: bool,
 }
 
 pub fn parse_import_time(text: &str) -> Result<Vec<ImportRecord>> {
+    parse_import_lines(text, false, false).map(|(records, _)| records)
+}
+
+/// Like `parse_import_time`, but skips malformed `import time:` lines instead of failing,
+/// returning a warning for each. Still fails if no line parses.
+pub fn parse_import_time_lenient(text: &str) -> Result<(Vec<ImportRecord>, Vec<String>)> {
+    parse_import_lines(text, true, false)
+}
+
+/// Also accepts `import time: <self> | <module>` lines without the cumulative column, from
+/// reduced importtime variants and hand-written fixtures. Their cumulative time is derived
+/// from self time and children, see `roll_up_cumulative`. Opt-in because such lines are
+/// otherwise malformed.
+pub fn parse_import_time_two_column(
+    text: &str,
+    lenient: bool,
+) -> Result<(Vec<ImportRecord>, Vec<String>)> {
+    parse_import_lines(text, lenient, true)
+}
+
+fn parse_import_lines(
+    text: &str,
+    lenient: bool,
+    two_column: bool,
+) -> Result<(Vec<ImportRecord>, Vec<String>)> {
+    let text = strip_ansi(text);
     // Import time lines are emitted after the import completes, so the log is in post-order.
     let mut records = Vec::new();
+    let mut warnings = Vec::new();
+    // Records from two-column lines, whose cumulative time is derived below.
+    let mut derived = Vec::new();
     for (line_no, line) in text.lines().enumerate() {
         if let Some(record) = parse_import_line(line) {
             records.push(record);
-        } else if line.starts_with("import time:") {
+        } else if let Some(record) = parse_two_column_line(line).filter(|_| two_column) {
+            derived.push(records.len());
+            records.push(record);
+        } else if strip_marker(line).is_some() {
             if line.contains("self [us]") {
                 continue;
             }
-            return Err(anyhow!("failed to parse import time on line {}", line_no + 1));
+            if !lenient {
+                return Err(anyhow!("failed to parse import time on line {}", line_no + 1));
+            }
+            warnings.push(format!("skipped malformed import time on line {}", line_no + 1));
+        }
+    }
+    if records.is_empty() {
+        return Err(NoRecords.into());
+    }
+    roll_up_cumulative(&mut records, &derived);
+    Ok((records, warnings))
+}
+
+/// Sets the cumulative time of each record in `derived` to its self time plus its children's
+/// cumulative times, relying on the log's post-order (children right before their parent).
+fn roll_up_cumulative(records: &mut [ImportRecord], derived: &[usize]) {
+    if derived.is_empty() {
+        return;
+    }
+    let mut is_derived = vec![false; records.len()];
+    for index in derived {
+        is_derived[*index] = true;
+    }
+    // (depth, cumulative) of records whose parent has not been seen yet.
+    let mut open: Vec<(usize, u64)> = Vec::new();
+    for (index, record) in records.iter_mut().enumerate() {
+        let mut children_us = 0;
+        while let Some((depth, cumulative)) = open.last().copied()
+            && depth > record.depth
+        {
+            open.pop();
+            if depth == record.depth + 1 {
+                children_us += cumulative;
+            }
+        }
+        if is_derived[index] {
+            record.cumulative_us = record.self_us + children_us;
         }
+        open.push((record.depth, record.cumulative_us));
     }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// CPython's `-X importtime` stderr log.
+    #[default]
+    Text,
+    /// A JSON array of `{name, self_us, cumulative_us, depth}` objects; see `parse_import_json`.
+    Json,
+    /// A report written by `graph --format json`, read back from its records; see
+    /// `parse_graph_json`.
+    Graph,
+}
+
+/// Reads records produced by other tools. The input is a JSON array of objects with `name`,
+/// `self_us`, `cumulative_us` and `depth` fields, ordered like CPython's log: each module
+/// after its children, with top-level modules at depth 1.
+pub fn parse_import_json(text: &str) -> Result<Vec<ImportRecord>> {
+    let records: Vec<ImportRecord> =
+        serde_json::from_str(text).context("failed to parse import time JSON")?;
+    check_json_records(records)
+}
+
+#[derive(Deserialize)]
+struct GraphRecords {
+    records: Option<Vec<ImportRecord>>,
+    #[serde(default)]
+    root_self_us: u64,
+    tree_options: Option<TreeOptions>,
+}
+
+/// The tree stored in a `graph --format json` report: its records, already filtered with
+/// `tree_options`, and the root's own time, which belongs to no record.
+#[derive(Debug)]
+pub struct GraphReport {
+    pub records: Vec<ImportRecord>,
+    pub root_self_us: u64,
+    pub tree_options: TreeOptions,
+}
+
+/// Reads the tree of a `graph --format json` report, so it can be laid out again without the
+/// original log. Reports from before `tree_options` was written get the defaults.
+pub fn parse_graph_json(text: &str) -> Result<GraphReport> {
+    let graph: GraphRecords =
+        serde_json::from_str(text).context("failed to parse graph JSON report")?;
+    let Some(records) = graph.records else {
+        return Err(anyhow!(
+            "the graph JSON report has no `records`; write it again with this version"
+        ));
+    };
+    Ok(GraphReport {
+        records: check_json_records(records)?,
+        root_self_us: graph.root_self_us,
+        tree_options: graph.tree_options.unwrap_or_default(),
+    })
+}
+
+fn check_json_records(records: Vec<ImportRecord>) -> Result<Vec<ImportRecord>> {
     if records.is_empty() {
-        return Err(anyhow!("no import time records found"));
+        return Err(NoRecords.into());
+    }
+    if let Some(record) = records.iter().find(|record| record.depth == 0) {
+        return Err(anyhow!("record {} has depth 0; top-level modules are depth 1", record.name));
     }
     Ok(records)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MergeRuns {
+    Sum,
+    Mean,
+    Max,
+}
+
+/// Splits a log holding several interpreter runs into one record list per run. Each run
+/// starts with CPython's `import time: self [us] | ...` header line.
+pub fn parse_import_runs(text: &str) -> Result<Vec<Vec<ImportRecord>>> {
+    split_import_runs(text)?
+        .iter()
+        .map(|run| parse_import_time(run))
+        .collect()
+}
+
+/// The text of each run in a multi-run log, see `parse_import_runs`.
+pub fn split_import_runs(text: &str) -> Result<Vec<String>> {
+    let text = strip_ansi(text);
+    let mut runs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if strip_marker(line).is_some() && line.contains("self [us]") && !current.is_empty() {
+            runs.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    runs.push(current);
+    let runs: Vec<String> = runs
+        .into_iter()
+        .filter(|run| {
+            run.lines()
+                .any(|line| parse_import_line(line).or_else(|| parse_two_column_line(line)).is_some())
+        })
+        .collect();
+    if runs.is_empty() {
+        return Err(NoRecords.into());
+    }
+    Ok(runs)
+}
+
+/// Splits a log captured from several processes, where each line carries a `[pid N]` prefix
+/// (as written by `strace -f` and similar tagging wrappers), into one log per process in order
+/// of first appearance. Untagged lines are grouped under `None`. Returns `None` when no line
+/// is tagged; processes without import time records, such as one holding only the header
+/// line, are dropped.
+pub fn split_by_pid(text: &str) -> Option<Vec<(Option<u32>, String)>> {
+    let text = strip_ansi(text);
+    let mut processes: Vec<(Option<u32>, String)> = Vec::new();
+    let mut tagged = false;
+    for line in text.lines() {
+        let (pid, line) = match pid_prefix(line) {
+            Some((pid, rest)) => {
+                tagged = true;
+                (Some(pid), rest)
+            }
+            None => (None, line),
+        };
+        let index = match processes.iter().position(|(other, _)| *other == pid) {
+            Some(index) => index,
+            None => {
+                processes.push((pid, String::new()));
+                processes.len() - 1
+            }
+        };
+        processes[index].1.push_str(line);
+        processes[index].1.push('\n');
+    }
+    if !tagged {
+        return None;
+    }
+    processes.retain(|(_, log)| log.lines().any(|line| parse_import_line(line).is_some()));
+    Some(processes)
+}
+
+/// Parses `[pid 1234] rest`, allowing the padding strace uses inside the brackets.
+fn pid_prefix(line: &str) -> Option<(u32, &str)> {
+    let rest = line.strip_prefix("[pid")?;
+    let (pid, rest) = rest.split_once(']')?;
+    let pid = pid.trim().parse().ok()?;
+    Some((pid, rest.strip_prefix(' ').unwrap_or(rest)))
+}
+
+/// Combines several runs into one record list, matching modules by name. The tree holds
+/// every module seen in any run, placed where it first appeared; a module missing from a
+/// run counts as zero there, so `Mean` averages over all runs.
+pub fn merge_runs(runs: &[Vec<ImportRecord>], strategy: MergeRuns) -> Vec<ImportRecord> {
+    let mut samples: HashMap<&str, Vec<(u64, u64)>> = HashMap::new();
+    for run in runs {
+        for record in run {
+            samples
+                .entry(record.name.as_str())
+                .or_default()
+                .push((record.self_us, record.cumulative_us));
+        }
+    }
+    let mut first_seen: HashMap<&str, &ImportRecord> = HashMap::new();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut roots = Vec::new();
+    for run in runs {
+        for (record, parent) in run.iter().zip(log_parents(run)) {
+            if first_seen.contains_key(record.name.as_str()) {
+                continue;
+            }
+            first_seen.insert(&record.name, record);
+            match parent {
+                Some(parent) => children.entry(&run[parent].name).or_default().push(&record.name),
+                None => roots.push(record.name.as_str()),
+            }
+        }
+    }
+    let combine = |values: &[(u64, u64)], pick: fn(&(u64, u64)) -> u64| -> u64 {
+        let iter = values.iter().map(pick);
+        match strategy {
+            MergeRuns::Sum => iter.sum(),
+            MergeRuns::Mean => iter.sum::<u64>() / runs.len() as u64,
+            MergeRuns::Max => iter.max().unwrap_or(0),
+        }
+    };
+    // Emit in post-order, children in the order they were first seen, like the log itself.
+    let mut merged = Vec::with_capacity(first_seen.len());
+    let mut stack: Vec<(&str, bool)> = roots.iter().rev().map(|&name| (name, false)).collect();
+    while let Some((name, expanded)) = stack.pop() {
+        if !expanded {
+            stack.push((name, true));
+            let kids = children.get(name).map(Vec::as_slice).unwrap_or_default();
+            stack.extend(kids.iter().rev().map(|&child| (child, false)));
+            continue;
+        }
+        let values = &samples[name];
+        merged.push(ImportRecord {
+            name: name.to_string(),
+            self_us: combine(values, |value| value.0),
+            cumulative_us: combine(values, |value| value.1),
+            depth: first_seen[name].depth,
+            synthetic: first_seen[name].synthetic,
+        });
+    }
+    merged
+}
+
+/// Index of each record's parent within a run in log (post-order) order: the first later
+/// record that is less deeply nested.
+fn log_parents(run: &[ImportRecord]) -> Vec<Option<usize>> {
+    let mut parents = vec![None; run.len()];
+    let mut pending: Vec<usize> = Vec::new();
+    for (index, record) in run.iter().enumerate() {
+        while let Some(&child) = pending.last() {
+            if run[child].depth <= record.depth {
+                break;
+            }
+            parents[child] = Some(index);
+            pending.pop();
+        }
+        pending.push(index);
+    }
+    parents
+}
+
+/// Every run's self time for each module, in run order, matched by name as in `merge_runs`.
+/// A module missing from a run gets a zero for it, as `merge_runs` counts it, so every module
+/// has one sample per run.
+pub fn self_time_samples(runs: &[Vec<ImportRecord>]) -> HashMap<String, Vec<u64>> {
+    let mut samples: HashMap<String, Vec<u64>> = HashMap::new();
+    for (run_index, run) in runs.iter().enumerate() {
+        for record in run {
+            let values = samples.entry(record.name.clone()).or_insert_with(|| vec![0; runs.len()]);
+            values[run_index] += record.self_us;
+        }
+    }
+    samples
+}
+
+/// Population standard deviation; zero for fewer than two values.
+pub fn std_dev(values: &[u64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<u64>() as f64 / count;
+    let variance = values
+        .iter()
+        .map(|value| (*value as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    variance.sqrt()
+}
+
+/// Writes `records` back out the way `-X importtime` logs them, header included, so filtered
+/// or merged records can be fed to other tools. Parses back to the same records.
+pub fn format_import_time(records: &[ImportRecord]) -> String {
+    let mut out = String::from("import time: self [us] | cumulative | imported package\n");
+    for record in records {
+        out.push_str(&format!(
+            "import time: {:>9} | {:>10} | {}{}\n",
+            record.self_us,
+            record.cumulative_us,
+            "  ".repeat(record.depth.saturating_sub(1)),
+            record.name
+        ));
+    }
+    out
+}
+
+/// Rejoins `import time:` lines that a narrow terminal wrapped: a line right after a record
+/// that has no `import time:` prefix and no whitespace is taken to be the rest of that
+/// record's module name. Heuristic, so only applied with `--unwrap`.
+pub fn unwrap_continuation_lines(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut after_record = false;
+    for line in text.lines() {
+        let fragment = line.trim();
+        let continues = after_record
+            && !fragment.is_empty()
+            && !fragment.contains(char::is_whitespace)
+            && !fragment.contains("import time:");
+        if continues {
+            // Drop the newline the wrap introduced; the record may continue on further lines.
+            out.pop();
+            out.push_str(fragment);
+            out.push('\n');
+            continue;
+        }
+        after_record = line.contains("import time:") && !line.contains("self [us]");
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Removes ANSI CSI escape sequences (`ESC [ params final`), as left behind by colorizing
+/// wrappers around stderr. Real import-time lines never contain ESC, so this is always safe.
+fn strip_ansi(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' || chars.peek() != Some(&'[') {
+            out.push(ch);
+            continue;
+        }
+        chars.next();
+        // Parameter and intermediate bytes, then one final byte in `@`..=`~`.
+        for next in chars.by_ref() {
+            if ('@'..='~').contains(&next) {
+                break;
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Modules compiled into the interpreter (`sys.builtin_module_names` on CPython) or frozen
+/// into it for bootstrapping. They report near-zero self time and are rarely actionable.
+/// `time` is left out: it is builtin too, but programs often have modules of that name.
+const BUILTIN_MODULES: &[&str] = &[
+    "_abc",
+    "_codecs",
+    "_collections",
+    "_frozen_importlib",
+    "_frozen_importlib_external",
+    "_functools",
+    "_imp",
+    "_io",
+    "_locale",
+    "_operator",
+    "_signal",
+    "_sre",
+    "_stat",
+    "_string",
+    "_symtable",
+    "_thread",
+    "_tokenize",
+    "_tracemalloc",
+    "_typing",
+    "_warnings",
+    "_weakref",
+    "atexit",
+    "builtins",
+    "errno",
+    "faulthandler",
+    "gc",
+    "itertools",
+    "marshal",
+    "posix",
+    "pwd",
+    "sys",
+    "winreg",
+    "zipimport",
+];
+
+pub fn is_builtin_module(name: &str) -> bool {
+    BUILTIN_MODULES.contains(&name)
+}
+
+/// CPython's import machinery as it appears in `-X importtime` logs. Its cost is interpreter
+/// overhead rather than something the program chose to import.
+pub const BOOTSTRAP_MODULES: &[&str] = &[
+    "_frozen_importlib",
+    "_frozen_importlib_external",
+    "importlib._bootstrap",
+    "importlib._bootstrap_external",
+    "<frozen importlib._bootstrap>",
+    "<frozen importlib._bootstrap_external>",
+];
+
+pub fn is_bootstrap_module(name: &str) -> bool {
+    BOOTSTRAP_MODULES.contains(&name)
+}
+
+/// Digit-group separators accepted in the microsecond columns: comma, underscore, space,
+/// and the no-break spaces some locales use.
+const GROUP_SEPARATORS: [char; 5] = [',', '_', ' ', '\u{a0}', '\u{202f}'];
+
+/// Fallback for reduced logs and fixtures without the cumulative column,
+/// `import time: <self> | <module>`. Cumulative starts out as the self time; see
+/// `roll_up_cumulative`.
+fn parse_two_column_line(line: &str) -> Option<ImportRecord> {
+    let stripped = strip_marker(line)?;
+    let (self_part, module_part) = stripped.split_once('|')?;
+    let module_part = module_part.trim_end();
+    let self_us = parse_microseconds(self_part.trim())?;
+    let leading_spaces = module_part.chars().take_while(|c| *c == ' ').count();
+    let name = module_part.trim();
+    // A bare number is a cumulative column with the module missing, not a module name.
+    if name.is_empty() || name.contains('|') || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(ImportRecord {
+        name: name.to_string(),
+        self_us,
+        cumulative_us: self_us,
+        depth: leading_spaces.div_ceil(2),
+        synthetic: false,
+    })
+}
+
+/// Parses a microsecond column such as `1234`, `1,234`, `1_234` or `1 234`. Separators may
+/// only sit between digits, one at a time; signs, decimals and other text are rejected.
+fn parse_microseconds(token: &str) -> Option<u64> {
+    let mut digits = String::with_capacity(token.len());
+    let mut previous_digit = false;
+    for ch in token.chars() {
+        if ch.is_ascii_digit() {
+            digits.push(ch);
+            previous_digit = true;
+        } else if GROUP_SEPARATORS.contains(&ch) && previous_digit {
+            previous_digit = false;
+        } else {
+            return None;
+        }
+    }
+    if !previous_digit {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Longest tag, such as `[stderr] ` from a logger or embedder, allowed before `import time:`.
+const MAX_TAG_LEN: usize = 32;
+
+/// The rest of `line` after its `import time:` marker, which may follow a short tag. Lines
+/// that mention the marker further in are someone else's output.
+fn strip_marker(line: &str) -> Option<&str> {
+    let marker = "import time:";
+    let start = line.find(marker).filter(|start| *start <= MAX_TAG_LEN)?;
+    Some(&line[start + marker.len()..])
+}
+
 fn parse_import_line(line: &str) -> Option<ImportRecord> {
-    let prefix = "import time:";
-    let stripped = line.strip_prefix(prefix)?;
+    let stripped = strip_marker(line)?;
     let mut parts = stripped.split('|').map(|part| part.trim_end());
     let self_part = parts.next()?.trim();
     let cumulative_part = parts.next()?.trim();
@@ -37,19 +563,20 @@ fn parse_import_line(line: &str) -> Option<ImportRecord> {
     if self_part.is_empty() || cumulative_part.is_empty() || module_part.is_empty() {
         return None;
     }
-    let self_us = self_part.parse().ok()?;
-    let cumulative_us = cumulative_part.parse().ok()?;
+    let self_us = parse_microseconds(self_part)?;
+    let cumulative_us = parse_microseconds(cumulative_part)?;
     let leading_spaces = module_part.chars().take_while(|c| *c == ' ').count();
     let name = module_part.trim().to_string();
     if name.is_empty() {
         return None;
     }
-    let depth = (leading_spaces + 1) / 2;
+    let depth = leading_spaces.div_ceil(2);
     Some(ImportRecord {
         name,
         self_us,
         cumulative_us,
         depth,
+        synthetic: false,
     })
 }
 
@@ -67,6 +594,109 @@ mod tests {
         assert_eq!(record.depth, 2);
     }
 
+    #[test]
+    fn wrapped_module_name_is_rejoined() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        8 |          8 |   very.long.namespace.pack\n\
+age.module\n\
+import time:        2 |         10 | very\n\
+\n\
+Hello from the program\n";
+        let unwrapped = unwrap_continuation_lines(log);
+        let records = parse_import_time(&unwrapped).expect("records");
+        assert_eq!(records[0].name, "very.long.namespace.package.module");
+        assert_eq!(records[0].depth, 2);
+        assert_eq!(records[1].name, "very");
+        // Text with spaces or after a blank line is not a name fragment.
+        assert!(unwrapped.ends_with("\n\nHello from the program\n"));
+        assert_eq!(parse_import_time(log).expect("records")[0].name, "very.long.namespace.pack");
+    }
+
+    #[test]
+    fn parse_import_line_allows_a_leading_tag() {
+        let record = parse_import_line("[stderr] import time:       8 |        12 |   pkg.mod")
+            .expect("record");
+        assert_eq!((record.name.as_str(), record.self_us, record.depth), ("pkg.mod", 8, 2));
+        let log = "\
+[stderr] import time: self [us] | cumulative | imported package\n\
+[stderr] import time:        4 |          4 |   json.decoder\n\
+[stderr] a warning that happens to mention import time: 1 | 2 | x\n\
+[stderr] import time:        6 |         10 | json\n";
+        let records = parse_import_time(log).expect("records");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].cumulative_us, 10);
+        let far = format!("{}import time:        8 |        12 | pkg", " ".repeat(40));
+        assert!(parse_import_line(&far).is_none());
+    }
+
+    #[test]
+    fn parse_import_line_accepts_grouped_digits() {
+        let record = parse_import_line("import time:     1,234 |  1_234_567 | pkg").expect("record");
+        assert_eq!(record.self_us, 1234);
+        assert_eq!(record.cumulative_us, 1_234_567);
+        let record = parse_import_line("import time:     1 234 |   12\u{a0}345 | pkg").expect("record");
+        assert_eq!(record.self_us, 1234);
+        assert_eq!(record.cumulative_us, 12345);
+        for bad in ["1,,234", ",123", "123,", "1.5", "-3", "12ab"] {
+            let line = format!("import time: {} | 5 | pkg", bad);
+            assert!(parse_import_line(&line).is_none(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parse_import_time_accepts_two_column_lines() {
+        let log = "\
+import time: self [us] | imported package\n\
+import time:       10 |   a.b\n\
+import time:        4 |     a.c.d\n\
+import time:        6 |   a.c\n\
+import time:       20 | a\n\
+import time:        7 | b\n";
+        assert!(parse_import_time(log).is_err());
+        let (records, _) = parse_import_time_two_column(log, false).expect("records");
+        let parsed: Vec<(&str, u64, u64, usize)> = records
+            .iter()
+            .map(|record| (record.name.as_str(), record.self_us, record.cumulative_us, record.depth))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("a.b", 10, 10, 2),
+                ("a.c.d", 4, 4, 3),
+                ("a.c", 6, 10, 2),
+                ("a", 20, 40, 1),
+                ("b", 7, 7, 1),
+            ]
+        );
+        assert!(parse_two_column_line("import time:       10 |         10 |").is_none());
+        assert!(parse_two_column_line("import time:       10 |         10").is_none());
+    }
+
+    #[test]
+    fn split_by_pid_separates_tagged_processes() {
+        let log = "\
+[pid  101] import time:       10 |         10 | a\n\
+[pid 202] import time:        5 |          5 | b\n\
+import time:        1 |          1 | c\n\
+some program output\n\
+[pid 101] import time:        3 |          3 | d\n";
+        let processes = split_by_pid(log).expect("tagged");
+        let pids: Vec<Option<u32>> = processes.iter().map(|(pid, _)| *pid).collect();
+        assert_eq!(pids, vec![Some(101), Some(202), None]);
+        let first = parse_import_time(&processes[0].1).expect("records");
+        let names: Vec<&str> = first.iter().map(|record| record.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "d"]);
+        assert!(split_by_pid("import time:        1 |          1 | c\n").is_none());
+
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+[pid 7] import time:        5 |          5 | b\n";
+        let processes = split_by_pid(log).expect("tagged");
+        let pids: Vec<Option<u32>> = processes.iter().map(|(pid, _)| *pid).collect();
+        assert_eq!(pids, vec![Some(7)]);
+    }
+
     #[test]
     fn parse_import_time_skips_header() {
         let log = "\
@@ -80,4 +710,137 @@ import time:        3 |          3 |   b.c\n";
         assert_eq!(records[1].name, "b");
         assert_eq!(records[2].name, "b.c");
     }
+
+    const TWO_RUNS: &str = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 |   a.b\n\
+import time:       20 |         30 | a\n\
+import time: self [us] | cumulative | imported package\n\
+import time:       30 |         30 |   a.b\n\
+import time:       40 |         70 | a\n";
+
+    #[test]
+    fn parse_import_runs_splits_on_headers() {
+        let runs = parse_import_runs(TWO_RUNS).expect("runs");
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[1][0].self_us, 30);
+    }
+
+    #[test]
+    fn merge_runs_sum() {
+        let runs = parse_import_runs(TWO_RUNS).expect("runs");
+        let merged = merge_runs(&runs, MergeRuns::Sum);
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].self_us, merged[0].cumulative_us), (40, 40));
+        assert_eq!((merged[1].self_us, merged[1].cumulative_us), (60, 100));
+        assert_eq!(merged[0].depth, 2);
+    }
+
+    #[test]
+    fn merge_runs_mean() {
+        let runs = parse_import_runs(TWO_RUNS).expect("runs");
+        let merged = merge_runs(&runs, MergeRuns::Mean);
+        assert_eq!((merged[0].self_us, merged[0].cumulative_us), (20, 20));
+        assert_eq!((merged[1].self_us, merged[1].cumulative_us), (30, 50));
+    }
+
+    #[test]
+    fn merge_runs_max() {
+        let runs = parse_import_runs(TWO_RUNS).expect("runs");
+        let merged = merge_runs(&runs, MergeRuns::Max);
+        assert_eq!((merged[0].self_us, merged[0].cumulative_us), (30, 30));
+        assert_eq!((merged[1].self_us, merged[1].cumulative_us), (40, 70));
+    }
+
+    #[test]
+    fn merge_runs_keeps_modules_missing_from_a_run() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 |   a.b\n\
+import time:       20 |         30 | a\n\
+import time: self [us] | cumulative | imported package\n\
+import time:       30 |         30 |   a.b\n\
+import time:        6 |          6 |     a.c.d\n\
+import time:        4 |         10 |   a.c\n\
+import time:       40 |         80 | a\n\
+import time:        8 |          8 | z\n";
+        let runs = parse_import_runs(log).expect("runs");
+        let merged = merge_runs(&runs, MergeRuns::Mean);
+        let summary: Vec<(&str, u64, usize)> = merged
+            .iter()
+            .map(|record| (record.name.as_str(), record.self_us, record.depth))
+            .collect();
+        assert_eq!(
+            summary,
+            [("a.b", 20, 2), ("a.c.d", 3, 3), ("a.c", 2, 2), ("a", 30, 1), ("z", 4, 1)]
+        );
+        let samples = self_time_samples(&runs);
+        assert_eq!(samples["a.c"], [0, 4]);
+        assert_eq!(samples["a"], [20, 40]);
+    }
+
+    #[test]
+    fn detects_builtin_modules() {
+        assert!(is_builtin_module("_imp"));
+        assert!(is_builtin_module("zipimport"));
+        assert!(!is_builtin_module("json"));
+        assert!(!is_builtin_module("time"));
+        assert!(!is_builtin_module("timeit"));
+    }
+
+    #[test]
+    fn json_adapter_round_trips_records() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |          8 | b\n";
+        let records = parse_import_time(log).expect("records");
+        let json = serde_json::to_string(&records).expect("json");
+        let parsed = parse_import_json(&json).expect("parsed");
+        let summary = |records: &[ImportRecord]| -> Vec<(String, u64, u64, usize)> {
+            records
+                .iter()
+                .map(|r| (r.name.clone(), r.self_us, r.cumulative_us, r.depth))
+                .collect()
+        };
+        assert_eq!(summary(&parsed), summary(&records));
+        assert!(parse_import_json("[]").is_err());
+        assert!(parse_import_json(r#"[{"name":"a","self_us":1,"cumulative_us":1,"depth":0}]"#)
+            .is_err());
+    }
+
+    #[test]
+    fn lenient_parse_skips_garbage_lines() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+DEBUG connecting to cache\n\
+import time: \x1b[0m garbled | | \n\
+import time:        5 |          8 | b\n\
+import time: 12 | oops\n";
+        assert!(parse_import_time(log).is_err());
+        let (records, warnings) = parse_import_time_lenient(log).expect("records");
+        let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+        assert_eq!(names, vec!["b.c", "b"]);
+        assert_eq!(
+            warnings,
+            vec![
+                "skipped malformed import time on line 4".to_string(),
+                "skipped malformed import time on line 6".to_string(),
+            ]
+        );
+        assert!(parse_import_time_lenient("import time: junk\n").is_err());
+    }
+
+    #[test]
+    fn ansi_escapes_are_stripped_before_parsing() {
+        let log = "\x1b[2mimport time: self [us] | cumulative | imported package\x1b[0m\n\
+                   \x1b[33mimport time:\x1b[0m      12 | \x1b[1m        34\x1b[0m | \x1b[32mjson\x1b[0m\n";
+        let records = parse_import_time(log).expect("records");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].name, "json");
+        assert_eq!(records[0].self_us, 12);
+        assert_eq!(records[0].cumulative_us, 34);
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
 }
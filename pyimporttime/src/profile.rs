@@ -0,0 +1,119 @@
+use anyhow::Result;
+
+use crate::layout::LayoutConfig;
+use crate::parser::{parse_import_time, GraphReport, ImportRecord};
+use crate::render::{
+    build_graph_html, build_graph_json, self_time_by_module, GraphJson, RenderOptions,
+};
+use crate::tree::{
+    build_tree_from_parsed, build_tree_from_report, critical_path, PathStep, Tree, TreeOptions,
+};
+
+/// An import-time log built into a tree, together with what is known about where it came
+/// from. The single entry point for code that wants reports without the command line.
+///
+/// ```
+/// use pyimporttime::{ImportProfile, LayoutConfig};
+///
+/// let log = "\
+/// import time: self [us] | cumulative | imported package
+/// import time:      300 |        300 |   json.decoder
+/// import time:      200 |        500 | json
+/// import time:      100 |        100 |   app.util
+/// import time:      400 |        500 | app
+/// ";
+/// let mut profile = ImportProfile::from_log(log)?;
+/// profile.interpreter = Some("CPython 3.12.3".to_string());
+/// assert_eq!(profile.record_count(), 4);
+/// assert_eq!(profile.total_ms(), 1.0);
+///
+/// let html = profile.to_html(&LayoutConfig::default())?;
+/// assert!(html.contains("CPython 3.12.3"));
+/// assert!(html.contains("json.decoder"));
+///
+/// let path: Vec<String> = profile.critical_path().into_iter().map(|step| step.name).collect();
+/// assert_eq!(path, ["json", "json.decoder"]);
+/// assert_eq!(profile.aggregate_by_module()[0], ("app".to_string(), 400));
+///
+/// let json = serde_json::to_value(profile.to_json(&LayoutConfig::default())?)?;
+/// assert_eq!(json["records"].as_array().map(Vec::len), Some(4));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ImportProfile {
+    tree: Tree,
+    /// Interpreter that wrote the log, e.g. `CPython 3.12.3`, when known.
+    pub interpreter: Option<String>,
+    record_count: usize,
+}
+
+impl ImportProfile {
+    /// Builds a profile from a `-X importtime` log with the default tree options.
+    pub fn from_log(text: &str) -> Result<Self> {
+        Self::from_records(parse_import_time(text)?, &TreeOptions::default())
+    }
+
+    /// Builds a profile from records in log order, see `build_tree_from_parsed`.
+    pub fn from_records(records: Vec<ImportRecord>, options: &TreeOptions) -> Result<Self> {
+        let record_count = records.len();
+        Ok(Self {
+            tree: build_tree_from_parsed(records, options)?,
+            interpreter: None,
+            record_count,
+        })
+    }
+
+    /// Rebuilds the profile stored in a `graph --format json` report, see `parse_graph_json`.
+    pub fn from_report(report: GraphReport) -> Result<Self> {
+        let record_count = report.records.len();
+        Ok(Self {
+            tree: build_tree_from_report(report)?,
+            interpreter: None,
+            record_count,
+        })
+    }
+
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    /// Records read from the log, before any were filtered out of the tree.
+    pub fn record_count(&self) -> usize {
+        self.record_count
+    }
+
+    pub fn total_ms(&self) -> f64 {
+        self.tree.total_us() as f64 / 1000.0
+    }
+
+    /// Time spent in the modules themselves, excluding the root's own time.
+    pub fn self_ms(&self) -> f64 {
+        self.tree.total_self_us() as f64 / 1000.0
+    }
+
+    /// The HTML report with default render options, titled with the interpreter if known.
+    pub fn to_html(&self, config: &LayoutConfig) -> Result<String> {
+        build_graph_html(&self.tree, config, &self.render_options())
+    }
+
+    /// The `graph --format json` report, records included.
+    pub fn to_json(&self, config: &LayoutConfig) -> Result<GraphJson> {
+        build_graph_json(&self.tree, config, &self.render_options())
+    }
+
+    pub fn critical_path(&self) -> Vec<PathStep> {
+        critical_path(&self.tree)
+    }
+
+    /// Self time in microseconds summed per module name, heaviest first.
+    pub fn aggregate_by_module(&self) -> Vec<(String, u64)> {
+        self_time_by_module(&self.tree)
+    }
+
+    fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            interpreter: self.interpreter.clone(),
+            ..RenderOptions::default()
+        }
+    }
+}
@@ -1,27 +1,73 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
 use anyhow::Result;
-use serde::Serialize;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
-use crate::layout::{layout_tree, LayoutConfig, Rect};
-use crate::tree::build_tree;
+use crate::layout::{color_for_name, layout_tree, LayoutConfig, Rect};
+use crate::parser::ImportRecord;
+use crate::tree::{
+    critical_path, module_paths, top_level_imports, tree_records, NodeKind, Tree, TreeOptions,
+};
+use crate::util::content_hash;
 
 #[derive(Serialize)]
 pub struct GraphJson {
     meta: GraphMeta,
     rects: Vec<GraphRect>,
+    /// Root to leaf, see `critical_path`.
+    critical_path: Vec<PathStepJson>,
+    /// The tree as import records, see `tree_records`, for `relayout` to build it again.
+    /// Left out of the data embedded in HTML reports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    records: Option<Vec<ImportRecord>>,
+    /// The root's own time, which no record holds, and the options the records were
+    /// filtered with; written along with `records`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root_self_us: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tree_options: Option<TreeOptions>,
+}
+
+#[derive(Serialize)]
+struct PathStepJson {
+    name: String,
+    self_us: u64,
+    cumulative_us: u64,
+    depth: usize,
 }
 
 #[derive(Serialize)]
 struct GraphMeta {
     title: String,
+    /// `absolute` for pixel coordinates, `normalized` for fractions of the canvas.
+    coordinate_space: &'static str,
     total_ms: f64,
+    /// Sum of all self times; what remains of `total_ms` is `overhead_ms`.
+    total_self_ms: f64,
+    /// `total_ms - total_self_ms`: cumulative time not accounted for by any module's self time.
+    overhead_ms: f64,
     width: f64,
     height: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interpreter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<CommandLine>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    over_budget: Option<Vec<String>>,
+    /// Hash of the module -> cumulative time mapping, see `fingerprint`.
+    fingerprint: String,
 }
 
 #[derive(Serialize)]
 struct GraphRect {
     label: String,
+    us: u64,
     ms: f64,
+    display: String,
     x: f64,
     y: f64,
     w: f64,
@@ -29,86 +75,1204 @@ struct GraphRect {
     color: String,
 }
 
-pub fn build_graph_json(text: &str, config: &LayoutConfig) -> Result<GraphJson> {
-    let tree = build_tree(text)?;
-    let rects = layout_tree(&tree, config);
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeUnit {
+    /// µs below 1 ms, ms below 1 s, s above.
+    Auto,
+    Us,
+    #[default]
+    Ms,
+    S,
+}
+
+/// Order rects are written to the SVG, which is also their paint order and DOM order.
+/// Anything that walks the DOM (scripts, hover targets) sees rects in this order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaintOrder {
+    /// Layout order: every parent before its children.
+    #[default]
+    Tree,
+    /// Largest area first, so small rects are never painted over. Parents still precede
+    /// their children because a child is never larger than its parent.
+    Area,
+}
+
+/// What `run` executed to produce the log, recorded so an archived report shows how it was
+/// made.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandLine {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: String,
+}
+
+impl CommandLine {
+    /// One-line form for the toolbar that pastes back into a POSIX shell.
+    fn display(&self) -> String {
+        let mut line = shell_quote(&self.program);
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&shell_quote(arg));
+        }
+        line
+    }
+}
+
+/// `word` as is if the shell would read it unchanged, otherwise in single quotes, with any
+/// single quote inside written as `'\''`.
+fn shell_quote(word: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Report details that don't come from the import log itself.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub interpreter: Option<String>,
+    /// The command `run` profiled; shown in the JSON meta and under the toolbar.
+    pub command: Option<CommandLine>,
+    pub time_unit: TimeUnit,
+    pub paint_order: PaintOrder,
+    /// Decimal places kept for JSON millisecond and geometry fields; `None` keeps full
+    /// precision. Raw microsecond fields are never rounded.
+    pub precision: Option<u32>,
+    /// Modules whose cumulative time exceeds this are outlined and listed as violators.
+    pub budget_ms: Option<f64>,
+    /// Page and canvas background; `None` uses `DEFAULT_BACKGROUND`.
+    pub background: Option<String>,
+    /// Raw import-time log to include in a collapsed section of the HTML report.
+    pub source: Option<String>,
+    /// Number each module rect with its line position in the log.
+    pub show_order: bool,
+    /// Template for rect labels; `None` uses `DEFAULT_LABEL_FORMAT`. See `format_label`.
+    pub label_format: Option<String>,
+    /// Append the number of modules imported beneath each parent to its label.
+    pub show_count: bool,
+    /// Prefix leaf module labels with their rank by cumulative time, e.g. `#1 numpy: 210 ms`.
+    pub show_rank: bool,
+    /// Append a bar chart of this many modules with the most self time below the treemap.
+    pub bars_top: Option<usize>,
+    /// URL template for each module rect, with `{module}` replaced by the module name after
+    /// swapping dots for `link_separator`. See `module_link`.
+    pub link_template: Option<String>,
+    /// Replaces the dots of `{module}` in links; `None` uses `DEFAULT_LINK_SEPARATOR`.
+    pub link_separator: Option<String>,
+    /// Modules to outline in the static output, matched by full name or as a dotted prefix.
+    pub highlight: Vec<String>,
+    /// Emit JSON rect coordinates as fractions (0.0-1.0) of the canvas instead of pixels.
+    pub normalized: bool,
+    /// Turn labels 90° in rects much taller than they are wide, see `ROTATE_LABEL_RATIO`.
+    pub rotate_labels: bool,
+    /// Skip self rects and shade the bottom of each module rect by its share of self time.
+    pub group_self: bool,
+    /// Only label this many module rects, the largest first; the size threshold still applies.
+    pub max_labels: Option<usize>,
+    /// Star the most expensive module among each parent's children, see `hotspot_rects`.
+    pub mark_hotspots: bool,
+    /// Title for folded-stack and speedscope exports; `None` uses `DEFAULT_TITLE`.
+    pub flamegraph_title: Option<String>,
+    /// Add a scaled-down copy of the treemap in a fixed corner of the HTML report, with a box
+    /// tracking the part that is scrolled into view.
+    pub minimap: bool,
+    /// Top-level imports shown by `build_sparkline`; `None` uses `DEFAULT_TOP`.
+    pub sparkline_top: Option<usize>,
+    /// Module to mark as the application's entry point; `None` looks for `ENTRY_MODULE`. See
+    /// `entry_rect`.
+    pub entry: Option<String>,
+    /// Append a ranked table of every module below the HTML treemap; clicking a row scrolls
+    /// to its rect. See `render_module_table`.
+    pub table: bool,
+    /// Draw rects as unfilled outlines stroked by depth, see `OUTLINE_COLORS`, with neutral
+    /// labels: a wireframe of the same layout.
+    pub outline: bool,
+}
+
+pub const DEFAULT_BACKGROUND: &str = "#333";
+pub const DEFAULT_LABEL_FORMAT: &str = "{name}: {time}";
+pub const DEFAULT_LINK_SEPARATOR: &str = "/";
+pub const DEFAULT_TOP: usize = 20;
+pub const DEFAULT_TITLE: &str = "Python import time";
+/// Stack for the root's own time, i.e. bootstrap imports folded away by `hide_bootstrap`.
+const BOOTSTRAP_FRAME: &str = "(bootstrap)";
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const HIGHLIGHT_COLOR: &str = "#ffeb3b";
+/// Entry module looked for when `--entry` is not given.
+const ENTRY_MODULE: &str = "__main__";
+const ENTRY_COLOR: &str = "#00e5ff";
+const SELF_OVERLAY_OPACITY: f64 = 0.3;
+const HOTSPOT_COLOR: &str = "#ff9800";
+/// How many times taller than wide a rect must be for `rotate_labels` to turn its label.
+const ROTATE_LABEL_RATIO: f64 = 2.0;
+/// Outline strokes by depth in `outline` mode, cycling for deeper levels.
+const OUTLINE_COLORS: [&str; 6] =
+    ["#4fc3f7", "#81c784", "#ffb74d", "#e57373", "#ba68c8", "#fff176"];
+const OUTLINE_LABEL_COLOR: &str = "#ccc";
+
+impl RenderOptions {
+    fn background(&self) -> &str {
+        self.background.as_deref().unwrap_or(DEFAULT_BACKGROUND)
+    }
+
+    /// `numpy` highlights `numpy` and `numpy.linalg`, but not `numpyx`.
+    fn is_highlighted(&self, module: &str) -> bool {
+        self.highlight.iter().any(|name| {
+            module
+                .strip_prefix(name.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    }
+
+    fn link_for(&self, module: &str) -> Option<String> {
+        let template = self.link_template.as_deref()?;
+        let separator = self.link_separator.as_deref().unwrap_or(DEFAULT_LINK_SEPARATOR);
+        Some(module_link(template, module, separator))
+    }
+}
+
+/// Fills `{module}` in `template` with `module`, dots replaced by `separator`, so
+/// `https://example.com/src/{module}.py` links `a.b` to `https://example.com/src/a/b.py`.
+pub fn module_link(template: &str, module: &str, separator: &str) -> String {
+    template.replace("{module}", &module.replace('.', separator))
+}
+
+/// CSS named colors, plus `transparent`, sorted for binary search.
+const CSS_NAMED_COLORS: &[&str] = &[
+    "aliceblue", "antiquewhite", "aqua", "aquamarine", "azure", "beige", "bisque", "black",
+    "blanchedalmond", "blue", "blueviolet", "brown", "burlywood", "cadetblue", "chartreuse",
+    "chocolate", "coral", "cornflowerblue", "cornsilk", "crimson", "cyan", "darkblue", "darkcyan",
+    "darkgoldenrod", "darkgray", "darkgreen", "darkgrey", "darkkhaki", "darkmagenta",
+    "darkolivegreen", "darkorange", "darkorchid", "darkred", "darksalmon", "darkseagreen",
+    "darkslateblue", "darkslategray", "darkslategrey", "darkturquoise", "darkviolet", "deeppink",
+    "deepskyblue", "dimgray", "dimgrey", "dodgerblue", "firebrick", "floralwhite", "forestgreen",
+    "fuchsia", "gainsboro", "ghostwhite", "gold", "goldenrod", "gray", "green", "greenyellow",
+    "grey", "honeydew", "hotpink", "indianred", "indigo", "ivory", "khaki", "lavender",
+    "lavenderblush", "lawngreen", "lemonchiffon", "lightblue", "lightcoral", "lightcyan",
+    "lightgoldenrodyellow", "lightgray", "lightgreen", "lightgrey", "lightpink", "lightsalmon",
+    "lightseagreen", "lightskyblue", "lightslategray", "lightslategrey", "lightsteelblue",
+    "lightyellow", "lime", "limegreen", "linen", "magenta", "maroon", "mediumaquamarine",
+    "mediumblue", "mediumorchid", "mediumpurple", "mediumseagreen", "mediumslateblue",
+    "mediumspringgreen", "mediumturquoise", "mediumvioletred", "midnightblue", "mintcream",
+    "mistyrose", "moccasin", "navajowhite", "navy", "oldlace", "olive", "olivedrab", "orange",
+    "orangered", "orchid", "palegoldenrod", "palegreen", "paleturquoise", "palevioletred",
+    "papayawhip", "peachpuff", "peru", "pink", "plum", "powderblue", "purple", "rebeccapurple",
+    "red", "rosybrown", "royalblue", "saddlebrown", "salmon", "sandybrown", "seagreen", "seashell",
+    "sienna", "silver", "skyblue", "slateblue", "slategray", "slategrey", "snow", "springgreen",
+    "steelblue", "tan", "teal", "thistle", "tomato", "transparent", "turquoise", "violet", "wheat",
+    "white", "whitesmoke", "yellow", "yellowgreen",
+];
+
+/// Accepts `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, or a named CSS color such as `navy`.
+/// Anything else is rejected so the value can be written into CSS and SVG unescaped.
+pub fn parse_color(value: &str) -> Result<String, String> {
+    let valid = match value.strip_prefix('#') {
+        Some(hex) => {
+            matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => CSS_NAMED_COLORS.binary_search(&value.to_ascii_lowercase().as_str()).is_ok(),
+    };
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(format!("expected a hex or named CSS color, got `{}`", value))
+    }
+}
+
+pub fn build_graph_json(
+    tree: &Tree,
+    config: &LayoutConfig,
+    options: &RenderOptions,
+) -> Result<GraphJson> {
+    let rects = layout_tree(tree, config);
+    let mut graph = graph_json(tree, &rects, config, options);
+    graph.records = Some(tree_records(tree));
+    graph.root_self_us = Some(tree.self_us(tree.root));
+    graph.tree_options = Some(tree.options.clone());
+    Ok(graph)
+}
+
+/// A hash of every module's cumulative time, sorted by name so it ignores import order,
+/// layout and colors. Equal fingerprints mean the import costs did not change.
+fn fingerprint(tree: &Tree) -> String {
+    let mut modules: Vec<String> = module_paths(tree)
+        .into_iter()
+        .map(|module| format!("{}\t{}", module.name, module.cumulative_us))
+        .collect();
+    modules.sort();
+    let parts: Vec<&str> = modules.iter().map(String::as_str).collect();
+    content_hash(&parts)
+}
+
+fn graph_json(
+    tree: &Tree,
+    rects: &[Rect],
+    config: &LayoutConfig,
+    options: &RenderOptions,
+) -> GraphJson {
+    let round = |value: f64| round_to(value, options.precision);
     let total_ms = tree.total_us() as f64 / 1000.0;
-    Ok(GraphJson {
+    let total_self_us = tree.total_self_us();
+    let overhead_ms = tree.total_us().saturating_sub(total_self_us) as f64 / 1000.0;
+    let (scale_x, scale_y, coordinate_space) = if options.normalized {
+        (1.0 / config.width, 1.0 / config.height, "normalized")
+    } else {
+        (1.0, 1.0, "absolute")
+    };
+    GraphJson {
         meta: GraphMeta {
             title: "Python import time".to_string(),
-            total_ms,
+            coordinate_space,
+            total_ms: round(total_ms),
+            total_self_ms: round(total_self_us as f64 / 1000.0),
+            overhead_ms: round(overhead_ms),
             width: config.width,
             height: config.height,
+            interpreter: options.interpreter.clone(),
+            command: options.command.clone(),
+            budget_ms: options.budget_ms,
+            over_budget: options.budget_ms.map(|budget| over_budget(rects, budget)),
+            fingerprint: fingerprint(tree),
         },
         rects: rects
-            .into_iter()
+            .iter()
             .map(|rect| GraphRect {
-                label: rect.name,
-                ms: rect.display_ms,
-                x: rect.x,
-                y: rect.y,
-                w: rect.w,
-                h: rect.h,
-                color: rect.color,
+                label: rect.name.clone(),
+                us: rect.us,
+                ms: round(rect.display_ms),
+                display: format_duration(rect.us, options.time_unit),
+                x: round(rect.x * scale_x),
+                y: round(rect.y * scale_y),
+                w: round(rect.w * scale_x),
+                h: round(rect.h * scale_y),
+                color: rect.color.clone(),
+            })
+            .collect(),
+        critical_path: critical_path(tree)
+            .into_iter()
+            .map(|step| PathStepJson {
+                name: step.name,
+                self_us: step.self_us,
+                cumulative_us: step.cumulative_us,
+                depth: step.depth,
             })
             .collect(),
-    })
+        records: None,
+        root_self_us: None,
+        tree_options: None,
+    }
 }
 
-pub fn build_graph_html(text: &str, config: &LayoutConfig) -> Result<String> {
-    let tree = build_tree(text)?;
-    let rects = layout_tree(&tree, config);
-    let total_ms = tree.total_us() as f64 / 1000.0;
-    let svg = render_svg(&rects, config);
-    let html = format!(
+/// Shows a parent's largest children next to the cursor. The SVG `<title>` elements stay as the
+/// fallback tooltip when scripts are disabled.
+const HOVER_SCRIPT: &str = "(function(){\
+var tip=document.getElementById('tooltip');\
+var svg=document.getElementById('import-graph');\
+svg.addEventListener('mousemove',function(e){\
+var g=e.target.closest('g[data-children]');\
+if(!g){tip.style.display='none';return;}\
+var title=g.querySelector('title');\
+tip.textContent='';\
+var head=document.createElement('div');\
+head.className='tip-head';\
+head.textContent=title?title.textContent:'';\
+tip.appendChild(head);\
+JSON.parse(g.getAttribute('data-children')).forEach(function(child){\
+var row=document.createElement('div');\
+row.textContent=child[0]+': '+child[1];\
+tip.appendChild(row);\
+});\
+tip.style.left=(e.pageX+12)+'px';\
+tip.style.top=(e.pageY+12)+'px';\
+tip.style.display='block';\
+});\
+svg.addEventListener('mouseleave',function(){tip.style.display='none';});\
+})();";
+
+pub fn build_graph_html(
+    tree: &Tree,
+    config: &LayoutConfig,
+    options: &RenderOptions,
+) -> Result<String> {
+    let mut html = Vec::new();
+    write_graph_html(&mut html, tree, config, options)?;
+    Ok(String::from_utf8(html)?)
+}
+
+/// Streams the HTML report to `out`, rect by rect, instead of assembling it in memory; for
+/// large logs the report runs to tens of megabytes.
+pub fn write_graph_html<W: Write + ?Sized>(
+    out: &mut W,
+    tree: &Tree,
+    config: &LayoutConfig,
+    options: &RenderOptions,
+) -> Result<()> {
+    let rects = layout_tree(tree, config);
+    let total = format_duration(tree.total_us(), options.time_unit);
+    let interpreter = options
+        .interpreter
+        .as_deref()
+        .map(|version| format!(" - {}", escape_xml(version)))
+        .unwrap_or_default();
+    let entry = entry_rect(&rects, options)
+        .map(|index| {
+            let rect = &rects[index];
+            format!(
+                " - entry {}: {} ({:.1}% of total)",
+                escape_xml(&rect.name),
+                escape_xml(&format_duration(rect.us, options.time_unit)),
+                rect.pct
+            )
+        })
+        .unwrap_or_default();
+    let budget = options
+        .budget_ms
+        .map(|budget| {
+            let violators = over_budget(&rects, budget);
+            if violators.is_empty() {
+                format!(" - all modules within {} ms budget", budget)
+            } else {
+                format!(
+                    " - {} over {} ms budget: {}",
+                    violators.len(),
+                    budget,
+                    escape_xml(&violators.join(", "))
+                )
+            }
+        })
+        .unwrap_or_default();
+    let source = options
+        .source
+        .as_deref()
+        .map(|text| {
+            format!(
+                "<details id=\"import-source\"><summary>Import time log</summary><pre>{}</pre></details>",
+                escape_xml(text)
+            )
+        })
+        .unwrap_or_default();
+    let bars = options
+        .bars_top
+        .map(|top| render_self_bars(tree, top, config, options.time_unit))
+        .unwrap_or_default();
+    let command = options
+        .command
+        .as_ref()
+        .map(|command| {
+            format!(
+                "<div id=\"command-line\">$ {} <span class=\"cwd\">(in {})</span></div>",
+                escape_xml(&command.display()),
+                escape_xml(&command.cwd)
+            )
+        })
+        .unwrap_or_default();
+    write!(
+        out,
         "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"UTF-8\"><title>Python import time</title><style>\
-        body{{margin:0;padding:0;background:#333;color:#eee;font-family:sans-serif;}}\
+        body{{margin:0;padding:0;background:{background};color:#eee;font-family:sans-serif;}}\
         #toolbar{{height:36px;line-height:36px;background:#444;padding:0 12px;font-size:14px;}}\
-        #graph-wrap{{overflow:auto;}}\
+        #command-line{{background:#3a3a3a;padding:2px 12px;font:12px monospace;}}\
+        #command-line .cwd{{color:#aaa;}}\
+        #graph-wrap{{overflow:auto;background:{background};}}\
+        #tooltip{{position:absolute;display:none;pointer-events:none;background:#222;\
+        border:1px solid #888;padding:4px 8px;font-size:12px;}}\
+        #tooltip .tip-head{{font-weight:bold;}}\
+        #self-bars h2{{font-size:14px;font-weight:normal;margin:12px;}}\
+        #minimap{{position:fixed;right:12px;bottom:12px;border:1px solid #888;cursor:pointer;\
+        line-height:0;background:{background};}}\
+        #module-table h2{{font-size:14px;font-weight:normal;margin:12px;}}\
+        #module-table .table-scroll{{max-height:400px;overflow:auto;margin:0 12px 12px;}}\
+        #module-table table{{border-collapse:collapse;font-size:12px;}}\
+        #module-table td,#module-table th{{padding:2px 8px;text-align:left;}}\
+        #module-table tbody tr{{cursor:pointer;}}\
+        #module-table tbody tr:hover{{background:#555;}}\
+        #module-table .swatch{{display:inline-block;width:10px;height:10px;margin-right:6px;}}\
+        g.table-target>rect{{stroke:{target};stroke-width:4;}}\
         </style></head><body>\
-        <div id=\"toolbar\">Python import time - total {:.3} ms</div>\
-        <div id=\"graph-wrap\">{}</div></body></html>",
-        total_ms, svg
+        <div id=\"toolbar\">Python import time - total {}{}{}{}</div>{}\
+        <div id=\"graph-wrap\">",
+        escape_xml(&total),
+        interpreter,
+        entry,
+        budget,
+        command,
+        background = options.background(),
+        target = HIGHLIGHT_COLOR
+    )?;
+    write_svg(out, &rects, config, options, &total)?;
+    let minimap = if options.minimap {
+        render_minimap(&rects, config)
+    } else {
+        String::new()
+    };
+    let table = if options.table {
+        render_module_table(&rects, options.time_unit)
+    } else {
+        String::new()
+    };
+    write!(
+        out,
+        "</div><div id=\"tooltip\"></div>{}{}{}{}\
+        <script type=\"application/json\" id=\"pyimporttime-data\">",
+        minimap, bars, table, source
+    )?;
+    let data = graph_json(tree, &rects, config, options);
+    serde_json::to_writer(ScriptJsonWriter(&mut *out), &data)?;
+    write!(out, "</script><script>{}</script>", HOVER_SCRIPT)?;
+    if options.minimap {
+        write!(out, "<script>{}</script>", MINIMAP_SCRIPT)?;
+    }
+    if options.table {
+        write!(out, "<script>{}</script>", TABLE_SCRIPT)?;
+    }
+    write!(out, "</body></html>")?;
+    Ok(())
+}
+
+const MINIMAP_WIDTH: f64 = 200.0;
+
+/// Keeps `#minimap-view` on the part of the treemap in view, which scrolls horizontally
+/// inside `#graph-wrap` and vertically with the page. Clicking the minimap centers the view
+/// on that spot.
+const MINIMAP_SCRIPT: &str = "(function(){\
+var wrap=document.getElementById('graph-wrap');\
+var map=document.getElementById('minimap');\
+var view=document.getElementById('minimap-view');\
+var canvas=map.querySelector('svg').viewBox.baseVal;\
+function update(){\
+var box=wrap.getBoundingClientRect();\
+var top=Math.max(0,-box.top);\
+var bottom=Math.min(box.height,window.innerHeight-box.top);\
+view.setAttribute('x',wrap.scrollLeft);\
+view.setAttribute('y',wrap.scrollTop+top);\
+view.setAttribute('width',wrap.clientWidth);\
+view.setAttribute('height',Math.max(0,bottom-top));\
+}\
+map.addEventListener('click',function(e){\
+var box=map.getBoundingClientRect();\
+var x=(e.clientX-box.left)/box.width*canvas.width;\
+var y=(e.clientY-box.top)/box.height*canvas.height;\
+wrap.scrollLeft=x-wrap.clientWidth/2;\
+window.scrollTo(window.scrollX,wrap.offsetTop+y-window.innerHeight/2);\
+update();\
+});\
+wrap.addEventListener('scroll',update);\
+window.addEventListener('scroll',update);\
+window.addEventListener('resize',update);\
+update();\
+})();";
+
+/// The treemap at `MINIMAP_WIDTH` pixels wide, in canvas coordinates so the script can place
+/// the view box without scaling. Rects under a pixel at that size are left out.
+fn render_minimap(rects: &[Rect], config: &LayoutConfig) -> String {
+    let scale = MINIMAP_WIDTH / config.width;
+    let mut out = format!(
+        "<div id=\"minimap\"><svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{:.0}\" \
+        viewBox=\"0 0 {} {}\" preserveAspectRatio=\"none\">",
+        MINIMAP_WIDTH,
+        config.height * scale,
+        config.width,
+        config.height
+    );
+    for rect in rects.iter().filter(|rect| rect.w * scale >= 1.0 && rect.h * scale >= 1.0) {
+        out.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+            rect.x, rect.y, rect.w, rect.h, rect.color
+        ));
+    }
+    out.push_str(
+        "<rect id=\"minimap-view\" fill=\"none\" stroke=\"#fff\" stroke-width=\"2\" \
+        vector-effect=\"non-scaling-stroke\"/></svg></div>",
+    );
+    out
+}
+
+/// Scrolls the treemap to the rect whose `data-path` matches the clicked table row and
+/// outlines it for a moment.
+const TABLE_SCRIPT: &str = "(function(){\
+var svg=document.getElementById('import-graph');\
+document.querySelector('#module-table tbody').addEventListener('click',function(e){\
+var row=e.target.closest('tr[data-path]');\
+if(!row){return;}\
+var path=row.getAttribute('data-path');\
+var g=Array.prototype.find.call(svg.querySelectorAll('g[data-path]'),function(g){\
+return g.getAttribute('data-path')===path;\
+});\
+if(!g){return;}\
+g.scrollIntoView({block:'center',inline:'center',behavior:'smooth'});\
+g.classList.add('table-target');\
+setTimeout(function(){g.classList.remove('table-target');},1500);\
+});\
+})();";
+
+/// Each rect's ancestor names and its own, joined by " > " like `module_paths`. Identifies a
+/// rect across the treemap and the module table.
+fn rect_paths(rects: &[Rect]) -> Vec<String> {
+    rects
+        .iter()
+        .map(|rect| {
+            let mut names = vec![rect.name.as_str()];
+            let mut parent = rect.parent;
+            while let Some(index) = parent {
+                names.push(&rects[index].name);
+                parent = rects[index].parent;
+            }
+            names.reverse();
+            names.join(" > ")
+        })
+        .collect()
+}
+
+/// Every module rect, numbered by cumulative time from the heaviest, with its color swatch.
+/// Self rects and "(other: ...)" groups are left out. Rows carry the rect's `data-path`.
+fn render_module_table(rects: &[Rect], unit: TimeUnit) -> String {
+    let paths = rect_paths(rects);
+    let mut modules: Vec<usize> = (0..rects.len())
+        .filter(|index| !rects[*index].is_self && !rects[*index].name.starts_with('('))
+        .collect();
+    // Stable, so ties keep their layout order.
+    modules.sort_by(|a, b| rects[*b].us.cmp(&rects[*a].us));
+    let mut out = format!(
+        "<div id=\"module-table\"><h2>{} modules by cumulative time</h2>\
+        <div class=\"table-scroll\"><table><thead><tr><th>#</th><th>Module</th><th>Time</th>\
+        <th>Share</th></tr></thead><tbody>",
+        modules.len()
+    );
+    for (rank, index) in modules.into_iter().enumerate() {
+        let rect = &rects[index];
+        out.push_str(&format!(
+            "<tr data-path=\"{}\"><td>{}</td>\
+            <td><span class=\"swatch\" style=\"background:{}\"></span>{}</td>\
+            <td>{}</td><td>{:.1}%</td></tr>",
+            escape_xml(&paths[index]),
+            rank + 1,
+            rect.color,
+            escape_xml(&rect.name),
+            escape_xml(&format_duration(rect.us, unit)),
+            rect.pct
+        ));
+    }
+    out.push_str("</tbody></table></div></div>");
+    out
+}
+
+/// Self time summed per module name, heaviest first, ties broken by name.
+pub(crate) fn self_time_by_module(tree: &Tree) -> Vec<(String, u64)> {
+    let mut totals: std::collections::BTreeMap<String, u64> = std::collections::BTreeMap::new();
+    for module in module_paths(tree) {
+        *totals.entry(module.name).or_default() += module.self_us;
+    }
+    let mut modules: Vec<(String, u64)> = totals.into_iter().collect();
+    modules.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    modules
+}
+
+const BAR_HEIGHT: f64 = 16.0;
+const BAR_GAP: f64 = 4.0;
+const BAR_LABEL_WIDTH: f64 = 240.0;
+
+/// Horizontal bars for the `top` modules by self time, colored like their treemap rects.
+fn render_self_bars(tree: &Tree, top: usize, config: &LayoutConfig, unit: TimeUnit) -> String {
+    let (width, hues) = (config.width, config.quantize_colors);
+    let modules: Vec<(String, u64)> = self_time_by_module(tree).into_iter().take(top).collect();
+    let max_us = modules.first().map_or(0, |(_, us)| *us).max(1) as f64;
+    let bar_space = (width - BAR_LABEL_WIDTH - 100.0).max(100.0);
+    let height = modules.len() as f64 * (BAR_HEIGHT + BAR_GAP) + BAR_GAP;
+    let mut out = format!(
+        "<div id=\"self-bars\"><h2>Top {} modules by self time</h2>\
+        <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+        font-family=\"sans-serif\" font-size=\"12\">",
+        modules.len(),
+        width,
+        height
+    );
+    for (row, (name, us)) in modules.iter().enumerate() {
+        let y = BAR_GAP + row as f64 * (BAR_HEIGHT + BAR_GAP);
+        let bar_width = (*us as f64 / max_us * bar_space).max(1.0);
+        let text_y = y + BAR_HEIGHT - 4.0;
+        out.push_str(&format!(
+            "<g class=\"bar\"><text x=\"{}\" y=\"{}\" text-anchor=\"end\" fill=\"#eee\">{}</text>\
+            <rect x=\"{}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\"/>\
+            <text x=\"{:.1}\" y=\"{}\" fill=\"#eee\">{}</text></g>",
+            BAR_LABEL_WIDTH - 6.0,
+            text_y,
+            escape_xml(name),
+            BAR_LABEL_WIDTH,
+            y,
+            bar_width,
+            BAR_HEIGHT,
+            color_for_name(name, false, hues),
+            BAR_LABEL_WIDTH + bar_width + 6.0,
+            text_y,
+            escape_xml(&format_duration(*us, unit))
+        ));
+    }
+    out.push_str("</svg></div>");
+    out
+}
+
+/// A standalone SVG document with the same treemap as the HTML report.
+pub fn build_graph_svg(
+    tree: &Tree,
+    config: &LayoutConfig,
+    options: &RenderOptions,
+) -> Result<String> {
+    let rects = layout_tree(tree, config);
+    let total = format_duration(tree.total_us(), options.time_unit);
+    Ok(render_svg(&rects, config, options, &total))
+}
+
+/// One row per rect: label, raw microseconds, milliseconds, geometry, and color.
+pub fn build_graph_csv(
+    tree: &Tree,
+    config: &LayoutConfig,
+    options: &RenderOptions,
+) -> Result<String> {
+    let rects = layout_tree(tree, config);
+    let mut csv = String::from("label,is_self,us,ms,x,y,w,h,color\n");
+    for rect in &rects {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{}\n",
+            escape_csv(&rect.name),
+            rect.is_self,
+            rect.us,
+            round_to(rect.display_ms, options.precision),
+            round_to(rect.x, options.precision),
+            round_to(rect.y, options.precision),
+            round_to(rect.w, options.precision),
+            round_to(rect.h, options.precision),
+            rect.color
+        ));
+    }
+    Ok(csv)
+}
+
+/// Each module's self time under its import chain: the `(frames, weight)` pairs behind both
+/// flamegraph exports.
+fn self_time_stacks(tree: &Tree) -> Vec<(Vec<String>, u64)> {
+    let mut stacks = Vec::new();
+    let bootstrap_us = tree.self_us(tree.root);
+    if bootstrap_us > 0 {
+        stacks.push((vec![BOOTSTRAP_FRAME.to_string()], bootstrap_us));
+    }
+    stacks.extend(
+        module_paths(tree)
+            .into_iter()
+            .filter(|module| module.self_us > 0)
+            .map(|module| (module.path, module.self_us)),
+    );
+    stacks
+}
+
+/// Folded stacks (`a;a.b 300`, weights in µs) for flamegraph.pl, inferno and similar tools.
+/// A title goes on a leading `# title` comment line, which those tools skip.
+pub fn build_folded(tree: &Tree, options: &RenderOptions) -> String {
+    let mut folded = options
+        .flamegraph_title
+        .as_ref()
+        .map(|title| format!("# {title}\n"))
+        .unwrap_or_default();
+    for (frames, weight) in self_time_stacks(tree) {
+        folded.push_str(&format!("{} {}\n", frames.join(";"), weight));
+    }
+    folded
+}
+
+#[derive(Serialize)]
+pub struct SpeedscopeJson {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    name: String,
+    exporter: String,
+    #[serde(rename = "activeProfileIndex")]
+    active_profile_index: usize,
+    shared: SpeedscopeShared,
+    profiles: Vec<SpeedscopeProfile>,
+}
+
+#[derive(Serialize)]
+struct SpeedscopeShared {
+    frames: Vec<SpeedscopeFrame>,
+}
+
+#[derive(Serialize)]
+struct SpeedscopeFrame {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct SpeedscopeProfile {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: String,
+    unit: &'static str,
+    #[serde(rename = "startValue")]
+    start_value: u64,
+    #[serde(rename = "endValue")]
+    end_value: u64,
+    /// Indexes into `shared.frames`, outermost first.
+    samples: Vec<Vec<usize>>,
+    weights: Vec<u64>,
+}
+
+/// A speedscope file (https://www.speedscope.app/file-format-schema.json) with one sampled
+/// profile: every module's self time as a sample weighted in microseconds.
+pub fn build_speedscope(tree: &Tree, options: &RenderOptions) -> SpeedscopeJson {
+    let title = options.flamegraph_title.as_deref().unwrap_or(DEFAULT_TITLE).to_string();
+    let mut frames: Vec<SpeedscopeFrame> = Vec::new();
+    let mut frame_index: HashMap<String, usize> = HashMap::new();
+    let mut samples = Vec::new();
+    let mut weights = Vec::new();
+    for (stack, weight) in self_time_stacks(tree) {
+        let sample = stack
+            .into_iter()
+            .map(|name| {
+                *frame_index.entry(name.clone()).or_insert_with(|| {
+                    frames.push(SpeedscopeFrame { name });
+                    frames.len() - 1
+                })
+            })
+            .collect();
+        samples.push(sample);
+        weights.push(weight);
+    }
+    SpeedscopeJson {
+        schema: "https://www.speedscope.app/file-format-schema.json",
+        name: title.clone(),
+        exporter: format!("pyimporttime {}", env!("CARGO_PKG_VERSION")),
+        active_profile_index: 0,
+        shared: SpeedscopeShared { frames },
+        profiles: vec![SpeedscopeProfile {
+            kind: "sampled",
+            name: title,
+            unit: "microseconds",
+            start_value: 0,
+            end_value: weights.iter().sum(),
+            samples,
+            weights,
+        }],
+    }
+}
+
+/// The most expensive top-level imports as one line of block characters scaled to the
+/// largest, followed by their names in the same order, e.g. `█▅▂ numpy pandas json`.
+pub fn build_sparkline(tree: &Tree, options: &RenderOptions) -> String {
+    let top = options.sparkline_top.unwrap_or(DEFAULT_TOP);
+    let modules: Vec<_> = top_level_imports(tree).into_iter().take(top).collect();
+    let max = modules.first().map_or(0, |module| module.cumulative_us).max(1);
+    let blocks: String = modules
+        .iter()
+        .map(|module| {
+            let level = (module.cumulative_us * SPARK_BLOCKS.len() as u64).div_ceil(max);
+            SPARK_BLOCKS[(level as usize).clamp(1, SPARK_BLOCKS.len()) - 1]
+        })
+        .collect();
+    let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+    format!("{} {}\n", blocks, names.join(" "))
+}
+
+/// One-line digest for the terminal, e.g.
+/// `parsed 432 modules, total 1234.5 ms, top: numpy 210.1 ms`.
+pub fn summary_line(tree: &Tree) -> String {
+    let mut line = format!(
+        "parsed {} modules, total {:.1} ms",
+        module_paths(tree).len(),
+        tree.total_us() as f64 / 1000.0
     );
-    Ok(html)
+    if let Some(top) = top_level_imports(tree).first() {
+        line.push_str(&format!(
+            ", top: {} {:.1} ms",
+            top.name,
+            top.cumulative_us as f64 / 1000.0
+        ));
+    }
+    line
 }
 
-fn render_svg(rects: &[Rect], config: &LayoutConfig) -> String {
+fn render_svg(rects: &[Rect], config: &LayoutConfig, options: &RenderOptions, total: &str) -> String {
+    let mut svg = Vec::new();
+    write_svg(&mut svg, rects, config, options, total).expect("writing to a Vec cannot fail");
+    String::from_utf8(svg).expect("rendered SVG is UTF-8")
+}
+
+/// Writes the treemap SVG one rect at a time, so only a single rect's markup is buffered.
+fn write_svg<W: Write + ?Sized>(
+    out: &mut W,
+    rects: &[Rect],
+    config: &LayoutConfig,
+    options: &RenderOptions,
+    total: &str,
+) -> io::Result<()> {
     let mut svg = String::new();
+    let xlink = if options.link_template.is_some() {
+        " xmlns:xlink=\"http://www.w3.org/1999/xlink\""
+    } else {
+        ""
+    };
     svg.push_str(&format!(
-        "<svg id=\"import-graph\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" xmlns=\"http://www.w3.org/2000/svg\">",
+        "<svg id=\"import-graph\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" xmlns=\"http://www.w3.org/2000/svg\"{xlink} role=\"img\" aria-labelledby=\"import-graph-title import-graph-desc\">",
         width = config.width,
         height = config.height
     ));
-    svg.push_str("<rect x=\"0\" y=\"0\" width=\"100%\" height=\"100%\" fill=\"#333\"/>");
-    for rect in rects {
-        let name = escape_xml(&rect.name);
+    let module_count = rects.iter().filter(|rect| !rect.is_self).count();
+    svg.push_str(&format!(
+        "<title id=\"import-graph-title\">{}</title>",
+        escape_xml("Python import time")
+    ));
+    svg.push_str(&format!(
+        "<desc id=\"import-graph-desc\">{}</desc>",
+        escape_xml(&format!(
+            "Python import time treemap, total {}, {} modules",
+            total, module_count
+        ))
+    ));
+    svg.push_str(&format!(
+        "<rect x=\"0\" y=\"0\" width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+        options.background()
+    ));
+    out.write_all(svg.as_bytes())?;
+    svg.clear();
+    let labeled = labeled_rects(rects, options.max_labels);
+    let self_us = options.group_self.then(|| self_us_by_rect(rects));
+    let paths = options.table.then(|| rect_paths(rects));
+    let entry = entry_rect(rects, options);
+    let ranks = options.show_rank.then(|| leaf_ranks(rects));
+    let hotspots = if options.mark_hotspots {
+        hotspot_rects(rects)
+    } else {
+        vec![false; rects.len()]
+    };
+    let mut ordered: Vec<(usize, &Rect)> = rects.iter().enumerate().collect();
+    if options.paint_order == PaintOrder::Area {
+        // Stable, so equal areas keep their layout order.
+        ordered.sort_by(|(_, a), (_, b)| {
+            (b.w * b.h)
+                .partial_cmp(&(a.w * a.h))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+    for (index, rect) in ordered {
+        if self_us.is_some() && rect.is_self {
+            continue;
+        }
         let title_label = if rect.is_self {
             format!("{} (self)", rect.name)
         } else {
             rect.name.clone()
         };
-        let title = escape_xml(&format!("{}: {:.3} ms", title_label, rect.display_ms));
-        let stroke = if rect.is_self { "none" } else { "#fff" };
-        svg.push_str(&format!(
-            "<g transform=\"translate({:.2},{:.2})\">",
-            rect.x, rect.y
-        ));
+        let duration = format_duration(rect.us, options.time_unit);
+        let title = escape_xml(&format!("{}: {}", title_label, duration));
+        let over_budget = options
+            .budget_ms
+            .is_some_and(|budget| !rect.is_self && rect.display_ms > budget);
+        let (fill, stroke) = if options.outline {
+            ("none", OUTLINE_COLORS[rect.depth.saturating_sub(1) % OUTLINE_COLORS.len()])
+        } else if rect.is_self {
+            (rect.color.as_str(), "none")
+        } else {
+            (rect.color.as_str(), "#fff")
+        };
+        let text_color = if options.outline { OUTLINE_LABEL_COLOR } else { "#fff" };
+        let children = if rect.top_children.is_empty() {
+            String::new()
+        } else {
+            let breakdown: Vec<(&str, String)> = rect
+                .top_children
+                .iter()
+                .map(|(name, us)| (name.as_str(), format_duration(*us, options.time_unit)))
+                .collect();
+            let json = serde_json::to_string(&breakdown).unwrap_or_default();
+            format!(" data-children=\"{}\"", escape_xml(&json))
+        };
+        let path = match &paths {
+            Some(paths) if !rect.is_self => {
+                format!(" data-path=\"{}\"", escape_xml(&paths[index]))
+            }
+            _ => String::new(),
+        };
         svg.push_str(&format!(
-            "<rect width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\" stroke=\"{}\"/>",
-            rect.w, rect.h, rect.color, stroke
+            "<g transform=\"translate({:.2},{:.2})\"{}{}>",
+            rect.x, rect.y, children, path
         ));
+        // Self rects, groups and "(see first)" references are not modules with a source file.
+        let link = (rect.kind == NodeKind::Module)
+            .then(|| options.link_for(&rect.name))
+            .flatten();
+        if let Some(link) = &link {
+            svg.push_str(&format!("<a xlink:href=\"{}\" target=\"_blank\">", escape_xml(link)));
+        }
+        if over_budget {
+            svg.push_str(&format!(
+                "<rect class=\"over-budget\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\" stroke=\"#f00\" stroke-width=\"3\"/>",
+                rect.w, rect.h, fill
+            ));
+        } else {
+            svg.push_str(&format!(
+                "<rect width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\" stroke=\"{}\"/>",
+                rect.w, rect.h, fill, stroke
+            ));
+        }
+        if let Some(self_us) = self_us.as_ref().and_then(|self_us| self_us[index])
+            && rect.us > 0
+        {
+            // A bar along the bottom, as tall as the module's share of self time.
+            let share = (self_us as f64 / rect.us as f64).min(1.0);
+            let height = (rect.h * share).max(config.self_min_px).min(rect.h);
+            let paint = if options.outline {
+                format!("fill=\"none\" stroke=\"{stroke}\" stroke-dasharray=\"2,2\"")
+            } else {
+                format!("fill=\"#000\" fill-opacity=\"{SELF_OVERLAY_OPACITY}\"")
+            };
+            svg.push_str(&format!(
+                "<rect class=\"self-overlay\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" {} pointer-events=\"none\"/>",
+                rect.h - height,
+                rect.w,
+                height,
+                paint
+            ));
+        }
+        if !rect.is_self && options.is_highlighted(&rect.name) {
+            svg.push_str(&format!(
+                "<rect class=\"highlight\" width=\"{:.2}\" height=\"{:.2}\" fill=\"none\" stroke=\"{}\" stroke-width=\"4\"/>",
+                rect.w, rect.h, HIGHLIGHT_COLOR
+            ));
+        }
+        if entry == Some(index) {
+            svg.push_str(&format!(
+                "<rect class=\"entry\" width=\"{:.2}\" height=\"{:.2}\" fill=\"none\" stroke=\"{}\" stroke-width=\"6\"/>",
+                rect.w, rect.h, ENTRY_COLOR
+            ));
+        }
+        if hotspots[index] {
+            svg.push_str(&format!(
+                "<rect class=\"hotspot\" width=\"{:.2}\" height=\"{:.2}\" fill=\"none\" stroke=\"{}\" stroke-width=\"3\"/>",
+                rect.w, rect.h, HOTSPOT_COLOR
+            ));
+            if rect.w > 16.0 && rect.h > 16.0 {
+                svg.push_str(&format!(
+                    "<text class=\"hotspot-star\" x=\"{:.2}\" y=\"12\" text-anchor=\"end\" fill=\"{}\" font-size=\"12\">★</text>",
+                    rect.w - 3.0,
+                    HOTSPOT_COLOR
+                ));
+            }
+        }
         svg.push_str(&format!("<title>{}</title>", title));
-        if !rect.is_self && rect.w > 40.0 && rect.h > 16.0 {
+        // Tall, narrow rects get their label along the height when `rotate_labels` is on.
+        let rotated = options.rotate_labels
+            && rect.h > rect.w * ROTATE_LABEL_RATIO
+            && rect.h > 40.0
+            && rect.w > 16.0;
+        if labeled[index] && !rect.is_self && (rotated || (rect.w > 40.0 && rect.h > 16.0)) {
+            let template = options.label_format.as_deref().unwrap_or(DEFAULT_LABEL_FORMAT);
+            let mut label = format_label(template, rect, options.time_unit);
+            if let Some(rank) = ranks.as_ref().and_then(|ranks| ranks[index]) {
+                label.insert_str(0, &format!("#{rank} "));
+            }
+            if options.show_count && rect.descendants > 0 {
+                label.push_str(&format!(" ({})", rect.descendants));
+            }
+            // Rotated 90° about the rect's corner, text runs down the left edge.
+            let position = if rotated {
+                "x=\"4\" y=\"-4\" transform=\"rotate(90)\""
+            } else {
+                "x=\"4\" y=\"14\""
+            };
             svg.push_str(&format!(
-                "<text x=\"4\" y=\"14\" fill=\"#fff\" font-size=\"10\" font-family=\"sans-serif\">{}: {:.3} ms</text>",
-                name, rect.display_ms
+                "<text {} fill=\"{}\" font-size=\"10\" font-family=\"sans-serif\">{}</text>",
+                position,
+                text_color,
+                escape_xml(&label)
             ));
         }
+        if options.show_order
+            && labeled[index]
+            && let Some(order) = rect.order
+            && rect.w > 24.0
+            && rect.h > 28.0
+        {
+            svg.push_str(&format!(
+                "<text class=\"order\" x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"end\" fill=\"{}\" font-size=\"9\" font-family=\"sans-serif\">{}</text>",
+                rect.w - 3.0,
+                rect.h - 3.0,
+                text_color,
+                order
+            ));
+        }
+        if link.is_some() {
+            svg.push_str("</a>");
+        }
         svg.push_str("</g>");
+        out.write_all(svg.as_bytes())?;
+        svg.clear();
+    }
+    out.write_all(b"</svg>")
+}
+
+/// Self time of each module rect, taken from its self child, or its whole time for modules
+/// without imports. Rects whose self child was dropped or folded get `None`.
+fn self_us_by_rect(rects: &[Rect]) -> Vec<Option<u64>> {
+    let mut self_us: Vec<Option<u64>> = rects
+        .iter()
+        .map(|rect| {
+            (!rect.is_self && !rect.name.starts_with('(') && rect.descendants == 0)
+                .then_some(rect.us)
+        })
+        .collect();
+    for rect in rects.iter().filter(|rect| rect.is_self) {
+        if let Some(parent) = rect.parent {
+            self_us[parent] = Some(rect.us);
+        }
+    }
+    self_us
+}
+
+/// Marks the module with the most cumulative time among each parent's children (top-level
+/// imports count as the root's children), so following the marks walks down the slowest
+/// chain at every level. Ties go to the first rect in layout order.
+fn hotspot_rects(rects: &[Rect]) -> Vec<bool> {
+    let mut largest: HashMap<Option<usize>, usize> = HashMap::new();
+    for (index, rect) in rects.iter().enumerate() {
+        if rect.is_self || rect.name.starts_with('(') {
+            continue;
+        }
+        largest
+            .entry(rect.parent)
+            .and_modify(|best| {
+                if rect.us > rects[*best].us {
+                    *best = index;
+                }
+            })
+            .or_insert(index);
+    }
+    let mut hotspots = vec![false; rects.len()];
+    for index in largest.into_values() {
+        hotspots[index] = true;
+    }
+    hotspots
+}
+
+/// Rank of each leaf module rect by cumulative time, 1 being the slowest. Ties keep layout
+/// order; parents and self rects get `None`.
+fn leaf_ranks(rects: &[Rect]) -> Vec<Option<usize>> {
+    let mut leaves: Vec<usize> = (0..rects.len())
+        .filter(|index| !rects[*index].is_self && rects[*index].descendants == 0)
+        .collect();
+    leaves.sort_by_key(|index| std::cmp::Reverse(rects[*index].us));
+    let mut ranks = vec![None; rects.len()];
+    for (rank, index) in leaves.into_iter().enumerate() {
+        ranks[index] = Some(rank + 1);
+    }
+    ranks
+}
+
+/// Which rects may carry text: all of them, or only the `max_labels` largest module rects.
+fn labeled_rects(rects: &[Rect], max_labels: Option<usize>) -> Vec<bool> {
+    let Some(max_labels) = max_labels else {
+        return vec![true; rects.len()];
+    };
+    let mut modules: Vec<usize> = (0..rects.len()).filter(|index| !rects[*index].is_self).collect();
+    modules.sort_by(|a, b| {
+        let area = |index: usize| rects[index].w * rects[index].h;
+        area(*b).total_cmp(&area(*a))
+    });
+    let mut labeled = vec![false; rects.len()];
+    for index in modules.into_iter().take(max_labels) {
+        labeled[index] = true;
+    }
+    labeled
+}
+
+/// The first module rect named `options.entry`, or `ENTRY_MODULE` when no entry was given,
+/// so the application's own code stands apart from the dependencies imported beside it.
+fn entry_rect(rects: &[Rect], options: &RenderOptions) -> Option<usize> {
+    let name = options.entry.as_deref().unwrap_or(ENTRY_MODULE);
+    rects.iter().position(|rect| !rect.is_self && rect.name == name)
+}
+
+/// Names of modules (not self rects) whose cumulative time exceeds `budget_ms`.
+fn over_budget(rects: &[Rect], budget_ms: f64) -> Vec<String> {
+    rects
+        .iter()
+        .filter(|rect| !rect.is_self && rect.display_ms > budget_ms)
+        .map(|rect| rect.name.clone())
+        .collect()
+}
+
+fn round_to(value: f64, digits: Option<u32>) -> f64 {
+    match digits {
+        Some(digits) => {
+            let scale = 10f64.powi(digits as i32);
+            (value * scale).round() / scale
+        }
+        None => value,
+    }
+}
+
+/// Expands `{name}`, `{leaf}` (last dotted component), `{time}` (in the chosen unit), `{ms}`,
+/// `{us}`, `{pct}` (share of the total) and `{depth}` in `template`. Other text is kept as is.
+fn format_label(template: &str, rect: &Rect, unit: TimeUnit) -> String {
+    let leaf = rect.name.rsplit('.').next().unwrap_or(&rect.name);
+    template
+        .replace("{name}", &rect.name)
+        .replace("{leaf}", leaf)
+        .replace("{time}", &format_duration(rect.us, unit))
+        .replace("{ms}", &format!("{:.3}", rect.us as f64 / 1000.0))
+        .replace("{us}", &rect.us.to_string())
+        .replace("{pct}", &format!("{:.1}", rect.pct))
+        .replace("{depth}", &rect.depth.to_string())
+}
+
+fn format_duration(us: u64, unit: TimeUnit) -> String {
+    let unit = match unit {
+        TimeUnit::Auto if us < 1_000 => TimeUnit::Us,
+        TimeUnit::Auto if us < 1_000_000 => TimeUnit::Ms,
+        TimeUnit::Auto => TimeUnit::S,
+        unit => unit,
+    };
+    match unit {
+        TimeUnit::Us => format!("{} µs", us),
+        TimeUnit::S => format!("{:.3} s", us as f64 / 1_000_000.0),
+        _ => format!("{:.3} ms", us as f64 / 1000.0),
+    }
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Keeps JSON serialized through it from closing the surrounding `<script>` element early or
+/// opening markup such as `<!--` inside it. These characters only occur inside JSON strings,
+/// where the `\uXXXX` forms decode to the same text.
+struct ScriptJsonWriter<W>(W);
+
+impl<W: Write> Write for ScriptJsonWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut start = 0;
+        for (index, byte) in buf.iter().enumerate() {
+            let escaped: &[u8] = match byte {
+                b'<' => b"\\u003c",
+                b'>' => b"\\u003e",
+                b'&' => b"\\u0026",
+                _ => continue,
+            };
+            self.0.write_all(&buf[start..index])?;
+            self.0.write_all(escaped)?;
+            start = index + 1;
+        }
+        self.0.write_all(&buf[start..])?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
     }
-    svg.push_str("</svg>");
-    svg
 }
 
 fn escape_xml(text: &str) -> String {
@@ -123,14 +1287,872 @@ fn escape_xml(text: &str) -> String {
 mod tests {
     use super::*;
     use crate::layout::LayoutConfig;
+    use crate::parser::parse_import_time;
+    use crate::tree::{build_tree, build_tree_from_parsed, nest_records};
+
+    #[test]
+    fn command_line_display_pastes_back_into_a_shell() {
+        let command = CommandLine {
+            program: "/opt/my env/bin/python".to_string(),
+            args: ["-c", "print('$HOME'); x", "", "a.py", "--n=1"].map(String::from).to_vec(),
+            cwd: "/".to_string(),
+        };
+        assert_eq!(
+            command.display(),
+            r#"'/opt/my env/bin/python' -c 'print('\''$HOME'\''); x' '' a.py --n=1"#
+        );
+    }
 
     #[test]
     fn graph_html_contains_svg() {
         let log = "\
 import time: self [us] | cumulative | imported package\n\
 import time:       10 |         10 | a\n";
-        let html = build_graph_html(log, &LayoutConfig::default()).expect("html");
+        let tree = build_tree(log).expect("tree");
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &RenderOptions::default()).expect("html");
         assert!(html.contains("<svg"));
         assert!(html.contains("import time"));
     }
+
+    #[test]
+    fn svg_starts_with_title_and_desc() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |          8 | b\n";
+        let tree = build_tree(log).expect("tree");
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &RenderOptions::default()).expect("html");
+        let svg_start = html.find("<svg").expect("svg");
+        let svg = &html[svg_start..];
+        let first_child = &svg[svg.find('>').expect("svg open tag") + 1..];
+        assert!(first_child.starts_with("<title id=\"import-graph-title\">Python import time</title>"));
+        assert!(svg.contains(
+            "<desc id=\"import-graph-desc\">Python import time treemap, total 0.008 ms, 2 modules</desc>"
+        ));
+    }
+
+    #[test]
+    fn graph_html_embeds_json_data() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 | </script>\n";
+        let tree = build_tree(log).expect("tree");
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &RenderOptions::default()).expect("html");
+        let start_tag = "<script type=\"application/json\" id=\"pyimporttime-data\">";
+        let start = html.find(start_tag).expect("data script") + start_tag.len();
+        let end = start + html[start..].find("</script>").expect("script end");
+        let data: serde_json::Value = serde_json::from_str(&html[start..end]).expect("json");
+        assert_eq!(data["rects"][0]["label"], "</script>");
+        assert_eq!(data["meta"]["total_ms"], 0.01);
+    }
+
+    #[test]
+    fn graph_json_critical_path_follows_largest_children() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      300 |        300 |     big.core\n\
+import time:      100 |        100 |     big.util\n\
+import time:       50 |        450 |   big\n\
+import time:       20 |        470 | app\n\
+import time:      400 |        400 | small\n";
+        let tree = build_tree(log).expect("tree");
+        let graph = build_graph_json(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("json");
+        let value = serde_json::to_value(&graph).expect("value");
+        let path = value["critical_path"].as_array().expect("critical path");
+        let names: Vec<&str> = path.iter().map(|step| step["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["app", "big", "big.core"]);
+        assert_eq!(path[0]["cumulative_us"], 470);
+        assert_eq!(path[0]["self_us"], 20);
+        assert_eq!(path[2]["depth"], 3);
+    }
+
+    #[test]
+    fn graph_json_meta_reconciles_self_and_cumulative() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        0 |        400 |   a.quiet\n\
+import time:      250 |        250 |   a.b\n\
+import time:      100 |        750 | a\n\
+import time:     1500 |       1500 | c\n";
+        let tree = build_tree(log).expect("tree");
+        let self_us: u64 = tree
+            .arena
+            .iter()
+            .filter(|node| node.name == "self")
+            .map(|node| node.cumulative_us)
+            .sum();
+        let graph = build_graph_json(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("json");
+        let value = serde_json::to_value(&graph).expect("value");
+        assert_eq!(value["meta"]["total_self_ms"], self_us as f64 / 1000.0);
+        assert_eq!(value["meta"]["total_self_ms"], 1.85);
+        assert_eq!(value["meta"]["total_ms"], 2.25);
+        assert_eq!(value["meta"]["overhead_ms"], 0.4);
+    }
+
+    #[test]
+    fn fingerprint_ignores_order_but_not_times() {
+        let fingerprint_of = |log: &str| {
+            let tree = build_tree(log).expect("tree");
+            let graph = build_graph_json(&tree, &LayoutConfig::default(), &RenderOptions::default())
+                .expect("json");
+            serde_json::to_value(&graph).expect("value")["meta"]["fingerprint"]
+                .as_str()
+                .expect("fingerprint")
+                .to_string()
+        };
+        let log = "\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      700 |        700 | c\n";
+        let reordered = "\
+import time:      700 |        700 | c\n\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n";
+        let slower = "\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      900 |        900 | c\n";
+        assert_eq!(fingerprint_of(log), fingerprint_of(reordered));
+        assert_ne!(fingerprint_of(log), fingerprint_of(slower));
+    }
+
+    #[test]
+    fn normalized_json_coordinates_are_fractions() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      700 |        700 | c\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig::default();
+        let absolute = serde_json::to_value(
+            build_graph_json(&tree, &config, &RenderOptions::default()).expect("json"),
+        )
+        .expect("value");
+        assert_eq!(absolute["meta"]["coordinate_space"], "absolute");
+
+        let options = RenderOptions {
+            normalized: true,
+            ..RenderOptions::default()
+        };
+        let value = serde_json::to_value(build_graph_json(&tree, &config, &options).expect("json"))
+            .expect("value");
+        assert_eq!(value["meta"]["coordinate_space"], "normalized");
+        let rects = value["rects"].as_array().expect("rects");
+        for rect in rects {
+            let (x, y) = (rect["x"].as_f64().unwrap(), rect["y"].as_f64().unwrap());
+            let (w, h) = (rect["w"].as_f64().unwrap(), rect["h"].as_f64().unwrap());
+            for value in [x, y, w, h, x + w, y + h] {
+                assert!((0.0..=1.0 + 1e-9).contains(&value), "{value} outside [0,1]");
+            }
+        }
+        let widest = rects.iter().filter_map(|rect| rect["w"].as_f64()).fold(0.0, f64::max);
+        let widest_px = absolute["rects"]
+            .as_array()
+            .expect("rects")
+            .iter()
+            .filter_map(|rect| rect["w"].as_f64())
+            .fold(0.0, f64::max);
+        assert!((widest - widest_px / config.width).abs() < 1e-9);
+    }
+
+    #[test]
+    fn graph_html_shows_interpreter_version() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            interpreter: Some("CPython 3.12.3".to_string()),
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        assert!(html.contains("total 0.010 ms - CPython 3.12.3</div>"));
+        assert!(html.contains("\"interpreter\":\"CPython 3.12.3\""));
+    }
+
+    #[test]
+    fn auto_time_unit_thresholds() {
+        assert_eq!(format_duration(999, TimeUnit::Auto), "999 µs");
+        assert_eq!(format_duration(1_000, TimeUnit::Auto), "1.000 ms");
+        assert_eq!(format_duration(999_999, TimeUnit::Auto), "999.999 ms");
+        assert_eq!(format_duration(1_000_000, TimeUnit::Auto), "1.000 s");
+        assert_eq!(format_duration(1_500, TimeUnit::Us), "1500 µs");
+        assert_eq!(format_duration(1_500, TimeUnit::S), "0.002 s");
+    }
+
+    #[test]
+    fn paint_order_area_emits_largest_first() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        2 |          2 |   b.c\n\
+import time:        8 |         10 | b\n\
+import time:        5 |          5 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig::default();
+        let options = RenderOptions {
+            paint_order: PaintOrder::Area,
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &config, &options).expect("html");
+        let titles: Vec<&str> = html
+            .split("<title>")
+            .skip(2)
+            .map(|part| &part[..part.find(':').expect("title label")])
+            .collect();
+        assert_eq!(titles, vec!["b", "b (self)", "a", "a (self)", "b.c", "b.c (self)"]);
+    }
+
+    #[test]
+    fn graph_json_respects_precision() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1234 |       1234 | a\n\
+import time:     4321 |       4321 | b\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            precision: Some(1),
+            ..RenderOptions::default()
+        };
+        let graph = build_graph_json(&tree, &LayoutConfig::default(), &options).expect("json");
+        let value = serde_json::to_value(&graph).expect("value");
+        assert_eq!(value["meta"]["total_ms"], 5.6);
+        let a = value["rects"]
+            .as_array()
+            .expect("rects")
+            .iter()
+            .find(|rect| rect["label"] == "a")
+            .expect("a");
+        assert_eq!(a["ms"], 1.2);
+        assert_eq!(a["us"], 1234);
+        let x = a["x"].as_f64().expect("x");
+        assert_eq!((x * 10.0).fract(), 0.0);
+    }
+
+    #[test]
+    fn graph_csv_quotes_labels() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 | a,b\n";
+        let tree = build_tree(log).expect("tree");
+        let csv = build_graph_csv(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("csv");
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("label,is_self,us,ms,x,y,w,h,color"));
+        assert!(lines.next().expect("row").starts_with("\"a,b\",false,10,0.01,"));
+    }
+
+    #[test]
+    fn rect_over_budget_is_highlighted() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     5000 |       5000 | slow\n\
+import time:      100 |        100 | fast\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            budget_ms: Some(1.0),
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        assert_eq!(html.matches("class=\"over-budget\"").count(), 1);
+        let slow = html.find("<title>slow: ").expect("slow title");
+        let highlight = html.find("class=\"over-budget\"").expect("highlight");
+        assert!(highlight < slow);
+        assert!(html.contains("1 over 1 ms budget: slow"));
+        assert!(html.contains("\"over_budget\":[\"slow\"]"));
+    }
+
+    #[test]
+    fn summary_line_format() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       50 |         50 |   numpy.core\n\
+import time:   210050 |     210100 | numpy\n\
+import time:     1000 |       1000 | json\n";
+        let tree = build_tree(log).expect("tree");
+        assert_eq!(
+            summary_line(&tree),
+            "parsed 3 modules, total 211.1 ms, top: numpy 210.1 ms"
+        );
+    }
+
+    #[test]
+    fn background_color_applies_to_svg_and_page() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            background: Some("#102030".to_string()),
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        assert!(html.contains("body{margin:0;padding:0;background:#102030;"));
+        assert!(html.contains("height=\"100%\" fill=\"#102030\"/>"));
+        assert!(!html.contains("#333"));
+    }
+
+    #[test]
+    fn parse_color_validates_input() {
+        assert!(parse_color("#abc").is_ok());
+        assert!(parse_color("#a1b2c3").is_ok());
+        assert!(parse_color("navy").is_ok());
+        assert!(parse_color("RebeccaPurple").is_ok());
+        assert!(parse_color("blakc").is_err());
+        assert!(parse_color("#abcde").is_err());
+        assert!(parse_color("red;}body{").is_err());
+    }
+
+    #[test]
+    fn embedded_source_is_escaped() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 | a\n\
+</pre><script>alert(1)</script>\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            source: Some(log.to_string()),
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        assert!(html.contains("<details id=\"import-source\">"));
+        assert!(html.contains("&lt;/pre&gt;&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert_eq!(html.matches("</pre>").count(), 1);
+    }
+
+    #[test]
+    fn parent_rects_have_children_breakdown() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1500 |       1500 |   p.a\n\
+import time:      500 |       2000 | p\n";
+        let tree = build_tree(log).expect("tree");
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("html");
+        assert!(html.contains("data-children=\"[[&quot;p.a&quot;,&quot;1.500 ms&quot;]]\""));
+        assert!(html.contains("<div id=\"tooltip\"></div>"));
+        assert!(html.contains("<title>p: 2.000 ms</title>"));
+    }
+
+    #[test]
+    fn show_order_numbers_rects() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1000 |       1000 | a\n\
+import time:     2000 |       2000 | b\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            show_order: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        assert_eq!(svg.matches("class=\"order\"").count(), 2);
+        assert!(svg.contains("font-family=\"sans-serif\">1</text>"));
+        let plain = build_graph_svg(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("svg");
+        assert!(!plain.contains("class=\"order\""));
+    }
+
+    #[test]
+    fn label_format_template_is_applied() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1500 |       1500 |   pkg.sub\n\
+import time:      500 |       2000 | pkg\n\
+import time:     2000 |       2000 | other\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            label_format: Some("{leaf} [{depth}] {ms}ms {us}us {pct}%".to_string()),
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        assert!(svg.contains(">sub [2] 1.500ms 1500us 37.5%</text>"));
+        assert!(svg.contains(">pkg [1] 2.000ms 2000us 50.0%</text>"));
+        let plain = build_graph_svg(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("svg");
+        assert!(plain.contains(">pkg.sub: 1.500 ms</text>"));
+    }
+
+    #[test]
+    fn show_count_appends_descendant_count() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1000 |       1000 |     p.a.x\n\
+import time:     1000 |       2000 |   p.a\n\
+import time:     1000 |       1000 |   p.b\n\
+import time:     1000 |       4000 | p\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            show_count: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        assert!(svg.contains(">p: 4.000 ms (3)</text>"));
+        assert!(svg.contains(">p.a: 2.000 ms (1)</text>"));
+        assert!(svg.contains(">p.b: 1.000 ms</text>"));
+    }
+
+    #[test]
+    fn show_rank_numbers_slowest_leaf_first() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     3000 |       3000 |   p.slow\n\
+import time:     1000 |       1000 |   p.fast\n\
+import time:     1000 |       5000 | p\n\
+import time:     2000 |       2000 | q\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            show_rank: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        assert!(svg.contains(">#1 p.slow: 3.000 ms</text>"));
+        assert!(svg.contains(">#2 q: 2.000 ms</text>"));
+        assert!(svg.contains(">#3 p.fast: 1.000 ms</text>"));
+        assert!(svg.contains(">p: 5.000 ms</text>"));
+    }
+
+    #[test]
+    fn outline_mode_draws_unfilled_rects() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1000 |       1000 |   p.a\n\
+import time:     1000 |       2000 | p\n\
+import time:     2000 |       2000 | q\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            outline: true,
+            group_self: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        // Everything after the background is the treemap itself.
+        let (_, treemap) = svg.split_once("height=\"100%\"").expect("background");
+        let rects: Vec<&str> = treemap.split("<rect").skip(1).collect();
+        assert!(rects.len() >= 3);
+        for rect in rects {
+            assert!(rect.contains("fill=\"none\""), "filled rect: {rect}");
+        }
+        assert!(treemap.contains(&format!("stroke=\"{}\"", OUTLINE_COLORS[1])));
+        assert!(treemap.contains(&format!("fill=\"{}\"", OUTLINE_LABEL_COLOR)));
+    }
+
+    #[test]
+    fn angle_bracket_module_names_never_leak_raw() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1000 |       1000 |   <frozen importlib._bootstrap>\n\
+import time:     1000 |       2000 | <frozen zipimport>\n";
+        let records = crate::parser::parse_import_time(log).expect("records");
+        let tree_options = crate::tree::TreeOptions {
+            include_builtin: true,
+            hide_bootstrap: false,
+            ..Default::default()
+        };
+        let tree = crate::tree::build_tree_from_parsed(records, &tree_options).expect("tree");
+        let options = RenderOptions {
+            budget_ms: Some(0.5),
+            show_order: true,
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        assert!(!html.contains("<frozen"));
+        assert!(html.contains("<title>&lt;frozen zipimport&gt;: 2.000 ms</title>"));
+        assert!(html.contains("&lt;frozen importlib._bootstrap&gt;: 1.000 ms</text>"));
+        assert!(html.contains("&quot;&lt;frozen importlib._bootstrap&gt;&quot;"));
+        assert!(html.contains("\\u003cfrozen zipimport\\u003e"));
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        assert!(!svg.contains("<frozen"));
+    }
+
+    #[test]
+    fn bars_list_heaviest_self_time_first() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       40 |         40 |   light\n\
+import time:      900 |        900 |   heavy\n\
+import time:      100 |       1040 | app\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions { bars_top: Some(2), ..RenderOptions::default() };
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        let bars = &html[html.find("<div id=\"self-bars\">").expect("bars section")..];
+        assert!(bars.contains("Top 2 modules by self time"));
+        let heavy = bars.find(">heavy<").expect("heavy bar");
+        let app = bars.find(">app<").expect("app bar");
+        assert!(heavy < app);
+        assert!(!bars.contains(">light<"));
+        assert!(bars.contains(&format!("fill=\"{}\"", color_for_name("heavy", false, None))));
+
+        let plain = build_graph_html(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("html");
+        assert!(!plain.contains("<div id=\"self-bars\">"));
+    }
+
+    #[test]
+    fn module_table_lists_heaviest_module_first() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       40 |         40 |   light\n\
+import time:      900 |        900 |   heavy\n\
+import time:      100 |       1040 | app\n\
+import time:      300 |        300 | other\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions { table: true, ..RenderOptions::default() };
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        let table = &html[html.find("<div id=\"module-table\">").expect("table section")..];
+        assert!(table.contains("4 modules by cumulative time"));
+        let rows: Vec<&str> = table.split("<tr data-path=\"").skip(1).collect();
+        assert!(rows[0].starts_with("app\"><td>1</td>"), "{}", rows[0]);
+        assert!(rows[1].starts_with("app &gt; heavy\"><td>2</td>"), "{}", rows[1]);
+        assert!(rows[2].starts_with("other\""));
+        assert!(rows[3].starts_with("app &gt; light\""));
+        // The rect carries the same path for the click handler to find.
+        assert!(html.contains("data-path=\"app &gt; heavy\">"));
+
+        let plain = build_graph_html(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("html");
+        assert!(!plain.contains("module-table\">"));
+        assert!(!plain.contains("data-path"));
+    }
+
+    #[test]
+    fn link_template_wraps_module_rects() {
+        assert_eq!(
+            module_link("https://github.com/me/repo/tree/main/{module}", "a.b", "/"),
+            "https://github.com/me/repo/tree/main/a/b"
+        );
+        assert_eq!(
+            module_link("https://docs.example/{module}.html", "a.b", "."),
+            "https://docs.example/a.b.html"
+        );
+
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       30 |         30 |   a.b\n\
+import time:       20 |         50 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            link_template: Some("https://example.com/src/{module}.py?x=1&y=2".to_string()),
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        assert!(svg.contains("xmlns:xlink=\"http://www.w3.org/1999/xlink\""));
+        assert!(svg.contains("<a xlink:href=\"https://example.com/src/a/b.py?x=1&amp;y=2\""));
+        assert_eq!(svg.matches("<a ").count(), 2);
+
+        let plain = build_graph_svg(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .expect("svg");
+        assert!(!plain.contains("xlink"));
+
+        // Input-file groups and "(see first)" references are not modules with a source file.
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       30 |         30 |   c\n\
+import time:       10 |         40 | a\n\
+import time:        5 |          5 |   c\n\
+import time:       10 |         15 | b\n";
+        let records = nest_records(parse_import_time(log).expect("records"), "app.log");
+        let tree_options = TreeOptions {
+            dedupe: true,
+            ..TreeOptions::default()
+        };
+        let tree = build_tree_from_parsed(records, &tree_options).expect("tree");
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        let links: Vec<&str> = svg
+            .split("<a xlink:href=\"https://example.com/src/")
+            .skip(1)
+            .filter_map(|rest| rest.split_once(".py?"))
+            .map(|(module, _)| module)
+            .collect();
+        assert_eq!(links, ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn highlighted_modules_are_outlined() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       30 |         30 |   numpy.linalg\n\
+import time:       20 |         50 | numpy\n\
+import time:       40 |         40 | numpyx\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            highlight: vec!["numpy".to_string()],
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &LayoutConfig::default(), &options).expect("svg");
+        let groups: Vec<&str> = svg.split("<g ").skip(1).collect();
+        let outlined = |name: &str| {
+            groups
+                .iter()
+                .find(|group| group.contains(&format!("<title>{name}: ")))
+                .expect(name)
+                .contains("class=\"highlight\"")
+        };
+        assert!(outlined("numpy"));
+        assert!(outlined("numpy.linalg"));
+        assert!(!outlined("numpyx"));
+        assert_eq!(svg.matches("class=\"highlight\"").count(), 2);
+        assert!(svg.contains(&format!("stroke=\"{HIGHLIGHT_COLOR}\" stroke-width=\"4\"")));
+    }
+
+    #[test]
+    fn entry_module_is_outlined_and_noted() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       30 |         30 |   app.models\n\
+import time:       20 |         50 | app\n\
+import time:       40 |         40 | __main__\n";
+        let tree = build_tree(log).expect("tree");
+        let entry_group = |options: &RenderOptions| {
+            let svg = build_graph_svg(&tree, &LayoutConfig::default(), options).expect("svg");
+            assert!(svg.matches("class=\"entry\"").count() <= 1);
+            svg.split("<g ")
+                .skip(1)
+                .find(|group| group.contains("class=\"entry\""))
+                .map(|group| group.to_string())
+        };
+        // Without --entry, `__main__` is picked up on its own.
+        let detected = entry_group(&RenderOptions::default()).expect("detected entry");
+        assert!(detected.contains("<title>__main__: "), "{detected}");
+        let options = RenderOptions { entry: Some("app".to_string()), ..RenderOptions::default() };
+        let named = entry_group(&options).expect("named entry");
+        assert!(named.contains("<title>app: "), "{named}");
+        assert!(named.contains(&format!("stroke=\"{ENTRY_COLOR}\" stroke-width=\"6\"")));
+
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &options).expect("html");
+        assert!(html.contains(" - entry app: 0.050 ms (55.6% of total)"));
+        let missing = RenderOptions { entry: Some("nope".to_string()), ..RenderOptions::default() };
+        assert!(entry_group(&missing).is_none());
+    }
+
+    #[test]
+    fn tall_narrow_rects_get_rotated_labels() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      100 |        100 | thin\n\
+import time:     4900 |       4900 | wide\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig {
+            width: 1000.0,
+            height: 800.0,
+            ..LayoutConfig::default()
+        };
+        let plain = build_graph_svg(&tree, &config, &RenderOptions::default()).expect("svg");
+        assert!(!plain.contains("sans-serif\">thin: "));
+        let options = RenderOptions {
+            rotate_labels: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &config, &options).expect("svg");
+        assert!(svg.contains(
+            "<text x=\"4\" y=\"-4\" transform=\"rotate(90)\" fill=\"#fff\" font-size=\"10\" \
+             font-family=\"sans-serif\">thin: 0.100 ms</text>"
+        ));
+        assert!(svg.contains(
+            "<text x=\"4\" y=\"14\" fill=\"#fff\" font-size=\"10\" \
+             font-family=\"sans-serif\">wide: 4.900 ms</text>"
+        ));
+    }
+
+    #[test]
+    fn max_labels_keeps_text_on_largest_rects() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     1000 |       1000 | small\n\
+import time:     3000 |       3000 | medium\n\
+import time:     6000 |       6000 | large\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig::default();
+        let all = build_graph_svg(&tree, &config, &RenderOptions::default()).expect("svg");
+        assert_eq!(all.matches("<text ").count(), 3);
+        let options = RenderOptions {
+            max_labels: Some(2),
+            show_order: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &config, &options).expect("svg");
+        let labeled: Vec<&str> = svg
+            .split("<g ")
+            .skip(1)
+            .filter(|group| group.contains("<text "))
+            .collect();
+        assert_eq!(labeled.len(), 2);
+        assert!(labeled.iter().any(|group| group.contains(">large: ")));
+        assert!(labeled.iter().any(|group| group.contains(">medium: ")));
+    }
+
+    #[test]
+    fn group_self_draws_overlays_proportional_to_self_time() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:     3000 |       3000 |   child\n\
+import time:     1000 |       4000 | parent\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig::default();
+        let options = RenderOptions {
+            group_self: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &config, &options).expect("svg");
+        assert!(!svg.contains("(self)"));
+        let rects = layout_tree(&tree, &config);
+        let overlays: Vec<(f64, f64)> = svg
+            .split("<rect class=\"self-overlay\"")
+            .skip(1)
+            .map(|overlay| {
+                let attr = |name: &str| -> f64 {
+                    let start = overlay.find(&format!(" {name}=\"")).expect(name) + name.len() + 3;
+                    let end = start + overlay[start..].find('"').expect("quote");
+                    overlay[start..end].parse().expect("number")
+                };
+                (attr("y"), attr("height"))
+            })
+            .collect();
+        assert_eq!(overlays.len(), 2);
+        for (name, ratio) in [("parent", 0.25), ("child", 1.0)] {
+            let rect = rects.iter().find(|rect| rect.name == name && !rect.is_self).expect(name);
+            let expected = rect.h * ratio;
+            assert!(
+                overlays
+                    .iter()
+                    .any(|(y, h)| (h - expected).abs() < 0.01 && (y + h - rect.h).abs() < 0.01),
+                "{name}: {overlays:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sparkline_scales_top_modules_in_order() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      100 |        100 | json\n\
+import time:     8000 |       8000 | numpy\n\
+import time:     4000 |       4000 | pandas\n\
+import time:       50 |         50 | re\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            sparkline_top: Some(3),
+            ..RenderOptions::default()
+        };
+        let line = build_sparkline(&tree, &options);
+        assert_eq!(line, "█▄▁ numpy pandas json\n");
+        let (blocks, names) = line.trim_end().split_once(' ').expect("blocks");
+        assert_eq!(blocks.chars().count(), names.split(' ').count());
+    }
+
+    #[test]
+    fn streamed_html_matches_string_output() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      700 |        700 | c<&>\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig::default();
+        let options = RenderOptions {
+            source: Some(log.to_string()),
+            bars_top: Some(3),
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &config, &options).expect("html");
+        let path = std::env::temp_dir()
+            .join(format!("pyimporttime-stream-{}.html", std::process::id()));
+        let mut file = io::BufWriter::new(std::fs::File::create(&path).expect("create"));
+        write_graph_html(&mut file, &tree, &config, &options).expect("stream");
+        drop(file);
+        assert_eq!(std::fs::read_to_string(&path).expect("read"), html);
+        std::fs::remove_file(&path).expect("remove");
+        assert!(html.contains(r#""label":"c\u003c\u0026\u003e""#));
+    }
+
+    #[test]
+    fn mark_hotspots_stars_largest_child_of_each_parent() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      300 |        300 |   a.b\n\
+import time:      100 |        100 |   a.c\n\
+import time:      200 |        600 | a\n\
+import time:      500 |        500 | d\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig::default();
+        let plain = build_graph_svg(&tree, &config, &RenderOptions::default()).expect("svg");
+        assert!(!plain.contains("class=\"hotspot\""));
+        let options = RenderOptions {
+            mark_hotspots: true,
+            ..RenderOptions::default()
+        };
+        let svg = build_graph_svg(&tree, &config, &options).expect("svg");
+        let marked: Vec<&str> = svg
+            .split("<g ")
+            .skip(1)
+            .filter(|group| group.contains("class=\"hotspot\""))
+            .filter_map(|group| group.split("<title>").nth(1)?.split(':').next())
+            .collect();
+        assert_eq!(marked, vec!["a", "a.b"]);
+    }
+
+    #[test]
+    fn folded_and_speedscope_exports_share_frames() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      700 |        700 | c\n";
+        let tree = build_tree(log).expect("tree");
+        let options = RenderOptions {
+            flamegraph_title: Some("app startup".to_string()),
+            ..RenderOptions::default()
+        };
+        let folded = build_folded(&tree, &options);
+        assert_eq!(folded, "# app startup\na 200\na;a.b 300\nc 700\n");
+        assert!(build_folded(&tree, &RenderOptions::default()).starts_with("a 200\n"));
+
+        let value = serde_json::to_value(build_speedscope(&tree, &options)).expect("json");
+        for key in ["$schema", "name", "shared", "profiles", "activeProfileIndex", "exporter"] {
+            assert!(value.get(key).is_some(), "{key}");
+        }
+        assert_eq!(value["name"], "app startup");
+        let names: Vec<&str> = value["shared"]["frames"]
+            .as_array()
+            .expect("frames")
+            .iter()
+            .map(|frame| frame["name"].as_str().expect("name"))
+            .collect();
+        assert_eq!(names, vec!["a", "a.b", "c"]);
+        let profile = &value["profiles"][0];
+        assert_eq!(profile["type"], "sampled");
+        assert_eq!(profile["unit"], "microseconds");
+        assert_eq!(profile["endValue"], 1200);
+        assert_eq!(profile["samples"], serde_json::json!([[0], [0, 1], [2]]));
+        assert_eq!(profile["weights"], serde_json::json!([200, 300, 700]));
+    }
+
+    #[test]
+    fn minimap_is_added_on_request() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      300 |        300 |   a.b\n\
+import time:      200 |        500 | a\n\
+import time:      700 |        700 | c\n";
+        let tree = build_tree(log).expect("tree");
+        let config = LayoutConfig::default();
+        let plain = build_graph_html(&tree, &config, &RenderOptions::default()).expect("html");
+        assert!(!plain.contains("<div id=\"minimap\">"));
+        assert!(!plain.contains("minimap-view"));
+        let options = RenderOptions {
+            minimap: true,
+            ..RenderOptions::default()
+        };
+        let html = build_graph_html(&tree, &config, &options).expect("html");
+        let start = html.find("<div id=\"minimap\"><svg").expect("minimap");
+        let minimap = &html[start..start + html[start..].find("</div>").expect("end")];
+        let viewbox = format!("viewBox=\"0 0 {} {}\"", config.width, config.height);
+        assert!(minimap.contains(&viewbox), "{minimap}");
+        assert!(minimap.contains("id=\"minimap-view\""));
+        let rects = layout_tree(&tree, &config);
+        assert_eq!(minimap.matches("<rect ").count(), rects.len() + 1);
+        assert!(html.contains("getElementById('minimap-view')"));
+    }
 }
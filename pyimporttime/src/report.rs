@@ -0,0 +1,1483 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Args, ValueEnum};
+
+use crate::cli::{parse_records, RecordSource};
+use crate::config::load_config;
+use crate::layout::{
+    layout_tree, sliver_warning, verify_layout, AreaScale, LayoutConfig, ParentPad, SelfStyle,
+    SortChildren, DEFAULT_GAP, DEFAULT_HEADER_HEIGHT, DEFAULT_HEIGHT, DEFAULT_PARENT_PAD,
+    DEFAULT_WIDTH,
+};
+use crate::parser::{format_import_time, parse_graph_json, parse_import_time, ImportRecord};
+use crate::profile::ImportProfile;
+use crate::render::{
+    build_folded, build_graph_csv, build_graph_html, build_graph_json, build_graph_svg,
+    build_speedscope, build_sparkline, parse_color, summary_line, write_graph_html, CommandLine,
+    PaintOrder, RenderOptions, TimeUnit, DEFAULT_LABEL_FORMAT, DEFAULT_LINK_SEPARATOR,
+    DEFAULT_TOP,
+};
+use crate::tree::{
+    group_by_directory, nest_records, subtract_baseline, tree_records, SelfDefinition, Tree,
+    TreeOptions,
+};
+use crate::util::{
+    content_hash, data_uri, open_in_browser_or_warn, output_is_current,
+    write_gzip_output, write_html_or_open, write_output_hash, write_text_output,
+};
+
+#[derive(Args)]
+pub(crate) struct GraphArgs {
+    /// Don't print the one-line summary to stderr.
+    #[arg(short, long)]
+    quiet: bool,
+    /// Include the raw import-time log in a collapsed section of the HTML report.
+    #[arg(long)]
+    embed_source: bool,
+    /// Print the report as a base64 `data:` URI on stdout instead of writing a file.
+    #[arg(long)]
+    data_uri: bool,
+    /// Gzip-compress the report, for archiving or transfer; implied by an `--output` ending in
+    /// `.gz`. Browsers won't open a compressed HTML report directly.
+    #[arg(long, conflicts_with = "data_uri")]
+    gzip: bool,
+    /// Check the layout for overlapping or escaping rects and report problems on stderr.
+    #[arg(long, hide = true)]
+    verify_layout: bool,
+    /// Warn on stderr when some rect is more than RATIO times longer than it is wide.
+    #[arg(
+        long,
+        value_name = "RATIO",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "20"
+    )]
+    min_aspect_warning: Option<f64>,
+    #[command(flatten)]
+    tree: TreeArgs,
+    #[command(flatten)]
+    layout: LayoutArgs,
+    #[command(flatten)]
+    render: RenderArgs,
+    /// Read option defaults from this TOML file instead of ./pyimporttime.toml.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+}
+
+impl GraphArgs {
+    /// Fails if a tree option was given, for `relayout`, which draws a report's tree as it
+    /// was built rather than filtering it again.
+    pub(crate) fn reject_tree_options(matches: &ArgMatches) -> Result<()> {
+        const TREE_OPTIONS: [&str; 8] = [
+            "dedupe",
+            "include_builtin",
+            "hide_bootstrap",
+            "root",
+            "since",
+            "collapse_single_child",
+            "subtract_overhead",
+            "self_def",
+        ];
+        for id in TREE_OPTIONS {
+            if matches.value_source(id) == Some(ValueSource::CommandLine) {
+                bail!(
+                    "--{} does not apply to relayout, which keeps the tree of the report; \
+                    pass it to graph instead",
+                    id.replace('_', "-")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in options from the config file unless they were given on the command line,
+    /// so the precedence is flags, then the file, then built-in defaults.
+    pub(crate) fn apply_config_file(&mut self, matches: &ArgMatches) -> Result<()> {
+        let Some(config) = load_config(self.config.as_deref())? else {
+            return Ok(());
+        };
+        let from_file = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        let layout = &mut self.layout;
+        override_with(&mut layout.width, config.width, from_file("width"));
+        override_with(&mut layout.height, config.height, from_file("height"));
+        override_with(&mut layout.gap, config.gap, from_file("gap"));
+        override_with(&mut layout.parent_pad, config.parent_pad, from_file("parent_pad"));
+        override_with(&mut layout.header_height, config.header_height, from_file("header_height"));
+        override_with(&mut layout.sort_children, config.sort_children, from_file("sort_children"));
+        override_with(&mut layout.self_style, config.self_style, from_file("self_style"));
+        override_with(&mut layout.min_rect_px, config.min_rect_px, from_file("min_rect_px"));
+        override_with(&mut layout.self_min_px, config.self_min_px, from_file("self_min_px"));
+        override_with(
+            &mut layout.collapse_below_pct,
+            config.collapse_below_pct,
+            from_file("collapse_below_pct"),
+        );
+        override_with(&mut layout.scale, config.scale, from_file("scale"));
+        override_with(&mut layout.max_rects, config.max_rects.map(Some), from_file("max_rects"));
+        override_with(&mut layout.flat, config.flat, from_file("flat"));
+        let render = &mut self.render;
+        override_with(&mut render.time_unit, config.time_unit, from_file("time_unit"));
+        override_with(&mut render.paint_order, config.paint_order, from_file("paint_order"));
+        override_with(&mut render.precision, config.precision.map(Some), from_file("precision"));
+        let background = config
+            .background
+            .map(|color| parse_color(&color).map_err(|err| anyhow!("config background: {err}")))
+            .transpose()?;
+        override_with(&mut render.background, background.map(Some), from_file("background"));
+        override_with(&mut render.label_format, config.label_format, from_file("label_format"));
+        override_with(
+            &mut render.link_template,
+            config.link_template.map(Some),
+            from_file("link_template"),
+        );
+        override_with(&mut render.link_separator, config.link_separator, from_file("link_separator"));
+        Ok(())
+    }
+
+    pub(crate) fn options(&self) -> GraphOptions {
+        GraphOptions {
+            quiet: self.quiet,
+            embed_source: self.embed_source,
+            data_uri: self.data_uri,
+            gzip: self.gzip,
+            verify_layout: self.verify_layout,
+            min_aspect_warning: self.min_aspect_warning,
+            tree: self.tree.options(),
+            layout: self.layout.config(),
+            render: self.render.options(),
+        }
+    }
+}
+
+fn override_with<T>(target: &mut T, value: Option<T>, allowed: bool) {
+    if allowed && let Some(value) = value {
+        *target = value;
+    }
+}
+
+/// Everything needed to turn an import log into a report.
+#[derive(Debug, Default)]
+pub(crate) struct GraphOptions {
+    quiet: bool,
+    embed_source: bool,
+    data_uri: bool,
+    gzip: bool,
+    verify_layout: bool,
+    min_aspect_warning: Option<f64>,
+    tree: TreeOptions,
+    layout: LayoutConfig,
+    render: RenderOptions,
+}
+
+#[derive(Args)]
+struct TreeArgs {
+    /// Collapse repeated imports of a module to a reference to its first occurrence.
+    #[arg(long)]
+    dedupe: bool,
+    /// Keep builtin modules, which are hidden by default.
+    #[arg(long)]
+    include_builtin: bool,
+    /// Fold CPython's import machinery (`_frozen_importlib`, `importlib._bootstrap`, ...)
+    /// into the root's self time; pass `--hide-bootstrap false` to show it.
+    #[arg(long, default_value_t = true, action = ArgAction::Set, value_name = "BOOL")]
+    hide_bootstrap: bool,
+    /// Only show the subtree of this module, e.g. `django`, scaled to fill the canvas.
+    #[arg(long, value_name = "MODULE")]
+    root: Option<String>,
+    /// Drop everything logged before MODULE started importing, for logs captured partway
+    /// through startup.
+    #[arg(long, value_name = "MODULE")]
+    since: Option<String>,
+    /// Merge chains of modules that each import exactly one other module into one rect,
+    /// e.g. `a > a.b > a.b.c` into `a.b.c`.
+    #[arg(long)]
+    collapse_single_child: bool,
+    /// Experimental: subtract an estimated US microseconds of `-X importtime` overhead from
+    /// every module's self time (default 2).
+    #[arg(
+        long,
+        value_name = "US",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "2"
+    )]
+    subtract_overhead: Option<u64>,
+    /// Take self time from the log's self column, or as cumulative time minus the imports'.
+    #[arg(long, value_enum, default_value_t = SelfDefinition::Reported)]
+    self_def: SelfDefinition,
+}
+
+impl TreeArgs {
+    fn options(&self) -> TreeOptions {
+        TreeOptions {
+            dedupe: self.dedupe,
+            include_builtin: self.include_builtin,
+            hide_bootstrap: self.hide_bootstrap,
+            root: self.root.clone(),
+            since: self.since.clone(),
+            collapse_single_child: self.collapse_single_child,
+            subtract_overhead_us: self.subtract_overhead,
+            self_definition: self.self_def,
+        }
+    }
+}
+
+#[derive(Args)]
+struct LayoutArgs {
+    #[arg(long, default_value_t = DEFAULT_WIDTH)]
+    width: f64,
+    #[arg(long, default_value_t = DEFAULT_HEIGHT)]
+    height: f64,
+    #[arg(long, default_value_t = DEFAULT_GAP)]
+    gap: f64,
+    /// Padding inside parent rects; a comma-separated list such as `4,2,1` sets it per depth,
+    /// from top-level modules down, with the last value used for deeper levels.
+    #[arg(
+        long,
+        value_name = "PX[,PX...]",
+        default_value_t = ParentPad::uniform(DEFAULT_PARENT_PAD)
+    )]
+    parent_pad: ParentPad,
+    #[arg(long, default_value_t = DEFAULT_HEADER_HEIGHT)]
+    header_height: f64,
+    #[arg(long, value_enum, default_value_t = SortChildren::Layout)]
+    sort_children: SortChildren,
+    /// How self-time rects are colored relative to their module.
+    #[arg(long, value_enum, default_value_t = SelfStyle::Distinct)]
+    self_style: SelfStyle,
+    /// Minimum width and height, in pixels, of module rects; modules that can't get it are
+    /// folded into "(other)" (0 disables).
+    #[arg(long, default_value_t = 0.0)]
+    min_rect_px: f64,
+    /// Minimum width and height, in pixels, of self time rects and overlays (0 disables).
+    #[arg(long, value_name = "PX", default_value_t = 0.0)]
+    self_min_px: f64,
+    /// Fold modules below P percent of their parent's time into one "(other)" rect (0 disables).
+    #[arg(long, value_name = "P", default_value_t = 0.0)]
+    collapse_below_pct: f64,
+    /// How sibling times map to areas; `log` keeps small modules visible beside a dominant one
+    /// at the cost of areas no longer being proportional to time.
+    #[arg(long, value_enum, default_value_t = AreaScale::Linear)]
+    scale: AreaScale,
+    /// Stop expanding subtrees once the report would exceed N rects.
+    #[arg(long, value_name = "N")]
+    max_rects: Option<usize>,
+    /// Draw only leaf rects, without parent padding or header strips.
+    #[arg(long)]
+    flat: bool,
+    /// Print each node's weight, area, and rect to stderr while laying out.
+    #[arg(long)]
+    verbose: bool,
+    /// Use at most N evenly spaced hues for package colors, so related packages group visually.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..=360))]
+    quantize_colors: Option<u16>,
+}
+
+impl LayoutArgs {
+    fn config(&self) -> LayoutConfig {
+        LayoutConfig {
+            width: self.width,
+            height: self.height,
+            gap: self.gap,
+            parent_pad: self.parent_pad,
+            header_height: self.header_height,
+            sort_children: self.sort_children,
+            self_style: self.self_style,
+            min_rect_px: self.min_rect_px,
+            self_min_px: self.self_min_px,
+            collapse_below_pct: self.collapse_below_pct,
+            scale: self.scale,
+            max_rects: self.max_rects,
+            flat: self.flat,
+            verbose: self.verbose,
+            quantize_colors: self.quantize_colors,
+        }
+    }
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// Unit used for displayed times; raw microseconds are always kept in JSON.
+    #[arg(long, value_enum, default_value_t = TimeUnit::Ms)]
+    time_unit: TimeUnit,
+    /// Order rects are drawn in, which is also their DOM order.
+    #[arg(long, value_enum, default_value_t = PaintOrder::Tree)]
+    paint_order: PaintOrder,
+    /// Decimal places for JSON milliseconds and coordinates (raw microseconds are exact).
+    #[arg(long, value_name = "DIGITS")]
+    precision: Option<u32>,
+    /// Outline and list modules whose cumulative time exceeds this many milliseconds.
+    #[arg(long, value_name = "MS")]
+    budget_ms: Option<f64>,
+    /// Background color as hex (`#222`) or a CSS color name.
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    background: Option<String>,
+    /// Label each module with its position in the log, i.e. the order imports finished.
+    #[arg(long)]
+    show_order: bool,
+    /// Rect label template with {name}, {leaf}, {time}, {ms}, {us}, {pct} and {depth}.
+    #[arg(long, value_name = "TEMPLATE", default_value = DEFAULT_LABEL_FORMAT)]
+    label_format: String,
+    /// Append how many modules each parent imported beneath it, e.g. `numpy: 42.1 ms (37)`.
+    #[arg(long)]
+    show_count: bool,
+    /// Prefix each leaf module's label with its rank by cumulative time, e.g. `#1 numpy`.
+    #[arg(long)]
+    show_rank: bool,
+    /// Append a bar chart of the modules with the most self time below the treemap.
+    #[arg(long)]
+    with_bars: bool,
+    /// How many modules the `--with-bars` chart and `--format sparkline` list.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_TOP)]
+    top: usize,
+    /// Link each module rect to this URL; `{module}` becomes the module path, e.g. `a/b`.
+    #[arg(long, value_name = "URL")]
+    link_template: Option<String>,
+    /// What replaces the dots of a module name in `--link-template`.
+    #[arg(long, value_name = "SEP", default_value = DEFAULT_LINK_SEPARATOR)]
+    link_separator: String,
+    /// Outline this module and its submodules in the output; may be repeated.
+    #[arg(long, value_name = "MODULE")]
+    highlight: Vec<String>,
+    /// Outline this module as the application's entry point and note its share of the total
+    /// in the toolbar; `__main__` is used when present if not given.
+    #[arg(long, value_name = "MODULE")]
+    entry: Option<String>,
+    /// Write JSON coordinates as fractions of the canvas (0-1) instead of pixels.
+    #[arg(long)]
+    normalized: bool,
+    /// Draw labels vertically in rects that are much taller than they are wide.
+    #[arg(long)]
+    rotate_labels: bool,
+    /// Draw self time as a translucent bar at the bottom of each module instead of its own rect.
+    #[arg(long)]
+    group_self: bool,
+    /// Star the most expensive child of every parent, tracing the slowest path at each level.
+    #[arg(long)]
+    mark_hotspots: bool,
+    /// Title carried by `--format folded` and `--format speedscope` output.
+    #[arg(long, value_name = "TITLE")]
+    flamegraph_title: Option<String>,
+    /// Add a minimap with a scroll-tracking view box to the HTML report, for large canvases.
+    #[arg(long)]
+    minimap: bool,
+    /// Append a table of every module, heaviest first, to the HTML report; clicking a row
+    /// scrolls to its rect.
+    #[arg(long)]
+    table: bool,
+    /// Draw a wireframe: unfilled rects outlined in a color per depth.
+    #[arg(long)]
+    outline: bool,
+    /// Label at most N rects, the largest first, to keep dense reports small.
+    #[arg(long, value_name = "N")]
+    max_labels: Option<usize>,
+}
+
+impl RenderArgs {
+    fn options(&self) -> RenderOptions {
+        RenderOptions {
+            time_unit: self.time_unit,
+            paint_order: self.paint_order,
+            precision: self.precision,
+            budget_ms: self.budget_ms,
+            background: self.background.clone(),
+            show_order: self.show_order,
+            label_format: Some(self.label_format.clone()),
+            show_count: self.show_count,
+            show_rank: self.show_rank,
+            bars_top: self.with_bars.then_some(self.top),
+            link_template: self.link_template.clone(),
+            link_separator: Some(self.link_separator.clone()),
+            highlight: self.highlight.clone(),
+            entry: self.entry.clone(),
+            normalized: self.normalized,
+            rotate_labels: self.rotate_labels,
+            group_self: self.group_self,
+            max_labels: self.max_labels,
+            mark_hotspots: self.mark_hotspots,
+            flamegraph_title: self.flamegraph_title.clone(),
+            minimap: self.minimap,
+            table: self.table,
+            outline: self.outline,
+            sparkline_top: Some(self.top),
+            ..RenderOptions::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ProfileVia {
+    /// Inject `-X importtime` before the interpreter arguments.
+    Flag,
+    /// Set `PYTHONPROFILEIMPORTTIME=1`.
+    #[default]
+    Env,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    Auto,
+    Html,
+    Json,
+    Svg,
+    Csv,
+    /// One line of block characters for the top-level imports, for status lines.
+    Sparkline,
+    /// Folded stacks of self time, for flamegraph.pl and inferno.
+    Folded,
+    /// A speedscope JSON profile.
+    Speedscope,
+    /// CPython's `-X importtime` log, after the tree options have been applied.
+    Importtime,
+}
+
+impl OutputFormat {
+    /// Resolves `Auto` from the output file extension, looking past a trailing `.gz`;
+    /// explicit formats are kept as-is.
+    fn resolve(self, output: Option<&Path>) -> OutputFormat {
+        if self != OutputFormat::Auto {
+            return self;
+        }
+        let output = output.map(|path| {
+            if is_gzip_path(path) {
+                Path::new(path.file_stem().unwrap_or_default())
+            } else {
+                path
+            }
+        });
+        let extension = output
+            .and_then(Path::extension)
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => OutputFormat::Json,
+            Some("svg") => OutputFormat::Svg,
+            Some("csv") => OutputFormat::Csv,
+            _ => OutputFormat::Html,
+        }
+    }
+}
+
+pub(crate) fn run_command(
+    python: &str,
+    args: Vec<String>,
+    output: Option<PathBuf>,
+    open: bool,
+    run_options: RunOptions,
+    mut options: GraphOptions,
+) -> Result<()> {
+    let executable = Executable::from_python_and_args(python, &args)?;
+    if run_options.dry_run {
+        println!("{}", describe_command(&profiling_command(&executable, run_options.via)));
+        if run_options.baseline {
+            let bare = executable.bare_interpreter(python);
+            println!("baseline:");
+            println!("{}", describe_command(&profiling_command(&bare, run_options.via)));
+        }
+        return Ok(());
+    }
+    let cmd = profiling_command(&executable, run_options.via);
+    if run_options.record_args {
+        options.render.command = Some(recorded_command(&cmd)?);
+    }
+    let output_data = run_with_import_timing(cmd)?;
+    if !output_data.status.success() {
+        eprintln!("warning: command exited with status {}", output_data.status);
+    }
+    if run_options.version_banner {
+        options.render.interpreter = query_interpreter_version(python, &executable);
+        if options.render.interpreter.is_none() {
+            eprintln!("warning: could not determine interpreter version");
+        }
+    }
+    let mut records = parse_import_time(&output_data.stderr)?;
+    if run_options.baseline {
+        let bare = executable.bare_interpreter(python);
+        let baseline = run_with_import_timing(profiling_command(&bare, run_options.via))?;
+        subtract_baseline(&mut records, &parse_import_time(&baseline.stderr)?);
+    }
+    if run_options.by_file {
+        let files = query_module_files(python, &executable, &records)?;
+        let root = std::env::current_dir().context("failed to read the working directory")?;
+        records = group_by_directory(&records, &files, &root);
+    }
+    let mut profile = ImportProfile::from_records(records, &options.tree)?;
+    profile.interpreter = options.render.interpreter.clone();
+    let tree = profile.tree();
+    print_summary(tree, &options);
+    report_layout_problems(tree, &options);
+    report_slivers(tree, &options);
+    attach_source(&mut options, &output_data.stderr);
+    write_graph(tree, output, open, OutputFormat::Html, &options)
+}
+
+#[derive(Default)]
+pub(crate) struct RunOptions {
+    pub(crate) version_banner: bool,
+    pub(crate) via: ProfileVia,
+    pub(crate) baseline: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) by_file: bool,
+    /// Record the profiled command line in the report, see `recorded_command`.
+    pub(crate) record_args: bool,
+}
+
+struct Executable {
+    path: PathBuf,
+    /// Arguments that belong to a multi-word `--python` launcher such as `uv run`.
+    launcher_args: Vec<String>,
+    args: Vec<String>,
+    is_script: bool,
+}
+
+impl Executable {
+    fn from_python_and_args(python: &str, args: &[String]) -> Result<Self> {
+        let mut words = python.split_whitespace().map(str::to_string);
+        let program = words.next().context("--python must not be empty")?;
+        let launcher_args: Vec<String> = words.collect();
+        // A launcher resolves scripts itself, so only bare interpreters get shebang lookup.
+        if launcher_args.is_empty()
+            && let Some(script_path) = find_python_script(args)
+        {
+            let mut script_args = Vec::with_capacity(args.len().saturating_sub(1));
+            script_args.extend(args.iter().skip(1).cloned());
+            return Ok(Self {
+                path: script_path,
+                launcher_args,
+                args: script_args,
+                is_script: true,
+            });
+        }
+        Ok(Self {
+            path: PathBuf::from(program),
+            launcher_args,
+            args: args.to_vec(),
+            is_script: false,
+        })
+    }
+
+    /// The interpreter command line that actually runs the program: the shebang for
+    /// scripts, otherwise the configured python.
+    fn interpreter(&self, python: &str) -> Vec<String> {
+        if self.is_script
+            && let Ok(Some(shebang)) = read_shebang(&self.path)
+        {
+            let parts: Vec<String> = shebang.split_whitespace().map(str::to_string).collect();
+            if !parts.is_empty() {
+                return parts;
+            }
+        }
+        python.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// The same interpreter running no user code, for measuring its own startup imports.
+    /// Words after the program, such as `python3` in `/usr/bin/env python3`, stay launcher
+    /// arguments so `-X importtime` is never put in front of them.
+    fn bare_interpreter(&self, python: &str) -> Executable {
+        let mut interpreter = self.interpreter(python);
+        let path = PathBuf::from(interpreter.remove(0));
+        Executable {
+            path,
+            launcher_args: interpreter,
+            args: vec!["-c".to_string(), String::new()],
+            is_script: false,
+        }
+    }
+}
+
+const VERSION_SNIPPET: &str =
+    "import platform; print(platform.python_implementation(), platform.python_version())";
+
+fn query_interpreter_version(python: &str, executable: &Executable) -> Option<String> {
+    let interpreter = executable.interpreter(python);
+    let (program, leading_args) = interpreter.split_first()?;
+    let output = Command::new(program)
+        .args(leading_args)
+        .args(["-c", VERSION_SNIPPET])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_interpreter_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads module names from stdin and prints `name<TAB>path` for those loaded from a file.
+const MODULE_FILES_SNIPPET: &str = "\
+import sys, importlib.util
+for name in sys.stdin.read().split():
+    try:
+        spec = importlib.util.find_spec(name)
+    except Exception:
+        continue
+    if spec is not None and spec.has_location and spec.origin:
+        print(name, spec.origin, sep='\\t')
+";
+
+/// Source file of each module in `records` that has one, as the interpreter resolves it.
+fn query_module_files(
+    python: &str,
+    executable: &Executable,
+    records: &[ImportRecord],
+) -> Result<HashMap<String, PathBuf>> {
+    let interpreter = executable.interpreter(python);
+    let (program, leading_args) =
+        interpreter.split_first().ok_or_else(|| anyhow!("no interpreter to query"))?;
+    let mut child = Command::new(program)
+        .args(leading_args)
+        .args(["-c", MODULE_FILES_SNIPPET])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("failed to query module files")?;
+    let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(names.join("\n").as_bytes())?;
+    }
+    let output = child.wait_with_output().context("failed to query module files")?;
+    if !output.status.success() {
+        bail!("module file query exited with status {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, path)| (name.to_string(), PathBuf::from(path)))
+        .collect())
+}
+
+/// Accepts `<implementation> <version>` as printed by `VERSION_SNIPPET`, e.g. `CPython 3.12.3`.
+fn parse_interpreter_version(text: &str) -> Option<String> {
+    let line = text.lines().next()?.trim();
+    let (implementation, version) = line.split_once(' ')?;
+    let recognizable = !implementation.is_empty()
+        && version.split('.').count() >= 2
+        && version.starts_with(|c: char| c.is_ascii_digit());
+    recognizable.then(|| line.to_string())
+}
+
+struct RunOutput {
+    stderr: String,
+    status: ExitStatus,
+}
+
+fn run_with_import_timing(mut cmd: Command) -> Result<RunOutput> {
+    let output_data = cmd.output().context("failed to run command")?;
+    Ok(RunOutput {
+        stderr: String::from_utf8_lossy(&output_data.stderr).to_string(),
+        status: output_data.status,
+    })
+}
+
+fn profiling_command(executable: &Executable, via: ProfileVia) -> Command {
+    let mut cmd = Command::new(&executable.path);
+    cmd.args(&executable.launcher_args);
+    // Shebang scripts and launchers own the interpreter command line, so there is nowhere to
+    // put `-X importtime`; the environment variable still reaches them.
+    if via == ProfileVia::Flag && !executable.is_script && executable.launcher_args.is_empty() {
+        cmd.args(["-X", "importtime"]);
+    } else {
+        if via == ProfileVia::Flag {
+            eprintln!(
+                "warning: cannot pass -X importtime through {}, falling back to PYTHONPROFILEIMPORTTIME",
+                executable.path.display()
+            );
+        }
+        cmd.env("PYTHONPROFILEIMPORTTIME", "1");
+    }
+    cmd.args(&executable.args);
+    cmd
+}
+
+/// The program, argument vector, and environment overrides of `cmd`, one per line.
+fn describe_command(cmd: &Command) -> String {
+    let args: Vec<String> = cmd
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let mut lines = vec![
+        format!("program: {}", cmd.get_program().to_string_lossy()),
+        format!("args: {:?}", args),
+    ];
+    for (key, value) in cmd.get_envs() {
+        let value = value.map_or_else(|| "<unset>".into(), |value| value.to_string_lossy());
+        lines.push(format!("env: {}={}", key.to_string_lossy(), value));
+    }
+    lines.join("\n")
+}
+
+/// The program and arguments of `cmd`, as `run` executes it, and the working directory. The
+/// program is recorded as the absolute path found on `PATH`, not resolving symlinks, so a
+/// virtualenv's interpreter stays recognizable.
+fn recorded_command(cmd: &Command) -> Result<CommandLine> {
+    let cwd = std::env::current_dir().context("failed to read the working directory")?;
+    let program = Path::new(cmd.get_program());
+    let resolved = if program.components().count() > 1 {
+        Some(program.to_path_buf())
+    } else {
+        program.to_str().and_then(find_in_path)
+    };
+    let program = resolved
+        .and_then(|path| std::path::absolute(path).ok())
+        .unwrap_or_else(|| program.to_path_buf());
+    Ok(CommandLine {
+        program: program.to_string_lossy().into_owned(),
+        args: cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+        cwd: cwd.display().to_string(),
+    })
+}
+
+/// Whether `graph` may skip rendering when `--output` already matches the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputCache {
+    Reuse,
+    Refresh,
+}
+
+pub(crate) fn graph_command(
+    inputs: &[String],
+    output: Option<PathBuf>,
+    open: bool,
+    format: OutputFormat,
+    source: RecordSource,
+    cache: OutputCache,
+    mut options: GraphOptions,
+) -> Result<()> {
+    let format = format.resolve(output.as_deref());
+    // Only HTML reports are opened; other formats are data for tools, not pages to view.
+    if open && format != OutputFormat::Html {
+        bail!(
+            "--open only applies to HTML output, not {}; drop --open or use --format html",
+            format!("{format:?}").to_lowercase()
+        );
+    }
+    // Only JSON carries coordinates; every other format would quietly draw in pixels.
+    if options.render.normalized && format != OutputFormat::Json {
+        bail!(
+            "--normalized only applies to JSON output, not {}; drop --normalized or use \
+            --format json",
+            format!("{format:?}").to_lowercase()
+        );
+    }
+    let texts = inputs
+        .iter()
+        .map(|input| source.read(input))
+        .collect::<Result<Vec<_>>>()?;
+    let settings = format!("{format:?} {source:?} {options:?}");
+    let mut parts = vec![env!("CARGO_PKG_VERSION"), settings.as_str()];
+    for (input, text) in inputs.iter().zip(&texts) {
+        parts.push(input);
+        parts.push(text);
+    }
+    let hash = content_hash(&parts);
+    if let Some(path) = output.as_deref()
+        && cache == OutputCache::Reuse
+        && output_is_current(path, &hash)
+    {
+        if !options.quiet {
+            eprintln!("{} is up to date, skipping render (use --force)", path.display());
+        }
+        if format == OutputFormat::Html {
+            if open {
+                open_in_browser_or_warn(path);
+            }
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+    let profile = match texts.as_slice() {
+        // A report's records were filtered when it was written; build them as they are.
+        [text] if source.reads_graph_report() => {
+            ImportProfile::from_report(parse_graph_json(text)?)?
+        }
+        [text] => ImportProfile::from_records(parse_records(text, source)?, &options.tree)?,
+        _ => {
+            let mut combined = Vec::new();
+            for ((input, text), label) in inputs.iter().zip(&texts).zip(input_labels(inputs)) {
+                let records = parse_records(text, source)
+                    .with_context(|| format!("failed to parse {}", input))?;
+                combined.extend(nest_records(records, &label));
+            }
+            ImportProfile::from_records(combined, &options.tree)?
+        }
+    };
+    let tree = profile.tree();
+    print_summary(tree, &options);
+    report_layout_problems(tree, &options);
+    report_slivers(tree, &options);
+    attach_source(&mut options, &texts.join("\n"));
+    write_graph(tree, output.clone(), open, format, &options)?;
+    if let Some(path) = output.as_deref() {
+        write_output_hash(path, &hash)?;
+    }
+    Ok(())
+}
+
+/// The top-level node names for combined logs: each input's file name, with as many of its
+/// directories as it takes to tell it apart from the others, so `svc/a/import.log` and
+/// `svc/b/import.log` become `a/import.log` and `b/import.log`. Inputs that still match,
+/// such as the same file given twice, get their position appended.
+fn input_labels(inputs: &[String]) -> Vec<String> {
+    let parts: Vec<Vec<String>> = inputs
+        .iter()
+        .map(|input| {
+            let parts: Vec<String> = if input == "-" {
+                vec!["stdin".to_string()]
+            } else {
+                Path::new(input)
+                    .components()
+                    .filter_map(|part| match part {
+                        Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                        _ => None,
+                    })
+                    .collect()
+            };
+            if parts.is_empty() {
+                vec![input.clone()]
+            } else {
+                parts
+            }
+        })
+        .collect();
+    let mut lengths = vec![1; inputs.len()];
+    loop {
+        let labels: Vec<String> = parts
+            .iter()
+            .zip(&lengths)
+            .map(|(parts, &length)| parts[parts.len() - length..].join("/"))
+            .collect();
+        let is_shared = |label: &String| labels.iter().filter(|other| *other == label).count() > 1;
+        let mut grew = false;
+        for (index, label) in labels.iter().enumerate() {
+            if is_shared(label) && lengths[index] < parts[index].len() {
+                lengths[index] += 1;
+                grew = true;
+            }
+        }
+        if !grew {
+            return labels
+                .iter()
+                .enumerate()
+                .map(|(index, label)| {
+                    if is_shared(label) {
+                        format!("{label} #{}", index + 1)
+                    } else {
+                        label.clone()
+                    }
+                })
+                .collect();
+        }
+    }
+}
+
+fn write_graph(
+    tree: &Tree,
+    output: Option<PathBuf>,
+    open: bool,
+    format: OutputFormat,
+    options: &GraphOptions,
+) -> Result<()> {
+    let gzip = options.gzip || output.as_deref().is_some_and(is_gzip_path);
+    // HTML reports are streamed to their file; everything else is small enough to build first.
+    if !options.data_uri && matches!(format, OutputFormat::Auto | OutputFormat::Html) {
+        return write_html_or_open(output, open, gzip, |out| {
+            write_graph_html(out, tree, &options.layout, &options.render)
+        });
+    }
+    let (content, mime) = match format {
+        OutputFormat::Json => {
+            let graph = build_graph_json(tree, &options.layout, &options.render)?;
+            (serde_json::to_string_pretty(&graph)?, "application/json")
+        }
+        OutputFormat::Svg => {
+            (build_graph_svg(tree, &options.layout, &options.render)?, "image/svg+xml")
+        }
+        OutputFormat::Csv => (build_graph_csv(tree, &options.layout, &options.render)?, "text/csv"),
+        OutputFormat::Sparkline => (build_sparkline(tree, &options.render), "text/plain"),
+        OutputFormat::Folded => (build_folded(tree, &options.render), "text/plain"),
+        OutputFormat::Importtime => (format_import_time(&tree_records(tree)), "text/plain"),
+        OutputFormat::Speedscope => {
+            let profile = build_speedscope(tree, &options.render);
+            (serde_json::to_string(&profile)?, "application/json")
+        }
+        OutputFormat::Auto | OutputFormat::Html => {
+            (build_graph_html(tree, &options.layout, &options.render)?, "text/html")
+        }
+    };
+    if options.data_uri {
+        if content.len() > DATA_URI_WARN_BYTES {
+            eprintln!(
+                "warning: report is {} bytes; a data URI this large may be truncated or rejected \
+                where it is pasted",
+                content.len()
+            );
+        }
+        println!("{}", data_uri(mime, content.as_bytes()));
+        return Ok(());
+    }
+    if gzip {
+        return write_gzip_output(&content, output);
+    }
+    write_text_output(content, output)
+}
+
+fn is_gzip_path(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Many chat and markdown renderers cap pasted text well below this.
+const DATA_URI_WARN_BYTES: usize = 1024 * 1024;
+
+/// Goes to stderr so piped stdout output stays clean.
+fn print_summary(tree: &Tree, options: &GraphOptions) {
+    if !options.quiet {
+        eprintln!("{}", summary_line(tree));
+    }
+}
+
+/// For layout bug reports: lays the tree out once more and lists what `verify_layout` finds.
+fn report_layout_problems(tree: &Tree, options: &GraphOptions) {
+    if !options.verify_layout {
+        return;
+    }
+    let rects = layout_tree(tree, &options.layout);
+    let problems = verify_layout(&rects, &options.layout);
+    for problem in &problems {
+        eprintln!("layout: {problem}");
+    }
+    eprintln!("layout check: {} rects, {} problems", rects.len(), problems.len());
+}
+
+/// Points at the thinnest leaf rect when `--min-aspect-warning` is given and it is too thin.
+fn report_slivers(tree: &Tree, options: &GraphOptions) {
+    let Some(threshold) = options.min_aspect_warning else {
+        return;
+    };
+    // The layout already ran once with --verbose; don't trace it twice.
+    let config = LayoutConfig {
+        verbose: false,
+        ..options.layout
+    };
+    if let Some(warning) = sliver_warning(&layout_tree(tree, &config), threshold) {
+        eprintln!("hint: {warning}");
+    }
+}
+
+/// Logs above this size are not embedded; they would dwarf the report itself.
+const MAX_EMBEDDED_SOURCE_BYTES: usize = 5 * 1024 * 1024;
+
+fn attach_source(options: &mut GraphOptions, text: &str) {
+    if !options.embed_source {
+        return;
+    }
+    if text.len() > MAX_EMBEDDED_SOURCE_BYTES {
+        eprintln!(
+            "warning: import log is {} bytes, skipping --embed-source (limit {} bytes)",
+            text.len(),
+            MAX_EMBEDDED_SOURCE_BYTES
+        );
+        return;
+    }
+    options.render.source = Some(text.to_string());
+}
+
+fn find_python_script(args: &[String]) -> Option<PathBuf> {
+    let candidate = args.first()?;
+    if candidate.starts_with('-') {
+        return None;
+    }
+    let path = Path::new(candidate);
+    if path.is_file() && is_python_shebang(path).unwrap_or(false) {
+        return Some(path.to_path_buf());
+    }
+    let path = find_in_path(candidate)?;
+    if is_python_shebang(&path).unwrap_or(false) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path_var = std::env::var_os("PATH")?;
+    for dir in std::env::split_paths(&path_var) {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+fn is_python_shebang(path: &Path) -> Result<bool> {
+    let Some(shebang) = read_shebang(path)? else {
+        return Ok(false);
+    };
+    let lower = shebang.to_ascii_lowercase();
+    Ok(lower.contains("python"))
+}
+
+fn read_shebang(path: &Path) -> Result<Option<String>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(line.strip_prefix("#!").map(|rest| rest.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    fn make_temp_dir() -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        dir.push(format!("pyimporttime-test-{}-{}", std::process::id(), nanos));
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn executable_prefers_shebang_script() {
+        let dir = make_temp_dir();
+        let script = dir.join("vs");
+        fs::write(&script, "#!/usr/bin/env python\nprint('hi')\n").unwrap();
+
+        let args = vec![
+            script.to_string_lossy().to_string(),
+            "arg1".to_string(),
+        ];
+        let exe = Executable::from_python_and_args("python", &args).unwrap();
+
+        assert_eq!(exe.path, script);
+        assert_eq!(exe.args, vec!["arg1".to_string()]);
+
+        fs::remove_file(&exe.path).unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn bare_interpreter_keeps_shebang_words_ahead_of_importtime_flag() {
+        let dir = make_temp_dir();
+        let script = dir.join("script.py");
+        fs::write(&script, "#!/usr/bin/env python3\nprint('hi')\n").unwrap();
+        let args = vec![script.to_string_lossy().to_string()];
+        let exe = Executable::from_python_and_args("python", &args).unwrap();
+
+        let bare = exe.bare_interpreter("python");
+        let cmd = profiling_command(&bare, ProfileVia::Flag);
+        assert_eq!(cmd.get_program(), "/usr/bin/env");
+        let cmd_args: Vec<&std::ffi::OsStr> = cmd.get_args().collect();
+        assert_eq!(cmd_args, vec!["python3", "-c", ""]);
+        assert!(cmd.get_envs().any(|(key, _)| key == "PYTHONPROFILEIMPORTTIME"));
+
+        let plain = Executable::from_python_and_args("python3", &[]).unwrap();
+        let cmd = profiling_command(&plain.bare_interpreter("python3"), ProfileVia::Flag);
+        let cmd_args: Vec<&std::ffi::OsStr> = cmd.get_args().collect();
+        assert_eq!(cmd_args, vec!["-X", "importtime", "-c", ""]);
+
+        fs::remove_file(&script).unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn executable_falls_back_to_python() {
+        let args = vec!["-c".to_string(), "print('hi')".to_string()];
+        let exe = Executable::from_python_and_args("python3", &args).unwrap();
+
+        assert_eq!(exe.path, PathBuf::from("python3"));
+        assert_eq!(exe.args, args);
+    }
+
+    #[test]
+    fn executable_resolves_script_from_path() {
+        let dir = make_temp_dir();
+        let script = dir.join("jp");
+        fs::write(&script, "#!/usr/bin/env python\nprint('hi')\n").unwrap();
+
+        let original_path = std::env::var_os("PATH");
+        unsafe {
+            std::env::set_var("PATH", &dir);
+        }
+
+        let args = vec!["jp".to_string(), "--help".to_string()];
+        let exe = Executable::from_python_and_args("python", &args).unwrap();
+
+        assert_eq!(exe.path, script);
+        assert_eq!(exe.args, vec!["--help".to_string()]);
+
+        unsafe {
+            match original_path {
+                Some(path) => std::env::set_var("PATH", path),
+                None => std::env::remove_var("PATH"),
+            }
+        }
+        fs::remove_file(&exe.path).unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_renders_on_nonzero_exit() {
+        let dir = make_temp_dir();
+        let script = dir.join("fake-python");
+        let output = dir.join("out.html");
+        let script_body = "\
+#!/bin/sh
+echo \"import time: self [us] | cumulative | imported package\" 1>&2
+echo \"import time:       1 |          1 | a\" 1>&2
+exit 2
+";
+        fs::write(&script, script_body).unwrap();
+        let mut perms = fs::metadata(&script).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&script, perms).unwrap();
+
+        let result = run_command(
+            script.to_str().unwrap(),
+            Vec::new(),
+            Some(output.clone()),
+            false,
+            RunOptions::default(),
+            GraphOptions::default(),
+        );
+
+        assert!(result.is_ok());
+        assert!(output.is_file());
+
+        fs::remove_file(&output).unwrap();
+        fs::remove_file(&script).unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_interpreter_version() {
+        assert_eq!(
+            parse_interpreter_version("CPython 3.12.3\n"),
+            Some("CPython 3.12.3".to_string())
+        );
+        assert_eq!(
+            parse_interpreter_version("PyPy 3.10.14\n"),
+            Some("PyPy 3.10.14".to_string())
+        );
+        assert_eq!(parse_interpreter_version("hello\n"), None);
+        assert_eq!(parse_interpreter_version(""), None);
+    }
+
+    #[test]
+    fn via_flag_injects_importtime_option() {
+        let args = vec!["-c".to_string(), "import json".to_string()];
+        let exe = Executable::from_python_and_args("python3", &args).unwrap();
+
+        let cmd = profiling_command(&exe, ProfileVia::Flag);
+        let cmd_args: Vec<&std::ffi::OsStr> = cmd.get_args().collect();
+        assert_eq!(cmd_args, vec!["-X", "importtime", "-c", "import json"]);
+        assert!(cmd.get_envs().all(|(key, _)| key != "PYTHONPROFILEIMPORTTIME"));
+
+        let cmd = profiling_command(&exe, ProfileVia::Env);
+        let cmd_args: Vec<&std::ffi::OsStr> = cmd.get_args().collect();
+        assert_eq!(cmd_args, vec!["-c", "import json"]);
+        assert!(cmd.get_envs().any(|(key, _)| key == "PYTHONPROFILEIMPORTTIME"));
+    }
+
+    #[test]
+    fn gzip_applies_to_every_format() {
+        use std::io::Read;
+
+        let tree = crate::tree::build_tree("import time:       10 |         10 | json\n").unwrap();
+        let dir = make_temp_dir();
+        let options = GraphOptions {
+            gzip: true,
+            ..GraphOptions::default()
+        };
+        let output = dir.join("r.json");
+        write_graph(&tree, Some(output.clone()), false, OutputFormat::Json, &options).unwrap();
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(fs::File::open(&output).unwrap())
+            .read_to_string(&mut json)
+            .unwrap();
+        assert!(json.contains("\"meta\""));
+
+        let output = dir.join("r.csv.gz");
+        let format = OutputFormat::Auto.resolve(Some(&output));
+        write_graph(&tree, Some(output.clone()), false, format, &GraphOptions::default()).unwrap();
+        let mut csv = String::new();
+        flate2::read::GzDecoder::new(fs::File::open(&output).unwrap())
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert!(csv.contains("json"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn input_labels_tell_same_named_files_apart() {
+        let inputs = [
+            "svc/a/import.log",
+            "svc/b/import.log",
+            "other/import.log",
+            "-",
+            "time",
+            "time",
+        ]
+        .map(String::from);
+        assert_eq!(
+            input_labels(&inputs),
+            [
+                "a/import.log",
+                "b/import.log",
+                "other/import.log",
+                "stdin",
+                "time #5",
+                "time #6"
+            ]
+        );
+    }
+
+    #[test]
+    fn auto_format_follows_output_extension() {
+        let resolve = |output: Option<&str>| OutputFormat::Auto.resolve(output.map(Path::new));
+        assert_eq!(resolve(Some("report.json")), OutputFormat::Json);
+        assert_eq!(resolve(Some("report.SVG")), OutputFormat::Svg);
+        assert_eq!(resolve(Some("report.csv")), OutputFormat::Csv);
+        assert_eq!(resolve(Some("report.html")), OutputFormat::Html);
+        assert_eq!(resolve(Some("report.json.gz")), OutputFormat::Json);
+        assert_eq!(resolve(Some("report.html.gz")), OutputFormat::Html);
+        assert_eq!(resolve(Some("report.gz")), OutputFormat::Html);
+        assert_eq!(resolve(None), OutputFormat::Html);
+        assert_eq!(
+            OutputFormat::Html.resolve(Some(Path::new("report.json"))),
+            OutputFormat::Html
+        );
+    }
+
+    #[test]
+    fn multi_word_python_splits_into_launcher_args() {
+        let args = vec!["script.py".to_string(), "--flag".to_string()];
+        let exe = Executable::from_python_and_args("uv  run", &args).unwrap();
+
+        assert_eq!(exe.path, PathBuf::from("uv"));
+        assert_eq!(exe.launcher_args, vec!["run".to_string()]);
+        assert_eq!(exe.args, args);
+        let cmd = profiling_command(&exe, ProfileVia::Env);
+        let cmd_args: Vec<&std::ffi::OsStr> = cmd.get_args().collect();
+        assert_eq!(cmd_args, vec!["run", "script.py", "--flag"]);
+    }
+
+    #[test]
+    fn graph_skips_rerender_when_output_is_current() {
+        let dir = make_temp_dir();
+        let input = dir.join("import.log");
+        let output = dir.join("report.svg");
+        fs::write(
+            &input,
+            "import time: self [us] | cumulative | imported package\n\
+             import time:       10 |         10 | json\n",
+        )
+        .unwrap();
+        let render = |cache| {
+            let options = GraphOptions {
+                quiet: true,
+                ..GraphOptions::default()
+            };
+            let input = [input.to_str().unwrap().to_string()];
+            let target = Some(output.clone());
+            let source = RecordSource::default();
+            graph_command(&input, target, false, OutputFormat::Auto, source, cache, options)
+                .unwrap();
+            fs::read_to_string(&output).unwrap()
+        };
+
+        // A sentinel shows whether the output was written again, whatever the mtime resolution.
+        let sentinel = "not rendered here";
+        assert!(render(OutputCache::Reuse).contains("<svg"));
+        assert!(dir.join("report.svg.hash").is_file());
+        fs::write(&output, sentinel).unwrap();
+        assert_eq!(render(OutputCache::Reuse), sentinel);
+        assert!(render(OutputCache::Refresh).contains("<svg"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn open_is_rejected_for_non_html_formats() {
+        let input = ["/nonexistent/import.log".to_string()];
+        let err = graph_command(
+            &input,
+            None,
+            true,
+            OutputFormat::Json,
+            RecordSource::default(),
+            OutputCache::Reuse,
+            GraphOptions::default(),
+        )
+        .expect_err("json with --open");
+        assert!(err.to_string().contains("--open only applies to HTML output"));
+        let err = graph_command(
+            &input,
+            Some(PathBuf::from("report.svg")),
+            true,
+            OutputFormat::Auto,
+            RecordSource::default(),
+            OutputCache::Reuse,
+            GraphOptions::default(),
+        )
+        .expect_err("svg with --open");
+        assert!(err.to_string().contains("not svg"));
+    }
+
+    #[test]
+    fn normalized_is_rejected_for_non_json_formats() {
+        let input = ["/nonexistent/import.log".to_string()];
+        let graph = |format| {
+            let mut options = GraphOptions::default();
+            options.render.normalized = true;
+            graph_command(
+                &input,
+                None,
+                false,
+                format,
+                RecordSource::default(),
+                OutputCache::Reuse,
+                options,
+            )
+        };
+        let err = graph(OutputFormat::Html).expect_err("html with --normalized");
+        assert!(err.to_string().contains("--normalized only applies to JSON output, not html"));
+        // JSON gets past the check and fails on the missing input instead.
+        let err = graph(OutputFormat::Json).expect_err("missing input");
+        assert!(!err.to_string().contains("--normalized"));
+    }
+
+    #[test]
+    fn dry_run_describes_resolved_command() {
+        let args = vec!["-c".to_string(), "import json".to_string()];
+        let exe = Executable::from_python_and_args("uv run", &args).unwrap();
+
+        let description = describe_command(&profiling_command(&exe, ProfileVia::Env));
+        assert_eq!(
+            description,
+            "program: uv\n\
+             args: [\"run\", \"-c\", \"import json\"]\n\
+             env: PYTHONPROFILEIMPORTTIME=1"
+        );
+
+        // The baseline keeps `run` as a launcher argument, so `--via flag` falls back to the
+        // environment instead of putting `-X importtime` between `uv` and `run`.
+        let bare = exe.bare_interpreter("uv run");
+        let description = describe_command(&profiling_command(&bare, ProfileVia::Flag));
+        assert_eq!(
+            description,
+            "program: uv\n\
+             args: [\"run\", \"-c\", \"\"]\n\
+             env: PYTHONPROFILEIMPORTTIME=1"
+        );
+    }
+
+    #[test]
+    fn recorded_command_appears_in_json_meta() {
+        let args = vec!["-c".to_string(), "import json".to_string()];
+        let exe = Executable::from_python_and_args("python3", &args).unwrap();
+        let command = recorded_command(&profiling_command(&exe, ProfileVia::Flag)).unwrap();
+        assert_eq!(Some(PathBuf::from(&command.program)), find_in_path("python3"));
+        assert!(Path::new(&command.program).is_absolute());
+        let tree = crate::tree::build_tree("import time:       10 |         10 | json\n").unwrap();
+        let render = RenderOptions {
+            command: Some(command),
+            ..RenderOptions::default()
+        };
+        let graph = build_graph_json(&tree, &LayoutConfig::default(), &render).unwrap();
+        let meta = &serde_json::to_value(&graph).unwrap()["meta"];
+        let args = serde_json::json!(["-X", "importtime", "-c", "import json"]);
+        assert_eq!(meta["command"]["args"], args);
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(meta["command"]["cwd"], cwd.display().to_string());
+        let html = build_graph_html(&tree, &LayoutConfig::default(), &render).unwrap();
+        assert!(html.contains(" -X importtime -c &apos;import json&apos;"), "{html}");
+        let plain = build_graph_json(&tree, &LayoutConfig::default(), &RenderOptions::default())
+            .unwrap();
+        assert!(serde_json::to_value(&plain).unwrap()["meta"].get("command").is_none());
+    }
+
+    #[test]
+    fn optional_value_flags_leave_the_next_argument_alone() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct TestCli {
+            input: Option<String>,
+            #[command(flatten)]
+            graph: GraphArgs,
+        }
+
+        let cli = TestCli::parse_from(["test", "--min-aspect-warning", "log.txt"]);
+        assert_eq!(cli.input.as_deref(), Some("log.txt"));
+        assert_eq!(cli.graph.options().min_aspect_warning, Some(20.0));
+        let cli = TestCli::parse_from(["test", "--min-aspect-warning=8", "log.txt"]);
+        assert_eq!(cli.graph.options().min_aspect_warning, Some(8.0));
+        let cli = TestCli::parse_from(["test", "--subtract-overhead", "log.txt"]);
+        assert_eq!(cli.input.as_deref(), Some("log.txt"));
+        assert_eq!(cli.graph.options().tree.subtract_overhead_us, Some(2));
+        let cli = TestCli::parse_from(["test", "--subtract-overhead=5", "log.txt"]);
+        assert_eq!(cli.graph.options().tree.subtract_overhead_us, Some(5));
+    }
+
+    #[test]
+    fn tree_flag_defaults_match_tree_options_default() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct TestCli {
+            #[command(flatten)]
+            tree: TreeArgs,
+        }
+
+        let cli = TestCli::parse_from(["test"]);
+        assert_eq!(format!("{:?}", cli.tree.options()), format!("{:?}", TreeOptions::default()));
+    }
+
+    #[test]
+    fn config_file_sets_defaults_and_flags_override_it() {
+        use clap::{CommandFactory, FromArgMatches, Parser};
+
+        #[derive(Parser)]
+        struct TestCli {
+            #[command(flatten)]
+            graph: GraphArgs,
+        }
+
+        let dir = make_temp_dir();
+        let config = dir.join("pyimporttime.toml");
+        fs::write(&config, "width = 1000\nheight = 500\nsort_children = \"name\"\n").unwrap();
+        let config_arg = config.to_str().unwrap();
+        let matches = TestCli::command()
+            .try_get_matches_from(["test", "--config", config_arg, "--width", "1234"])
+            .unwrap();
+        let mut cli = TestCli::from_arg_matches(&matches).unwrap();
+        cli.graph.apply_config_file(&matches).unwrap();
+
+        let layout = cli.graph.options().layout;
+        assert_eq!(layout.width, 1234.0);
+        assert_eq!(layout.height, 500.0);
+        assert_eq!(layout.sort_children, SortChildren::Name);
+
+        fs::write(&config, "widht = 1\n").unwrap();
+        let mut cli = TestCli::from_arg_matches(&matches).unwrap();
+        assert!(cli.graph.apply_config_file(&matches).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,35 @@
-use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-use crate::parser::{parse_import_time, ImportRecord};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use crate::parser::parse_import_time;
+use crate::parser::{is_bootstrap_module, is_builtin_module, GraphReport, ImportRecord};
+
+/// What a tree node stands for; only `Module` nodes are named after a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Module,
+    /// The root, or a node grouping other records, such as one input file of a combined log.
+    Group,
+    /// A module's time spent in its own body, named "self".
+    SelfTime,
+    /// A later import of a module already shown, collapsed by `dedupe_subtrees`.
+    Duplicate,
+}
 
 #[derive(Debug)]
 pub struct ArenaNode {
     pub(crate) name: String,
+    pub(crate) kind: NodeKind,
     pub(crate) cumulative_us: u64,
     pub(crate) parent: Option<usize>,
     pub(crate) children: Vec<usize>,
+    /// 1-based position of the module's line in the log; `None` for the root and self nodes.
+    pub(crate) order: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -15,6 +37,8 @@ pub struct Tree {
     pub(crate) arena: Vec<ArenaNode>,
     pub(crate) root: usize,
     pub(crate) totals: Vec<u64>,
+    /// The options the tree was built with, kept so a report can be rebuilt the same way.
+    pub(crate) options: TreeOptions,
 }
 
 impl Tree {
@@ -22,29 +46,349 @@ impl Tree {
         self.totals[self.root]
     }
 
+    #[cfg_attr(not(feature = "render"), allow(dead_code))]
     pub(crate) fn sum_children(&self, index: usize) -> u64 {
         self.totals[index]
     }
+
+    /// What the node's children (self node included) add up to, ignoring the node's own
+    /// cumulative time; a node without children counts its cumulative time.
+    pub(crate) fn children_total(&self, index: usize) -> u64 {
+        let children = &self.arena[index].children;
+        if children.is_empty() {
+            return self.arena[index].cumulative_us;
+        }
+        children.iter().map(|child| self.totals[*child]).sum()
+    }
+
+    pub(crate) fn recompute_totals(&mut self) {
+        let mut totals = vec![0; self.arena.len()];
+        compute_totals(&self.arena, self.root, &mut totals);
+        self.totals = totals;
+    }
+
+    /// Number of modules imported beneath each node, self nodes excluded. Relies on
+    /// children always being stored after their parent in the arena.
+    #[cfg_attr(not(feature = "render"), allow(dead_code))]
+    pub(crate) fn descendant_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.arena.len()];
+        for index in (0..self.arena.len()).rev() {
+            let node = &self.arena[index];
+            if let Some(parent) = node.parent
+                && node.name != "self"
+            {
+                counts[parent] += counts[index] + 1;
+            }
+        }
+        counts
+    }
+
+    /// Sum of every self node under the root: time spent in module bodies rather than
+    /// attributed to nesting.
+    pub fn total_self_us(&self) -> u64 {
+        let mut total = 0;
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            let node = &self.arena[index];
+            if node.name == "self" {
+                total += node.cumulative_us;
+            }
+            stack.extend(node.children.iter().copied());
+        }
+        total
+    }
+
+    pub(crate) fn self_us(&self, index: usize) -> u64 {
+        self.arena[index]
+            .children
+            .iter()
+            .map(|child| &self.arena[*child])
+            .find(|child| child.name == "self")
+            .map_or(0, |child| child.cumulative_us)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleCost {
+    pub name: String,
+    pub self_us: u64,
+    pub cumulative_us: u64,
+}
+
+/// Direct children of the root, most expensive first. These are the imports the
+/// program triggered itself, which makes them the natural lazy-import candidates.
+pub fn top_level_imports(tree: &Tree) -> Vec<ModuleCost> {
+    let mut modules: Vec<ModuleCost> = tree.arena[tree.root]
+        .children
+        .iter()
+        .filter(|index| tree.arena[**index].name != "self")
+        .map(|index| ModuleCost {
+            name: tree.arena[*index].name.clone(),
+            self_us: tree.self_us(*index),
+            cumulative_us: tree.arena[*index].cumulative_us,
+        })
+        .collect();
+    modules.sort_by(|a, b| {
+        b.cumulative_us
+            .cmp(&a.cumulative_us)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    modules
+}
+
+/// One module on the critical path, see `critical_path`.
+#[derive(Debug, Clone)]
+pub struct PathStep {
+    pub name: String,
+    pub self_us: u64,
+    pub cumulative_us: u64,
+    /// 1 for the top-level import.
+    pub depth: usize,
+}
+
+/// The hottest chain of imports: from the root, repeatedly the child module with the largest
+/// total, ties going to the one logged first, down to a module that imports nothing.
+pub fn critical_path(tree: &Tree) -> Vec<PathStep> {
+    let mut steps = Vec::new();
+    let mut index = tree.root;
+    loop {
+        let next = tree.arena[index]
+            .children
+            .iter()
+            .copied()
+            .filter(|child| tree.arena[*child].name != "self")
+            .max_by(|a, b| {
+                tree.totals[*a]
+                    .cmp(&tree.totals[*b])
+                    .then_with(|| tree.arena[*b].order.cmp(&tree.arena[*a].order))
+            });
+        let Some(next) = next else {
+            return steps;
+        };
+        steps.push(PathStep {
+            name: tree.arena[next].name.clone(),
+            self_us: tree.self_us(next),
+            cumulative_us: tree.arena[next].cumulative_us,
+            depth: steps.len() + 1,
+        });
+        index = next;
+    }
+}
+
+/// Where a module's self time comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelfDefinition {
+    /// The log's self column.
+    #[default]
+    Reported,
+    /// Cumulative time minus the cumulative times of the module's imports, clamped at zero,
+    /// which also counts overhead the self column leaves out; see `residual_self_times`.
+    Residual,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TreeOptions {
+    pub dedupe: bool,
+    /// Keep builtin modules (see `is_builtin_module`); they are dropped otherwise.
+    pub include_builtin: bool,
+    /// Fold `BOOTSTRAP_MODULES` and everything they imported into the root's self time.
+    pub hide_bootstrap: bool,
+    /// Re-root the tree at the first import of this module; see `reroot`.
+    pub root: Option<String>,
+    /// Drop everything logged before the first import of this module; see `records_since`.
+    pub since: Option<String>,
+    /// Merge chains of modules that import exactly one other module; see
+    /// `collapse_single_child_chains`.
+    pub collapse_single_child: bool,
+    /// Take this many microseconds of profiling overhead off every record's self time; see
+    /// `subtract_overhead`.
+    pub subtract_overhead_us: Option<u64>,
+    pub self_definition: SelfDefinition,
+}
+
+/// Matches the command line's defaults, so every subcommand counts bootstrap time the same.
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            dedupe: false,
+            include_builtin: false,
+            hide_bootstrap: true,
+            root: None,
+            since: None,
+            collapse_single_child: false,
+            subtract_overhead_us: None,
+            self_definition: SelfDefinition::default(),
+        }
+    }
+}
+
+#[cfg(test)]
 pub fn build_tree(text: &str) -> Result<Tree> {
-    let mut records = parse_import_time(text)?;
+    build_tree_from_parsed(parse_import_time(text)?, &TreeOptions::default())
+}
+
+/// Builds a tree from records in log order, as returned by `parse_import_time`.
+pub fn build_tree_from_parsed(
+    records: Vec<ImportRecord>,
+    options: &TreeOptions,
+) -> Result<Tree> {
+    let records = match &options.since {
+        Some(name) => records_since(records, name)?,
+        None => records,
+    };
+    let records = match options.self_definition {
+        SelfDefinition::Reported => records,
+        SelfDefinition::Residual => residual_self_times(records),
+    };
+    let records = match options.subtract_overhead_us {
+        Some(overhead_us) => subtract_overhead(records, overhead_us),
+        None => records,
+    };
+    // Number records before filtering so the order matches the log lines.
+    let mut records: Vec<(usize, ImportRecord)> =
+        records.into_iter().enumerate().map(|(index, record)| (index + 1, record)).collect();
     // Import time logs are emitted after child imports complete, so reverse to build a pre-order tree.
     records.reverse();
-    build_tree_from_records(&records)
+    let mut root_self_us = if options.hide_bootstrap {
+        drop_subtrees(&mut records, is_bootstrap_module)
+    } else {
+        0
+    };
+    if !options.include_builtin {
+        root_self_us += drop_modules(&mut records, is_builtin_module);
+    }
+    let mut tree = build_tree_from_records(&records)?;
+    tree.options = options.clone();
+    add_root_self_time(&mut tree, root_self_us);
+    if options.dedupe {
+        dedupe_subtrees(&mut tree);
+    }
+    if let Some(name) = &options.root {
+        reroot(&mut tree, name)?;
+    }
+    if options.collapse_single_child {
+        collapse_single_child_chains(&mut tree);
+    }
+    Ok(tree)
+}
+
+/// Builds the tree stored in a graph JSON report as it was, without filtering its records
+/// again, since `tree_options` were applied before they were written.
+#[cfg_attr(not(feature = "render"), allow(dead_code))]
+pub fn build_tree_from_report(report: GraphReport) -> Result<Tree> {
+    let records: Vec<(usize, ImportRecord)> = report
+        .records
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| (index + 1, record))
+        .rev()
+        .collect();
+    let mut tree = build_tree_from_records(&records)?;
+    tree.options = report.tree_options;
+    add_root_self_time(&mut tree, report.root_self_us);
+    Ok(tree)
+}
+
+/// Gives the root a self node holding `us`, time that belongs to no module.
+fn add_root_self_time(tree: &mut Tree, us: u64) {
+    if us == 0 {
+        return;
+    }
+    let self_index = tree.arena.len();
+    tree.arena.push(ArenaNode {
+        name: "self".to_string(),
+        kind: NodeKind::SelfTime,
+        cumulative_us: us,
+        parent: Some(tree.root),
+        children: Vec::new(),
+        order: None,
+    });
+    let root = tree.root;
+    tree.arena[root].children.push(self_index);
+    tree.recompute_totals();
+}
+
+/// Drops modules matching `is_dropped` and their subtrees from pre-order `records`, taking
+/// their time out of every ancestor, and returns the total time removed.
+fn drop_subtrees(records: &mut Vec<(usize, ImportRecord)>, is_dropped: fn(&str) -> bool) -> u64 {
+    let mut kept: Vec<(usize, ImportRecord)> = Vec::with_capacity(records.len());
+    // Indices into `kept` of the ancestors of the current record.
+    let mut ancestors: Vec<usize> = Vec::new();
+    let mut skip_below = None;
+    let mut folded = 0;
+    for (order, record) in records.drain(..) {
+        if let Some(depth) = skip_below {
+            if record.depth > depth {
+                continue;
+            }
+            skip_below = None;
+        }
+        while ancestors.last().is_some_and(|&index| kept[index].1.depth >= record.depth) {
+            ancestors.pop();
+        }
+        if !record.synthetic && is_dropped(&record.name) {
+            for &index in &ancestors {
+                let ancestor = &mut kept[index].1;
+                ancestor.cumulative_us =
+                    ancestor.cumulative_us.saturating_sub(record.cumulative_us);
+            }
+            folded += record.cumulative_us;
+            skip_below = Some(record.depth);
+            continue;
+        }
+        ancestors.push(kept.len());
+        kept.push((order, record));
+    }
+    *records = kept;
+    folded
 }
 
-fn build_tree_from_records(records: &[ImportRecord]) -> Result<Tree> {
+/// Drops modules matching `is_dropped` from pre-order `records` but keeps what they
+/// imported, one level up. A dropped module's self time goes to the module that imported
+/// it, so no time is lost; the self time of dropped top-level modules is returned for the
+/// root.
+fn drop_modules(records: &mut Vec<(usize, ImportRecord)>, is_dropped: fn(&str) -> bool) -> u64 {
+    let mut kept: Vec<(usize, ImportRecord)> = Vec::with_capacity(records.len());
+    // Logged depth of each ancestor of the current record, with its index into `kept`, or
+    // `None` if it was dropped.
+    let mut ancestors: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut root_self_us = 0;
+    for (order, mut record) in records.drain(..) {
+        while ancestors.last().is_some_and(|&(depth, _)| depth >= record.depth) {
+            ancestors.pop();
+        }
+        if !record.synthetic && is_dropped(&record.name) {
+            match ancestors.iter().rev().find_map(|&(_, index)| index) {
+                Some(parent) => kept[parent].1.self_us += record.self_us,
+                None => root_self_us += record.self_us,
+            }
+            ancestors.push((record.depth, None));
+            continue;
+        }
+        let dropped_ancestors = ancestors.iter().filter(|(_, index)| index.is_none()).count();
+        ancestors.push((record.depth, Some(kept.len())));
+        record.depth -= dropped_ancestors;
+        kept.push((order, record));
+    }
+    *records = kept;
+    root_self_us
+}
+
+fn build_tree_from_records(records: &[(usize, ImportRecord)]) -> Result<Tree> {
     let mut arena = Vec::new();
     arena.push(ArenaNode {
         name: "Total".to_string(),
+        kind: NodeKind::Group,
         cumulative_us: 0,
         parent: None,
         children: Vec::new(),
+        order: None,
     });
     let root = 0;
     let mut stack: Vec<usize> = vec![root];
-    for record in records {
+    for (order, record) in records {
         while stack.len() > record.depth {
             stack.pop();
         }
@@ -52,18 +396,22 @@ fn build_tree_from_records(records: &[ImportRecord]) -> Result<Tree> {
         let node_index = arena.len();
         arena.push(ArenaNode {
             name: record.name.clone(),
+            kind: if record.synthetic { NodeKind::Group } else { NodeKind::Module },
             cumulative_us: record.cumulative_us,
             parent: Some(parent),
             children: Vec::new(),
+            order: Some(*order),
         });
         arena[parent].children.push(node_index);
         if record.self_us > 0 {
             let self_index = arena.len();
             arena.push(ArenaNode {
                 name: "self".to_string(),
+                kind: NodeKind::SelfTime,
                 cumulative_us: record.self_us,
                 parent: Some(node_index),
                 children: Vec::new(),
+                order: None,
             });
             arena[node_index].children.push(self_index);
         }
@@ -73,31 +421,456 @@ fn build_tree_from_records(records: &[ImportRecord]) -> Result<Tree> {
         arena,
         root,
         totals: Vec::new(),
+        options: TreeOptions::default(),
     };
-    let mut totals = vec![0; tree.arena.len()];
-    compute_totals(&tree.arena, root, &mut totals);
-    tree.totals = totals;
+    tree.recompute_totals();
     Ok(tree)
 }
 
-fn compute_totals(arena: &[ArenaNode], index: usize, totals: &mut [u64]) -> u64 {
-    let node = &arena[index];
-    if node.children.is_empty() {
-        totals[index] = node.cumulative_us;
-        return totals[index];
+/// Moves `records` one level down under a synthetic top-level node called `name`, so logs
+/// from several programs can share one tree. The new node has no self time of its own and
+/// is left alone by the filters and dedupe, whatever its name.
+pub fn nest_records(records: Vec<ImportRecord>, name: &str) -> Vec<ImportRecord> {
+    let cumulative_us = records
+        .iter()
+        .filter(|record| record.depth == 1)
+        .map(|record| record.cumulative_us)
+        .sum();
+    let mut nested: Vec<ImportRecord> = records
+        .into_iter()
+        .map(|record| ImportRecord {
+            depth: record.depth + 1,
+            ..record
+        })
+        .collect();
+    // Post-order: the parent line follows its children, as in CPython's log.
+    nested.push(ImportRecord {
+        name: name.to_string(),
+        self_us: 0,
+        cumulative_us,
+        depth: 1,
+        synthetic: true,
+    });
+    nested
+}
+
+/// The modules under the root as records in log order (each module after its children), the
+/// shape `parse_import_json` reads, so a tree written out can be built again. The root's own
+/// time, such as folded bootstrap imports, belongs to no module and is left out; it is
+/// `tree.self_us(tree.root)`.
+pub fn tree_records(tree: &Tree) -> Vec<ImportRecord> {
+    let mut records = Vec::new();
+    push_records(tree, tree.root, 0, &mut records);
+    records
+}
+
+fn push_records(tree: &Tree, index: usize, depth: usize, records: &mut Vec<ImportRecord>) {
+    // Children are stored newest-first.
+    for &child in tree.arena[index].children.iter().rev() {
+        if tree.arena[child].name != "self" {
+            push_records(tree, child, depth + 1, records);
+        }
+    }
+    if index != tree.root {
+        records.push(ImportRecord {
+            name: tree.arena[index].name.clone(),
+            self_us: tree.self_us(index),
+            cumulative_us: tree.arena[index].cumulative_us,
+            depth,
+            synthetic: tree.arena[index].kind == NodeKind::Group,
+        });
+    }
+}
+
+/// Keeps the first import of `name`, with the modules it imported, and everything logged
+/// after it, for logs captured partway through startup. Modules whose import was already
+/// under way when `name` started are dropped, since part of their time went to records that
+/// are gone, and the depths of the rest close up so `name` becomes a top-level import.
+pub fn records_since(records: Vec<ImportRecord>, name: &str) -> Result<Vec<ImportRecord>> {
+    let Some(first) = records.iter().position(|record| record.name == name) else {
+        return Err(anyhow!("module {name} not found in the import time log"));
+    };
+    // Post-order: the module's imports are the deeper records right before it.
+    let depth = records[first].depth;
+    let mut start = first;
+    while start > 0 && records[start - 1].depth > depth {
+        start -= 1;
+    }
+    // An enclosing import ends shallower than everything kept before it, and encloses all of
+    // that, so each kept record moves up one level per enclosing import dropped after it.
+    let mut min_depth = depth;
+    let mut kept: Vec<(ImportRecord, usize)> = Vec::new();
+    for (index, record) in records.into_iter().enumerate().skip(start) {
+        if index > first && record.depth < min_depth {
+            min_depth = record.depth;
+            for (_, enclosing) in &mut kept {
+                *enclosing += 1;
+            }
+            continue;
+        }
+        kept.push((record, 0));
+    }
+    Ok(kept
+        .into_iter()
+        .map(|(record, enclosing)| ImportRecord {
+            depth: record.depth - enclosing,
+            ..record
+        })
+        .collect())
+}
+
+/// Replaces each record's self time with its cumulative time minus its direct children's
+/// cumulative times, clamped at zero. Relies on the log's post-order, like `subtract_overhead`.
+pub fn residual_self_times(records: Vec<ImportRecord>) -> Vec<ImportRecord> {
+    // (depth, cumulative) of records whose parent has not been seen yet.
+    let mut open: Vec<(usize, u64)> = Vec::new();
+    records
+        .into_iter()
+        .map(|record| {
+            let mut children_us: u64 = 0;
+            while let Some((depth, cumulative)) = open.last().copied()
+                && depth > record.depth
+            {
+                open.pop();
+                if depth == record.depth + 1 {
+                    children_us += cumulative;
+                }
+            }
+            open.push((record.depth, record.cumulative_us));
+            ImportRecord {
+                self_us: record.cumulative_us.saturating_sub(children_us),
+                ..record
+            }
+        })
+        .collect()
+}
+
+/// Takes an estimated `overhead_us` that `-X importtime` itself adds to each import (timer
+/// calls and the log line) off every record's self time, clamped at zero, and the removed time
+/// off every ancestor's cumulative time. The overhead is a rough constant, not a measurement.
+pub fn subtract_overhead(records: Vec<ImportRecord>, overhead_us: u64) -> Vec<ImportRecord> {
+    // (depth, time removed from the subtree) of records whose parent has not been seen yet.
+    let mut open: Vec<(usize, u64)> = Vec::new();
+    records
+        .into_iter()
+        .map(|record| {
+            let mut removed = 0;
+            while let Some((depth, child_removed)) = open.last().copied()
+                && depth > record.depth
+            {
+                open.pop();
+                if depth == record.depth + 1 {
+                    removed += child_removed;
+                }
+            }
+            let self_removed = record.self_us.min(overhead_us);
+            removed += self_removed;
+            open.push((record.depth, removed));
+            ImportRecord {
+                self_us: record.self_us - self_removed,
+                cumulative_us: record.cumulative_us.saturating_sub(removed),
+                ..record
+            }
+        })
+        .collect()
+}
+
+/// Subtracts the cost each module had in a baseline run (typically the bare interpreter) so
+/// only time attributable to the profiled program remains. Modules are matched by name and
+/// clamped at zero.
+#[cfg_attr(not(feature = "render"), allow(dead_code))]
+pub fn subtract_baseline(records: &mut [ImportRecord], baseline: &[ImportRecord]) {
+    let baseline: HashMap<&str, &ImportRecord> = baseline
+        .iter()
+        .map(|record| (record.name.as_str(), record))
+        .collect();
+    for record in records.iter_mut() {
+        if let Some(base) = baseline.get(record.name.as_str()) {
+            record.self_us = record.self_us.saturating_sub(base.self_us);
+            record.cumulative_us = record.cumulative_us.saturating_sub(base.cumulative_us);
+        }
+    }
+}
+
+/// Top-level node for modules without a source file under the project root.
+pub const OUTSIDE_PROJECT: &str = "(outside project)";
+
+#[derive(Default)]
+struct DirNode {
+    self_us: u64,
+    children: BTreeMap<String, DirNode>,
+}
+
+/// Rolls module self times up by the directory holding each module's file, giving records for
+/// a directory tree topped by `root`'s own name. Modules without a file under `root` are pooled
+/// into `OUTSIDE_PROJECT` so the total is unchanged.
+#[cfg_attr(not(feature = "render"), allow(dead_code))]
+pub fn group_by_directory(
+    records: &[ImportRecord],
+    files: &HashMap<String, PathBuf>,
+    root: &Path,
+) -> Vec<ImportRecord> {
+    let root_name = root
+        .file_name()
+        .map_or_else(|| root.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let mut project = DirNode::default();
+    let mut outside = 0;
+    for record in records {
+        let directory = files
+            .get(&record.name)
+            .and_then(|file| file.parent())
+            .and_then(|dir| dir.strip_prefix(root).ok());
+        let Some(directory) = directory else {
+            outside += record.self_us;
+            continue;
+        };
+        let mut node = &mut project;
+        for component in directory.components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            node = node.children.entry(name).or_default();
+        }
+        node.self_us += record.self_us;
+    }
+    let mut grouped = Vec::new();
+    if !project.children.is_empty() || project.self_us > 0 {
+        push_directory(&root_name, &project, 1, &mut grouped);
+    }
+    if outside > 0 {
+        grouped.push(ImportRecord {
+            name: OUTSIDE_PROJECT.to_string(),
+            self_us: outside,
+            cumulative_us: outside,
+            depth: 1,
+            synthetic: false,
+        });
+    }
+    grouped
+}
+
+/// Appends `node` and its subdirectories in post-order, like CPython's log, and returns the
+/// directory's cumulative time.
+fn push_directory(name: &str, node: &DirNode, depth: usize, out: &mut Vec<ImportRecord>) -> u64 {
+    let mut cumulative_us = node.self_us;
+    for (child_name, child) in &node.children {
+        cumulative_us += push_directory(child_name, child, depth + 1, out);
+    }
+    out.push(ImportRecord {
+        name: name.to_string(),
+        self_us: node.self_us,
+        cumulative_us,
+        depth,
+        synthetic: false,
+    });
+    cumulative_us
+}
+
+/// Keeps the first occurrence of each module intact and collapses later occurrences into
+/// leaf reference nodes sized by their own cumulative time.
+pub fn dedupe_subtrees(tree: &mut Tree) -> usize {
+    let mut seen = HashSet::new();
+    let mut collapsed = 0;
+    let mut stack = vec![tree.root];
+    while let Some(index) = stack.pop() {
+        let node = &tree.arena[index];
+        if node.kind == NodeKind::Module && !seen.insert(node.name.clone()) {
+            let node = &mut tree.arena[index];
+            node.name = format!("{} (see first)", node.name);
+            node.kind = NodeKind::Duplicate;
+            // Detach the dropped children too, or `descendant_counts` still finds them.
+            for child in std::mem::take(&mut node.children) {
+                tree.arena[child].parent = None;
+            }
+            collapsed += 1;
+            continue;
+        }
+        // Children are stored newest-first, so pushing them in order pops the oldest first.
+        stack.extend(tree.arena[index].children.iter().copied());
+    }
+    if collapsed > 0 {
+        tree.recompute_totals();
+    }
+    collapsed
+}
+
+/// Merges every module that imports exactly one other module with that module, repeatedly,
+/// so a chain like `a > a.b > a.b.c` becomes one node named `a.b.c`. Names that don't extend
+/// each other are joined with " > ". The merged node keeps the top module's cumulative time,
+/// adds up the chain's self time and takes over the deepest module's children. Returns the
+/// number of modules merged away.
+pub fn collapse_single_child_chains(tree: &mut Tree) -> usize {
+    let mut collapsed = 0;
+    let mut stack = vec![tree.root];
+    while let Some(index) = stack.pop() {
+        if index != tree.root {
+            while let Some(child) = only_module_child(tree, index) {
+                absorb_child(tree, index, child);
+                collapsed += 1;
+            }
+        }
+        stack.extend(tree.arena[index].children.iter().copied());
+    }
+    if collapsed > 0 {
+        tree.recompute_totals();
+    }
+    collapsed
+}
+
+fn only_module_child(tree: &Tree, index: usize) -> Option<usize> {
+    let mut modules = tree.arena[index]
+        .children
+        .iter()
+        .copied()
+        .filter(|&child| tree.arena[child].name != "self");
+    let child = modules.next()?;
+    modules.next().is_none().then_some(child)
+}
+
+/// Moves `child`'s self time and children into `parent` and detaches `child`, leaving it
+/// unreachable with no parent so `descendant_counts` skips it.
+fn absorb_child(tree: &mut Tree, parent: usize, child: usize) {
+    let parent_self = tree.arena[parent]
+        .children
+        .iter()
+        .copied()
+        .find(|&index| tree.arena[index].name == "self");
+    let mut children: Vec<usize> = parent_self.into_iter().collect();
+    for grandchild in std::mem::take(&mut tree.arena[child].children) {
+        if let Some(parent_self) = parent_self
+            && tree.arena[grandchild].name == "self"
+        {
+            tree.arena[parent_self].cumulative_us += tree.arena[grandchild].cumulative_us;
+            tree.arena[grandchild].parent = None;
+            continue;
+        }
+        tree.arena[grandchild].parent = Some(parent);
+        children.push(grandchild);
+    }
+    let name = chain_label(&tree.arena[parent].name, &tree.arena[child].name);
+    tree.arena[child].parent = None;
+    let node = &mut tree.arena[parent];
+    node.name = name;
+    node.children = children;
+}
+
+fn chain_label(parent: &str, child: &str) -> String {
+    match child.strip_prefix(parent) {
+        Some(rest) if rest.starts_with('.') => child.to_string(),
+        _ => format!("{parent} > {child}"),
+    }
+}
+
+/// Makes the first import of `name`, in log order, the root so its subtree fills the whole
+/// canvas and its cumulative time becomes the total. Fails if the module is not in the tree.
+pub fn reroot(tree: &mut Tree, name: &str) -> Result<()> {
+    let mut stack = vec![tree.root];
+    while let Some(index) = stack.pop() {
+        let node = &tree.arena[index];
+        if index != tree.root && node.name == name {
+            tree.root = index;
+            return Ok(());
+        }
+        stack.extend(node.children.iter().copied());
     }
-    let mut sum = 0;
-    for child in &node.children {
-        sum += compute_totals(arena, *child, totals);
+    Err(anyhow!("module {name} not found in the import time log"))
+}
+
+#[derive(Debug, Clone)]
+pub struct ModulePath {
+    pub name: String,
+    /// Ancestor names from the top-level import down to and including this module.
+    pub path: Vec<String>,
+    pub self_us: u64,
+    pub cumulative_us: u64,
+    /// Self time plus the totals of the modules imported beneath it. Differs from
+    /// `cumulative_us` where the log's own cumulative column doesn't add up.
+    pub total_us: u64,
+}
+
+/// A render-independent listing for snapshot tests: one `path<TAB>self_us<TAB>cumulative_us`
+/// line per module, with the ancestor path joined by " > " and lines sorted, so reordering
+/// sibling imports in the log leaves the output byte-for-byte unchanged.
+pub fn canonical_listing(tree: &Tree) -> String {
+    let mut lines: Vec<String> = module_paths(tree)
+        .into_iter()
+        .map(|module| {
+            format!("{}\t{}\t{}", module.path.join(" > "), module.self_us, module.cumulative_us)
+        })
+        .collect();
+    lines.sort();
+    let mut listing = String::from("# path\tself_us\tcumulative_us\n");
+    for line in lines {
+        listing.push_str(&line);
+        listing.push('\n');
     }
-    totals[index] = sum;
-    sum
+    listing
+}
+
+/// Every module with its resolved ancestor chain, in the order the imports started.
+pub fn module_paths(tree: &Tree) -> Vec<ModulePath> {
+    let mut modules = Vec::new();
+    let mut stack = vec![(tree.root, Vec::new())];
+    while let Some((index, path)) = stack.pop() {
+        let node = &tree.arena[index];
+        for child in node.children.iter().copied() {
+            if tree.arena[child].name == "self" {
+                continue;
+            }
+            let mut child_path = path.clone();
+            child_path.push(tree.arena[child].name.clone());
+            stack.push((child, child_path));
+        }
+        if index != tree.root {
+            modules.push(ModulePath {
+                name: node.name.clone(),
+                path,
+                self_us: tree.self_us(index),
+                cumulative_us: node.cumulative_us,
+                total_us: tree.children_total(index),
+            });
+        }
+    }
+    modules
+}
+
+fn compute_totals(arena: &[ArenaNode], index: usize, totals: &mut [u64]) -> u64 {
+    // Iterative post-order traversal so very deep logs cannot overflow the stack.
+    let mut stack = vec![(index, false)];
+    while let Some((current, children_done)) = stack.pop() {
+        let node = &arena[current];
+        if node.children.is_empty() {
+            totals[current] = node.cumulative_us;
+        } else if children_done {
+            // A module's own cumulative time can exceed what its children (self node
+            // included) account for; the excess still belongs to the module.
+            let children: u64 = node.children.iter().map(|child| totals[*child]).sum();
+            totals[current] = children.max(node.cumulative_us);
+        } else {
+            stack.push((current, true));
+            stack.extend(node.children.iter().map(|child| (*child, false)));
+        }
+    }
+    totals[index]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn totals_keep_cumulative_beyond_children() {
+        // b reports 50 us cumulative, but only 5 us self plus 3 us for b.c.
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |         50 | b\n\
+import time:       10 |         10 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let b = tree.arena.iter().position(|node| node.name == "b").expect("b");
+        assert_eq!(tree.sum_children(b), 50);
+        assert_eq!(tree.children_total(b), 8);
+        assert_eq!(tree.total_us(), 60);
+    }
+
     #[test]
     fn build_tree_includes_self_nodes() {
         let log = "\
@@ -131,4 +904,449 @@ import time:        2 |          3 | parent\n";
             .expect("child");
         assert!(tree.arena[parent_index].children.contains(&child_index));
     }
+
+    #[test]
+    fn top_level_imports_sorted_by_cumulative() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       10 |         10 | a\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |         15 | b\n";
+        let tree = build_tree(log).expect("tree");
+        let modules = top_level_imports(&tree);
+        let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a"]);
+        assert_eq!(modules[0].self_us, 5);
+        assert_eq!(modules[0].cumulative_us, 15);
+    }
+
+    #[test]
+    fn dedupe_collapses_repeated_module() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        4 |          4 |     pydantic.main\n\
+import time:        6 |         10 |   pydantic\n\
+import time:        1 |         11 | app\n\
+import time:        3 |          3 |     pydantic.main\n\
+import time:        2 |          5 |   pydantic\n\
+import time:        1 |          6 | worker\n";
+        let mut tree = build_tree(log).expect("tree");
+        assert_eq!(dedupe_subtrees(&mut tree), 1);
+        let collapsed = tree
+            .arena
+            .iter()
+            .position(|node| node.name == "pydantic (see first)")
+            .expect("collapsed reference");
+        assert!(tree.arena[collapsed].children.is_empty());
+        assert_eq!(tree.totals[collapsed], 5);
+        let app = tree.arena.iter().position(|node| node.name == "app").expect("app");
+        assert_eq!(tree.totals[app], 11);
+        assert_eq!(tree.total_us(), 17);
+        let counts = tree.descendant_counts();
+        assert_eq!(counts[collapsed], 0);
+        assert_eq!(counts[tree.root], 5);
+    }
+
+    #[test]
+    fn filtered_tree_round_trips_through_importtime_text() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        4 |          4 |   _io\n\
+import time:        1,200 |      1,500 |     a.b.c\n\
+import time:        6 |      1,506 |   a.b\n\
+import time:        2 |      1,512 | a\n\
+import time:       90 |         90 | marshal\n\
+import time:        7 |          7 | d\n";
+        // Builtins such as `_io` and `marshal` are filtered out by default.
+        let tree = build_tree(log).expect("tree");
+        let records = tree_records(&tree);
+        let text = crate::parser::format_import_time(&records);
+        assert!(!text.contains("marshal") && !text.contains("_io"));
+        assert!(text.contains("import time:      1200 |       1500 |     a.b.c\n"), "{text}");
+        let reparsed = parse_import_time(&text).expect("reparsed");
+        assert_eq!(format!("{reparsed:?}"), format!("{records:?}"));
+        assert_eq!(canonical_listing(&build_tree(&text).expect("tree")), canonical_listing(&tree));
+    }
+
+    #[test]
+    fn since_trims_log_to_first_import_of_module() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        4 |          4 |     a.x\n\
+import time:        6 |          6 |     a.y\n\
+import time:        1 |         11 |   a\n\
+import time:        2 |          2 |     c.z\n\
+import time:        3 |          5 |   c\n\
+import time:        1 |         17 | x\n\
+import time:        7 |          7 | y\n";
+        let records = parse_import_time(log).expect("records");
+        let trimmed = records_since(records.clone(), "a.y").expect("trimmed");
+        let kept: Vec<(&str, usize)> =
+            trimmed.iter().map(|record| (record.name.as_str(), record.depth)).collect();
+        assert_eq!(kept, [("a.y", 1), ("c.z", 2), ("c", 1), ("y", 1)]);
+
+        let options = TreeOptions { since: Some("a.y".to_string()), ..TreeOptions::default() };
+        let tree = build_tree_from_parsed(records.clone(), &options).expect("tree");
+        assert_eq!(tree.total_us(), 18);
+        let err = records_since(records, "missing").unwrap_err();
+        assert_eq!(err.to_string(), "module missing not found in the import time log");
+    }
+
+    #[test]
+    fn subtract_overhead_lowers_self_times_without_going_negative() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        1 |          1 |   a.x\n\
+import time:       10 |         10 |   a.y\n\
+import time:      100 |        111 | a\n";
+        let records = parse_import_time(log).expect("records");
+        let trimmed = subtract_overhead(records, 3);
+        let times: Vec<(&str, u64, u64)> = trimmed
+            .iter()
+            .map(|record| (record.name.as_str(), record.self_us, record.cumulative_us))
+            .collect();
+        assert_eq!(times, [("a.x", 0, 0), ("a.y", 7, 7), ("a", 97, 104)]);
+        let options = TreeOptions { subtract_overhead_us: Some(3), ..TreeOptions::default() };
+        let tree = build_tree_from_parsed(parse_import_time(log).expect("records"), &options)
+            .expect("tree");
+        assert_eq!(tree.total_us(), 104);
+    }
+
+    #[test]
+    fn residual_self_time_is_cumulative_minus_children() {
+        // The log's self column for `a` leaves out 50 us of overhead, and `b` claims more
+        // self time than its cumulative allows.
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      100 |        100 |   a.x\n\
+import time:      200 |        200 |   a.y\n\
+import time:       50 |        400 | a\n\
+import time:      300 |        250 |   b.x\n\
+import time:       10 |        200 | b\n";
+        let records = parse_import_time(log).expect("records");
+        let self_times = |records: &[ImportRecord]| -> Vec<u64> {
+            records.iter().map(|record| record.self_us).collect()
+        };
+        assert_eq!(self_times(&records), [100, 200, 50, 300, 10]);
+        let residual = residual_self_times(records.clone());
+        assert_eq!(self_times(&residual), [100, 200, 100, 250, 0]);
+        let options = TreeOptions {
+            self_definition: SelfDefinition::Residual,
+            ..TreeOptions::default()
+        };
+        let reported = build_tree_from_parsed(records.clone(), &TreeOptions::default())
+            .expect("tree");
+        let residual = build_tree_from_parsed(records, &options).expect("tree");
+        let a = |tree: &Tree| {
+            let index = tree.arena.iter().position(|node| node.name == "a").expect("a");
+            tree.self_us(index)
+        };
+        assert_eq!((a(&reported), a(&residual)), (50, 100));
+    }
+
+    #[test]
+    fn single_child_chain_collapses_into_one_node() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        5 |          5 |     ns.pkg.core\n\
+import time:        3 |          8 |   ns.pkg\n\
+import time:        2 |         10 | ns\n\
+import time:        4 |          4 |     json.decoder\n\
+import time:        1 |          5 |   json\n\
+import time:        1 |          1 |   re\n\
+import time:        2 |          8 | app\n";
+        let mut tree = build_tree(log).expect("tree");
+        assert_eq!(collapse_single_child_chains(&mut tree), 3);
+        let names: Vec<&str> = tree.arena[tree.root]
+            .children
+            .iter()
+            .map(|&index| tree.arena[index].name.as_str())
+            .collect();
+        assert_eq!(names, ["app", "ns.pkg.core"]);
+        let chain = tree.arena[tree.root].children[1];
+        assert_eq!(tree.totals[chain], 10);
+        assert_eq!(tree.self_us(chain), 10);
+        // `app` imports two modules so it stays; `json` and its one import merge.
+        let listing = canonical_listing(&tree);
+        assert!(listing.contains("app > json.decoder\t5\t5"), "{listing}");
+        assert_eq!(tree.total_us(), 18);
+        assert_eq!(chain_label("app", "json"), "app > json");
+    }
+
+    #[test]
+    fn module_paths_include_ancestors() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        2 |          2 |     b.c.d\n\
+import time:        3 |          5 |   b.c\n\
+import time:        5 |         10 | b\n\
+import time:       10 |         10 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let modules = module_paths(&tree);
+        let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "b.c", "b.c.d", "a"]);
+        assert_eq!(modules[2].path, vec!["b", "b.c", "b.c.d"]);
+        assert_eq!(modules[2].self_us, 2);
+        assert_eq!(modules[1].cumulative_us, 5);
+        assert_eq!(modules[3].path, vec!["a"]);
+    }
+
+    #[test]
+    fn subtract_baseline_removes_interpreter_startup_cost() {
+        let baseline = parse_import_time(
+            "\
+import time:       40 |         40 |   encodings.aliases\n\
+import time:       60 |        100 | encodings\n",
+        )
+        .expect("baseline");
+        let mut records = parse_import_time(
+            "\
+import time:       45 |         45 |   encodings.aliases\n\
+import time:       60 |        105 | encodings\n\
+import time:       30 |         30 | app\n",
+        )
+        .expect("records");
+        subtract_baseline(&mut records, &baseline);
+        let tree = build_tree_from_parsed(records, &TreeOptions::default()).expect("tree");
+        assert_eq!(tree.total_us(), 35);
+        let aliases = tree
+            .arena
+            .iter()
+            .position(|node| node.name == "encodings.aliases")
+            .expect("aliases");
+        assert_eq!(tree.totals[aliases], 5);
+    }
+
+    #[test]
+    fn builtin_modules_are_filtered_by_default() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:       90 |         90 | _frozen_importlib_external\n\
+import time:        4 |          4 | marshal\n\
+import time:       10 |         10 | json\n";
+        let tree = build_tree(log).expect("tree");
+        let names: Vec<&str> = tree.arena.iter().map(|node| node.name.as_str()).collect();
+        assert!(names.contains(&"json"));
+        assert!(!names.contains(&"_frozen_importlib_external"));
+        assert!(!names.contains(&"marshal"));
+
+        let options = TreeOptions {
+            include_builtin: true,
+            ..TreeOptions::default()
+        };
+        let records = parse_import_time(log).expect("records");
+        let tree = build_tree_from_parsed(records, &options).expect("tree");
+        assert_eq!(tree.total_us(), 104);
+    }
+
+    #[test]
+    fn builtin_modules_hand_their_imports_and_time_up() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:      500 |        500 |     heavy\n\
+import time:        5 |        505 |   _io\n\
+import time:        5 |        510 | b\n\
+import time:        3 |          3 | sys\n\
+import time:       10 |         10 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let paths: Vec<String> =
+            module_paths(&tree).into_iter().map(|m| m.path.join(" > ")).collect();
+        assert_eq!(paths, ["b", "b > heavy", "a"]);
+        let b = module_paths(&tree).into_iter().find(|m| m.name == "b").expect("b");
+        assert_eq!((b.cumulative_us, b.self_us), (510, 10));
+        assert_eq!(tree.total_us(), 523);
+        assert_eq!(tree.self_us(tree.root), 3);
+    }
+
+    #[test]
+    fn nested_logs_combine_under_per_file_roots() {
+        let api = parse_import_time(
+            "import time:        3 |          3 |   json.decoder\n\
+             import time:        5 |          8 | json\n",
+        )
+        .expect("api records");
+        let worker = parse_import_time("import time:       20 |         20 | numpy\n")
+            .expect("worker records");
+        let mut records = nest_records(api, "api.log");
+        records.extend(nest_records(worker, "worker.log"));
+
+        let tree = build_tree_from_parsed(records, &TreeOptions::default()).expect("tree");
+        let top: Vec<(String, u64)> = top_level_imports(&tree)
+            .into_iter()
+            .map(|module| (module.name, module.cumulative_us))
+            .collect();
+        assert_eq!(top, vec![("worker.log".to_string(), 20), ("api.log".to_string(), 8)]);
+        assert_eq!(tree.total_us(), 28);
+        let paths = module_paths(&tree);
+        let decoder = paths.iter().find(|m| m.name == "json.decoder").expect("decoder");
+        assert_eq!(decoder.path, vec!["api.log", "json", "json.decoder"]);
+    }
+
+    #[test]
+    fn per_file_roots_survive_dedupe_and_the_builtin_filter() {
+        let log = parse_import_time("import time:       20 |         20 | numpy\n").expect("log");
+        let mut records = nest_records(log.clone(), "sys");
+        records.extend(nest_records(log, "sys"));
+        let options = TreeOptions {
+            dedupe: true,
+            ..TreeOptions::default()
+        };
+        let tree = build_tree_from_parsed(records, &options).expect("tree");
+        let top: Vec<(String, u64)> = top_level_imports(&tree)
+            .into_iter()
+            .map(|module| (module.name, module.cumulative_us))
+            .collect();
+        assert_eq!(top, vec![("sys".to_string(), 20), ("sys".to_string(), 20)]);
+        let names: Vec<&str> = tree.arena.iter().map(|node| node.name.as_str()).collect();
+        assert!(names.contains(&"numpy (see first)"), "{names:?}");
+        assert!(tree_records(&tree).iter().any(|record| record.synthetic));
+    }
+
+    #[test]
+    fn hide_bootstrap_folds_machinery_into_root_self_time() {
+        let log = "\
+import time:      300 |        300 | _frozen_importlib_external\n\
+import time:       40 |         40 |     importlib._bootstrap_external\n\
+import time:       10 |         50 |   importlib._bootstrap\n\
+import time:       20 |         70 | app\n";
+        let options = TreeOptions {
+            hide_bootstrap: true,
+            include_builtin: true,
+            ..TreeOptions::default()
+        };
+        let records = parse_import_time(log).expect("records");
+        let tree = build_tree_from_parsed(records, &options).expect("tree");
+        assert_eq!(tree.total_us(), 370);
+        let names: Vec<String> = module_paths(&tree).into_iter().map(|m| m.name).collect();
+        assert_eq!(names, vec!["app"]);
+        let app = tree.arena.iter().position(|node| node.name == "app").expect("app");
+        assert_eq!(tree.arena[app].cumulative_us, 20);
+        assert_eq!(tree.self_us(tree.root), 350);
+    }
+
+    #[test]
+    fn group_by_directory_rolls_up_self_time() {
+        let log = "\
+import time:       30 |         30 |     app.db.models\n\
+import time:       20 |         50 |   app.db\n\
+import time:      400 |        400 |   json\n\
+import time:       10 |        460 | app\n\
+import time:        5 |          5 | setup_helpers\n";
+        let records = parse_import_time(log).expect("records");
+        let root = Path::new("/work/proj");
+        let files: HashMap<String, PathBuf> = [
+            ("app.db.models", "/work/proj/src/app/db/models.py"),
+            ("app.db", "/work/proj/src/app/db/__init__.py"),
+            ("app", "/work/proj/src/app/__init__.py"),
+            ("json", "/usr/lib/python3.12/json/__init__.py"),
+            ("setup_helpers", "/work/proj/setup_helpers.py"),
+        ]
+        .into_iter()
+        .map(|(name, file)| (name.to_string(), PathBuf::from(file)))
+        .collect();
+        let grouped = group_by_directory(&records, &files, root);
+        let tree = build_tree_from_parsed(grouped, &TreeOptions::default()).expect("tree");
+        assert_eq!(tree.total_us(), 465);
+        let costs: Vec<(String, u64, u64)> = module_paths(&tree)
+            .into_iter()
+            .map(|m| (m.path.join("/"), m.self_us, m.cumulative_us))
+            .collect();
+        assert!(costs.contains(&("proj".to_string(), 5, 65)));
+        assert!(costs.contains(&("proj/src/app".to_string(), 10, 60)));
+        assert!(costs.contains(&("proj/src/app/db".to_string(), 50, 50)));
+        assert!(costs.contains(&(OUTSIDE_PROJECT.to_string(), 400, 400)));
+    }
+
+    #[test]
+    fn root_option_extracts_subtree() {
+        let log = "\
+import time:        4 |          4 |     django.utils.functional\n\
+import time:        6 |         10 |   django.utils\n\
+import time:        2 |          2 |   django.conf\n\
+import time:        8 |         20 | django\n\
+import time:       50 |         50 | numpy\n";
+        let options = TreeOptions {
+            root: Some("django".to_string()),
+            ..TreeOptions::default()
+        };
+        let records = parse_import_time(log).expect("records");
+        let tree = build_tree_from_parsed(records, &options).expect("tree");
+        assert_eq!(tree.total_us(), 20);
+        let top: Vec<String> = top_level_imports(&tree).into_iter().map(|m| m.name).collect();
+        assert_eq!(top, vec!["django.utils", "django.conf"]);
+        let paths = module_paths(&tree);
+        assert!(paths.iter().all(|m| m.name != "numpy"));
+        let functional = paths.iter().find(|m| m.name == "django.utils.functional").expect("fn");
+        assert_eq!(functional.path, vec!["django.utils", "django.utils.functional"]);
+
+        let options = TreeOptions {
+            root: Some("flask".to_string()),
+            ..TreeOptions::default()
+        };
+        let records = parse_import_time(log).expect("records");
+        let err = build_tree_from_parsed(records, &options).expect_err("missing module");
+        assert!(err.to_string().contains("module flask not found"));
+    }
+
+    #[test]
+    fn nodes_keep_log_line_order() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   b.c\n\
+import time:        5 |          8 | b\n\
+import time:       10 |         10 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let order = |name: &str| {
+            tree.arena
+                .iter()
+                .find(|node| node.name == name)
+                .and_then(|node| node.order)
+        };
+        assert_eq!(order("b.c"), Some(1));
+        assert_eq!(order("b"), Some(2));
+        assert_eq!(order("a"), Some(3));
+        assert_eq!(tree.arena[tree.root].order, None);
+    }
+
+    #[test]
+    fn canonical_listing_ignores_sibling_order() {
+        let first = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        3 |          3 |   json.decoder\n\
+import time:        5 |          8 | json\n\
+import time:       10 |         10 | re\n";
+        let reordered = "\
+import time:   10 |   10 | re\n\
+import time:    3 |    3 |   json.decoder\n\
+import time:    5 |    8 | json\n";
+        let listing = canonical_listing(&build_tree(first).expect("tree"));
+        assert_eq!(listing, canonical_listing(&build_tree(reordered).expect("tree")));
+        assert_eq!(
+            listing,
+            "# path\tself_us\tcumulative_us\n\
+             json\t5\t8\n\
+             json > json.decoder\t3\t3\n\
+             re\t10\t10\n"
+        );
+    }
+
+    #[test]
+    fn descendant_counts_skip_self_nodes() {
+        let log = "\
+import time: self [us] | cumulative | imported package\n\
+import time:        1 |          1 |     a.b.c\n\
+import time:        1 |          2 |   a.b\n\
+import time:        1 |          1 |   a.d\n\
+import time:        1 |          4 | a\n";
+        let tree = build_tree(log).expect("tree");
+        let counts = tree.descendant_counts();
+        let count = |name: &str| {
+            let index = tree.arena.iter().position(|node| node.name == name).expect(name);
+            counts[index]
+        };
+        assert_eq!(count("a"), 3);
+        assert_eq!(count("a.b"), 1);
+        assert_eq!(count("a.d"), 0);
+        assert_eq!(counts[tree.root], 4);
+    }
 }
@@ -1,22 +1,207 @@
 use std::fs;
 use std::io::{self, Read, Write};
+#[cfg(feature = "render")]
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+#[cfg(feature = "render")]
+use std::thread;
+#[cfg(feature = "render")]
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{bail, Context, Result};
+use encoding_rs::{Encoding, UTF_8};
+#[cfg(feature = "render")]
+use flate2::{write::GzEncoder, Compression};
 
-pub fn read_input(input: &str) -> Result<String> {
+/// `read_input` name for the system clipboard.
+pub const CLIPBOARD_INPUT: &str = "@clipboard";
+
+/// Clipboard readers tried in order by `read_input`; the first that succeeds wins.
+const CLIPBOARD_COMMANDS: &[(&str, &[&str])] = &[
+    ("pbpaste", &[]),
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("xsel", &["--clipboard", "--output"]),
+    ("powershell", &["-NoProfile", "-Command", "Get-Clipboard"]),
+];
+
+/// Where `read_input` takes its text from.
+#[derive(Debug, PartialEq, Eq)]
+enum InputSource<'a> {
+    Stdin,
+    Clipboard,
+    ZipMember(&'a str, &'a str),
+    File(&'a str),
+}
+
+fn input_source(input: &str) -> InputSource<'_> {
     if input == "-" {
-        let mut buf = String::new();
-        io::stdin().read_to_string(&mut buf)?;
-        Ok(buf)
+        InputSource::Stdin
+    } else if input == CLIPBOARD_INPUT {
+        InputSource::Clipboard
+    } else if let Some((archive, member)) = zip_member(input) {
+        InputSource::ZipMember(archive, member)
     } else {
-        fs::read_to_string(input).with_context(|| format!("failed to read {}", input))
+        InputSource::File(input)
+    }
+}
+
+/// The file `input` names, or `None` for stdin, the clipboard and zip archive members, which
+/// can't be watched for a writer still appending to them.
+#[cfg(feature = "render")]
+pub fn plain_file_input(input: &str) -> Option<&Path> {
+    match input_source(input) {
+        InputSource::File(path) => Some(Path::new(path)),
+        InputSource::Stdin | InputSource::Clipboard | InputSource::ZipMember(..) => None,
+    }
+}
+
+/// Reads `-` from stdin, `@clipboard` from the system clipboard, `archive.zip:member` from
+/// inside a zip archive, and anything else as a file. An existing file whose name happens to
+/// contain `.zip:` is read as a file.
+pub fn read_input(input: &str) -> Result<String> {
+    read_input_as(input, UTF_8)
+}
+
+/// Like `read_input`, but decodes the bytes with `encoding`. A byte order mark overrides it,
+/// and undecodable bytes become U+FFFD instead of failing the read.
+pub fn read_input_as(input: &str, encoding: &'static Encoding) -> Result<String> {
+    let bytes = match input_source(input) {
+        InputSource::Stdin => {
+            let mut buf = Vec::new();
+            io::stdin().read_to_end(&mut buf)?;
+            buf
+        }
+        InputSource::Clipboard => read_clipboard(CLIPBOARD_COMMANDS)?,
+        InputSource::ZipMember(archive, member) => read_zip_member(archive, member)?,
+        InputSource::File(path) => {
+            fs::read(path).with_context(|| format!("failed to read {}", path))?
+        }
+    };
+    Ok(decode(&bytes, encoding))
+}
+
+fn decode(bytes: &[u8], encoding: &'static Encoding) -> String {
+    encoding.decode(bytes).0.into_owned()
+}
+
+/// Looks up an `--encoding` name such as `utf-8`, `latin1` or `windows-1252`.
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding> {
+    match Encoding::for_label(label.trim().as_bytes()) {
+        Some(encoding) => Ok(encoding),
+        None => bail!("unknown encoding {label:?}; try utf-8, utf-16le or windows-1252"),
     }
 }
 
+fn read_clipboard(commands: &[(&str, &[&str])]) -> Result<Vec<u8>> {
+    for (program, args) in commands {
+        // A missing tool or one without a display to talk to just means trying the next.
+        if let Ok(output) = Command::new(program).args(*args).output()
+            && output.status.success()
+        {
+            return Ok(output.stdout);
+        }
+    }
+    let programs: Vec<&str> = commands.iter().map(|(program, _)| *program).collect();
+    bail!(
+        "no clipboard available (tried {}); save the log to a file or pipe it to stdin instead",
+        programs.join(", ")
+    )
+}
+
+fn zip_member(input: &str) -> Option<(&str, &str)> {
+    if Path::new(input).exists() {
+        return None;
+    }
+    // ASCII lowercasing keeps byte offsets, so the index applies to `input` as well.
+    let split = input.to_ascii_lowercase().find(".zip:")? + ".zip".len();
+    Some((&input[..split], &input[split + 1..]))
+}
+
+fn read_zip_member(archive: &str, member: &str) -> Result<Vec<u8>> {
+    let file = fs::File::open(archive).with_context(|| format!("failed to read {}", archive))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .with_context(|| format!("{} is not a zip archive", archive))?;
+    let mut entry = zip
+        .by_name(member)
+        .with_context(|| format!("{} has no member {}", archive, member))?;
+    let mut bytes = Vec::new();
+    entry
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read {} from {}", member, archive))?;
+    Ok(bytes)
+}
+
+/// Refuses an `output` that resolves to the same file as one of `inputs` (or the archive an
+/// `archive.zip:member` input is read from), since writing it would destroy the log.
+pub fn ensure_output_is_not_input(inputs: &[String], output: Option<&Path>) -> Result<()> {
+    let Some(output) = output else {
+        return Ok(());
+    };
+    // A missing output cannot be an existing input.
+    let Ok(resolved) = fs::canonicalize(output) else {
+        return Ok(());
+    };
+    for input in inputs.iter().filter(|input| *input != "-") {
+        let path = zip_member(input).map_or(input.as_str(), |(archive, _)| archive);
+        if fs::canonicalize(path).is_ok_and(|path| path == resolved) {
+            bail!(
+                "--output {} is the input {}; pick another output or pass --overwrite-input",
+                output.display(),
+                input
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Blocks until `path` has not changed size or mtime for `quiet`, for logs that another
+/// process is still writing.
+#[cfg(feature = "render")]
+pub fn wait_for_stable_file(path: &Path, quiet: Duration) -> Result<()> {
+    let poll = (quiet / 4).clamp(Duration::from_millis(10), Duration::from_millis(250));
+    let snapshot = |path: &Path| -> Result<(u64, Option<SystemTime>)> {
+        let metadata =
+            fs::metadata(path).with_context(|| format!("failed to stat {}", path.display()))?;
+        Ok((metadata.len(), metadata.modified().ok()))
+    };
+    let mut last = snapshot(path)?;
+    let mut stable_since = Instant::now();
+    while stable_since.elapsed() < quiet {
+        thread::sleep(poll);
+        let current = snapshot(path)?;
+        if current != last {
+            last = current;
+            stable_since = Instant::now();
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the directory `output` goes in exists, creating it and its parents with
+/// `create`, so a missing directory gets a clear error instead of a bare OS one.
+pub fn ensure_output_dir(output: Option<&Path>, create: bool) -> Result<()> {
+    let Some(dir) = output.and_then(Path::parent).filter(|dir| !dir.as_os_str().is_empty())
+    else {
+        return Ok(());
+    };
+    if dir.is_dir() {
+        return Ok(());
+    }
+    if create {
+        return fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create directory {}", dir.display()));
+    }
+    bail!(
+        "the output directory {} does not exist; create it or pass --mkdir",
+        dir.display()
+    )
+}
+
 pub fn write_text_output(text: String, output: Option<PathBuf>) -> Result<()> {
     if let Some(path) = output {
+        ensure_output_dir(Some(&path), false)?;
         fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))?;
     } else {
         io::stdout().write_all(text.as_bytes())?;
@@ -24,31 +209,138 @@ pub fn write_text_output(text: String, output: Option<PathBuf>) -> Result<()> {
     Ok(())
 }
 
-pub fn write_html_or_open(html: String, output: Option<PathBuf>, open: bool) -> Result<()> {
-    let target = html_output_target(output)?;
-    write_html_to_target(&html, &target)?;
-    let path = target.path();
-    if open {
-        if let Err(err) = open_in_browser(path) {
-            eprintln!("warning: failed to open browser: {err}");
+/// Like `write_text_output`, but gzip-compressed. Refuses to write the compressed bytes to a
+/// terminal, where they would only garble it.
+#[cfg(feature = "render")]
+pub fn write_gzip_output(text: &str, output: Option<PathBuf>) -> Result<()> {
+    let Some(path) = output else {
+        if io::stdout().is_terminal() {
+            bail!(
+                "refusing to write gzip-compressed output to a terminal; pass --output or pipe it"
+            );
         }
+        let mut encoder = GzEncoder::new(io::stdout().lock(), Compression::default());
+        encoder.write_all(text.as_bytes())?;
+        encoder.finish()?.flush()?;
+        return Ok(());
+    };
+    write_html_to_target(&HtmlOutputTarget::Path(path), true, |out| {
+        Ok(out.write_all(text.as_bytes())?)
+    })
+}
+
+#[cfg(feature = "render")]
+/// Writes the report produced by `write` to `output`, or a temp file without one, and prints
+/// its path. The report is streamed to the file rather than built up in memory first. With
+/// `gzip` the file is compressed and never opened, since browsers don't open `.html.gz`.
+pub fn write_html_or_open(
+    output: Option<PathBuf>,
+    open: bool,
+    gzip: bool,
+    write: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let target = html_output_target(output, gzip)?;
+    write_html_to_target(&target, gzip, write)?;
+    let path = target.path();
+    if gzip {
+        eprintln!(
+            "note: {} is gzip-compressed for archiving or transfer; browsers won't open it \
+            until it is decompressed, e.g. with gunzip",
+            path.display()
+        );
+    } else if open {
+        open_in_browser_or_warn(path);
     }
     println!("{}", path.display());
     Ok(())
 }
 
-fn temp_html_path() -> Result<PathBuf> {
+/// FNV-1a over `parts`, hex encoded. Stable across builds, unlike `DefaultHasher`.
+#[cfg(feature = "render")]
+pub fn content_hash(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+#[cfg(feature = "render")]
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard, padded base64 (RFC 4648).
+#[cfg(feature = "render")]
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A `data:` URI carrying `bytes` base64 encoded, e.g. for pasting into markdown.
+#[cfg(feature = "render")]
+pub fn data_uri(mime: &str, bytes: &[u8]) -> String {
+    format!("data:{mime};base64,{}", base64_encode(bytes))
+}
+
+/// The sidecar file holding the hash `output` was rendered from.
+#[cfg(feature = "render")]
+pub fn hash_sidecar_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".hash");
+    PathBuf::from(name)
+}
+
+/// True if `output` exists and its sidecar records `hash`.
+#[cfg(feature = "render")]
+pub fn output_is_current(output: &Path, hash: &str) -> bool {
+    output.is_file()
+        && fs::read_to_string(hash_sidecar_path(output)).is_ok_and(|stored| stored.trim() == hash)
+}
+
+#[cfg(feature = "render")]
+pub fn write_output_hash(output: &Path, hash: &str) -> Result<()> {
+    let sidecar = hash_sidecar_path(output);
+    fs::write(&sidecar, format!("{hash}\n"))
+        .with_context(|| format!("failed to write {}", sidecar.display()))
+}
+
+#[cfg(feature = "render")]
+pub fn open_in_browser_or_warn(path: &Path) {
+    if let Err(err) = open_in_browser(path) {
+        eprintln!("warning: failed to open browser: {err}");
+    }
+}
+
+#[cfg(feature = "render")]
+fn temp_html_path(gzip: bool) -> Result<PathBuf> {
     let mut path = std::env::temp_dir();
-    let file_name = format!("pyimporttime-{}.html", std::process::id());
+    let extension = if gzip { "html.gz" } else { "html" };
+    let file_name = format!("pyimporttime-{}.{}", std::process::id(), extension);
     path.push(file_name);
     Ok(path)
 }
 
+#[cfg(feature = "render")]
 enum HtmlOutputTarget {
     Path(PathBuf),
     Temp(PathBuf),
 }
 
+#[cfg(feature = "render")]
 impl HtmlOutputTarget {
     fn path(&self) -> &Path {
         match self {
@@ -57,19 +349,36 @@ impl HtmlOutputTarget {
     }
 }
 
-fn html_output_target(output: Option<PathBuf>) -> Result<HtmlOutputTarget> {
+#[cfg(feature = "render")]
+fn html_output_target(output: Option<PathBuf>, gzip: bool) -> Result<HtmlOutputTarget> {
     if let Some(path) = output {
         return Ok(HtmlOutputTarget::Path(path));
     }
-    Ok(HtmlOutputTarget::Temp(temp_html_path()?))
+    Ok(HtmlOutputTarget::Temp(temp_html_path(gzip)?))
 }
 
-fn write_html_to_target(html: &str, target: &HtmlOutputTarget) -> Result<()> {
+#[cfg(feature = "render")]
+fn write_html_to_target(
+    target: &HtmlOutputTarget,
+    gzip: bool,
+    write: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
     let path = target.path();
-    fs::write(path, html).with_context(|| format!("failed to write {}", path.display()))?;
+    ensure_output_dir(Some(path), false)?;
+    let failed = || format!("failed to write {}", path.display());
+    let mut file = io::BufWriter::new(fs::File::create(path).with_context(failed)?);
+    if gzip {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        write(&mut encoder).with_context(failed)?;
+        file = encoder.finish().with_context(failed)?;
+    } else {
+        write(&mut file).with_context(failed)?;
+    }
+    file.flush().with_context(failed)?;
     Ok(())
 }
 
+#[cfg(feature = "render")]
 fn open_in_browser(path: &Path) -> Result<()> {
     let status = Command::new("xdg-open")
         .arg(path)
@@ -81,21 +390,207 @@ fn open_in_browser(path: &Path) -> Result<()> {
     Ok(())
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "render"))]
 mod tests {
     use super::*;
 
     #[test]
     fn write_html_to_temp_creates_file() {
         let html = "<html><body>ok</body></html>";
-        let target = html_output_target(None).unwrap();
+        let target = html_output_target(None, false).unwrap();
         let path = target.path().to_path_buf();
 
-        write_html_to_target(html, &target).unwrap();
+        write_html_to_target(&target, false, |out| Ok(out.write_all(html.as_bytes())?)).unwrap();
 
         let contents = fs::read_to_string(&path).unwrap();
         assert_eq!(contents, html);
 
         fs::remove_file(&path).unwrap();
     }
+
+    #[test]
+    fn gzip_output_decompresses_to_the_html() {
+        let html = "<html><body>ok</body></html>".repeat(100);
+        let target = html_output_target(None, true).unwrap();
+        let path = target.path().to_path_buf();
+        assert!(path.to_string_lossy().ends_with(".html.gz"));
+
+        write_html_to_target(&target, true, |out| Ok(out.write_all(html.as_bytes())?)).unwrap();
+
+        let compressed = fs::read(&path).unwrap();
+        assert!(compressed.len() < html.len());
+        let mut contents = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice()).read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, html);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_output_directory_is_named_or_created() {
+        let dir = std::env::temp_dir().join(format!("pyimporttime-mkdir-{}", std::process::id()));
+        let output = dir.join("nonexistent/sub/out.html");
+        let err = write_text_output("<html></html>".to_string(), Some(output.clone()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("nonexistent/sub"), "{err}");
+        assert!(err.contains("--mkdir"), "{err}");
+        ensure_output_dir(Some(&output), true).unwrap();
+        write_text_output("<html></html>".to_string(), Some(output.clone())).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "<html></html>");
+        ensure_output_dir(Some(Path::new("out.html")), false).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_plain_files_are_waited_for() {
+        assert_eq!(plain_file_input("run.log"), Some(Path::new("run.log")));
+        assert_eq!(plain_file_input("-"), None);
+        assert_eq!(plain_file_input(CLIPBOARD_INPUT), None);
+        assert_eq!(plain_file_input("missing-logs.zip:run.log"), None);
+    }
+
+    #[test]
+    fn wait_for_stable_file_waits_for_writer() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("pyimporttime-wait-{}.txt", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || {
+            for _ in 0..5 {
+                thread::sleep(Duration::from_millis(20));
+                let mut file = fs::OpenOptions::new().append(true).open(&writer_path).unwrap();
+                file.write_all(b"line\n").unwrap();
+            }
+        });
+
+        wait_for_stable_file(&path, Duration::from_millis(150)).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 5);
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn base64_decode(text: &str) -> Vec<u8> {
+        let mut bits = 0u32;
+        let mut count = 0;
+        let mut out = Vec::new();
+        for ch in text.bytes().take_while(|ch| *ch != b'=') {
+            let value = BASE64_ALPHABET.iter().position(|c| *c == ch).expect("base64") as u32;
+            bits = (bits << 6) | value;
+            count += 6;
+            if count >= 8 {
+                count -= 8;
+                out.push((bits >> count) as u8);
+                bits &= (1 << count) - 1;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn data_uri_round_trips() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        let html = "<html><body>µs \u{1f40d} & more</body></html>".repeat(7);
+        let uri = data_uri("text/html", html.as_bytes());
+        let payload = uri.strip_prefix("data:text/html;base64,").expect("prefix");
+        assert_eq!(base64_decode(payload), html.as_bytes());
+    }
+
+    #[test]
+    fn read_input_extracts_zip_member() {
+        let dir = std::env::temp_dir().join(format!("pyimporttime-zip-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("artifacts.zip");
+        let log = "import time:       10 |         10 | json\n";
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("logs/startup.txt", options).unwrap();
+        writer.write_all(log.as_bytes()).unwrap();
+        writer.start_file("logs/other.txt", options).unwrap();
+        writer.write_all(b"other").unwrap();
+        writer.finish().unwrap();
+
+        let input = format!("{}:logs/startup.txt", archive.display());
+        assert_eq!(read_input(&input).unwrap(), log);
+        let missing = format!("{}:logs/missing.txt", archive.display());
+        let err = read_input(&missing).unwrap_err();
+        assert!(format!("{err:#}").contains("has no member logs/missing.txt"));
+
+        let plain = dir.join("plain.txt");
+        fs::write(&plain, log).unwrap();
+        assert_eq!(read_input(plain.to_str().unwrap()).unwrap(), log);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn content_hash_separates_parts() {
+        assert_eq!(content_hash(&["ab", "c"]), content_hash(&["ab", "c"]));
+        assert_ne!(content_hash(&["ab", "c"]), content_hash(&["a", "bc"]));
+        assert_eq!(
+            hash_sidecar_path(Path::new("/tmp/report.html")),
+            PathBuf::from("/tmp/report.html.hash")
+        );
+    }
+
+    #[test]
+    fn output_matching_input_is_refused() {
+        let dir = std::env::temp_dir().join(format!("pyimporttime-same-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("log.json");
+        fs::write(&log, "import time:       10 |         10 | json\n").unwrap();
+        let inputs = vec![log.display().to_string()];
+
+        let same = dir.join(".").join("log.json");
+        let err = ensure_output_is_not_input(&inputs, Some(&same)).unwrap_err();
+        assert!(err.to_string().contains("--overwrite-input"), "{err}");
+        let zipped = vec![format!("{}:startup.txt", dir.join("logs.zip").display())];
+        fs::write(dir.join("logs.zip"), "").unwrap();
+        assert!(ensure_output_is_not_input(&zipped, Some(&dir.join("logs.zip"))).is_err());
+
+        assert!(ensure_output_is_not_input(&inputs, Some(&dir.join("report.html"))).is_ok());
+        assert!(ensure_output_is_not_input(&["-".to_string()], Some(&log)).is_ok());
+        assert!(ensure_output_is_not_input(&inputs, None).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clipboard_input_is_dispatched_to_clipboard_commands() {
+        assert_eq!(input_source("@clipboard"), InputSource::Clipboard);
+        assert_eq!(input_source("-"), InputSource::Stdin);
+        assert_eq!(input_source("log.txt"), InputSource::File("log.txt"));
+
+        let log = "import time:       10 |         10 | json\n";
+        let script = format!("printf '{}'", log.replace('\n', "\\n"));
+        let commands: &[(&str, &[&str])] = &[
+            ("pyimporttime-missing-clipboard", &[]),
+            ("sh", &["-c", "exit 1"]),
+            ("sh", &["-c", script.as_str()]),
+        ];
+        assert_eq!(read_clipboard(commands).unwrap(), log.as_bytes());
+        let err = read_clipboard(&commands[..2]).unwrap_err().to_string();
+        assert!(err.contains("no clipboard available"), "{err}");
+        assert!(err.contains("pyimporttime-missing-clipboard"), "{err}");
+    }
+
+    #[test]
+    fn non_utf8_input_is_decoded_with_the_given_encoding() {
+        let dir = std::env::temp_dir().join(format!("pyimporttime-enc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("latin1.txt");
+        // "café" in windows-1252: 0xE9 on its own is not valid UTF-8.
+        fs::write(&log, b"import time:       10 |         10 | caf\xe9\n").unwrap();
+        let path = log.to_str().unwrap();
+
+        let encoding = parse_encoding("latin1").unwrap();
+        let text = read_input_as(path, encoding).unwrap();
+        assert_eq!(text, "import time:       10 |         10 | caf\u{e9}\n");
+        // Without --encoding the bad byte is replaced rather than failing the read.
+        assert!(read_input(path).unwrap().ends_with("caf\u{fffd}\n"));
+        assert!(parse_encoding("no-such-encoding").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }